@@ -0,0 +1,127 @@
+//! Bubble level and inclinometer, on the filtered [`Orientation`] of the motion service.
+
+use crate::motion::Orientation;
+
+/// The bubble reaches the edge of the vial at this angle.
+pub const BUBBLE_RANGE_DEGREES: f32 = 10.0;
+/// Considered level within this angle.
+pub const LEVEL_TOLERANCE_DEGREES: f32 = 0.5;
+/// A reference surface tilted more than this is most likely a mistake.
+pub const MAX_ZERO_DEGREES: f32 = 15.0;
+
+#[derive(Debug, Default)]
+pub struct Inclinometer {
+    zero: Orientation,
+    /// The reading frozen by the user.
+    held: Option<Orientation>,
+}
+
+impl Inclinometer {
+    pub fn new(zero: Orientation) -> Self {
+        Self { zero, held: None }
+    }
+
+    pub fn zero(&self) -> Orientation {
+        self.zero
+    }
+
+    pub fn is_zeroed(&self) -> bool {
+        self.zero != Orientation::default()
+    }
+
+    /// Take `current` as level, returns false if it's too far off.
+    pub fn set_zero(&mut self, current: Orientation) -> bool {
+        if current.pitch.abs() > MAX_ZERO_DEGREES || current.roll.abs() > MAX_ZERO_DEGREES {
+            return false;
+        }
+        self.zero = current;
+        self.held = None;
+        true
+    }
+
+    pub fn clear_zero(&mut self) {
+        self.zero = Orientation::default();
+        self.held = None;
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.held.is_some()
+    }
+
+    /// Freeze the reading at `current`, or let it go.
+    pub fn toggle_hold(&mut self, current: Orientation) {
+        self.held = match self.held {
+            Some(_) => None,
+            None => Some(self.relative(current)),
+        };
+    }
+
+    /// What to show for `current`, relative to the zero point, unless held.
+    pub fn reading(&self, current: Orientation) -> Orientation {
+        self.held.unwrap_or_else(|| self.relative(current))
+    }
+
+    fn relative(&self, current: Orientation) -> Orientation {
+        Orientation {
+            pitch: current.pitch - self.zero.pitch,
+            roll: current.roll - self.zero.roll,
+        }
+    }
+}
+
+/// Position of the bubble in the vial, (x, y) within -1..1, positive to the right and down.
+///
+/// The bubble floats to the raised side, and stays in the circle: a positive roll lowers the
+/// right edge and sends it left, a positive pitch raises the top edge and sends it up.
+pub fn bubble_position(reading: Orientation) -> (f32, f32) {
+    let x = -reading.roll / BUBBLE_RANGE_DEGREES;
+    let y = -reading.pitch / BUBBLE_RANGE_DEGREES;
+    let distance = (x * x + y * y).sqrt();
+    if distance > 1.0 {
+        (x / distance, y / distance)
+    } else {
+        (x, y)
+    }
+}
+
+pub fn is_level(reading: Orientation) -> bool {
+    reading.pitch.abs() <= LEVEL_TOLERANCE_DEGREES && reading.roll.abs() <= LEVEL_TOLERANCE_DEGREES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tilted(accel: [f32; 3]) -> (f32, f32) {
+        bubble_position(Orientation::from_accel(&accel))
+    }
+
+    #[test]
+    fn flat_is_centered() {
+        let (x, y) = tilted([0.0, 0.0, 1.0]);
+        assert!(x.abs() < 0.001 && y.abs() < 0.001);
+    }
+
+    #[test]
+    fn right_edge_lowered_moves_left() {
+        let radians = 5f32.to_radians();
+        let (x, y) = tilted([-radians.sin(), 0.0, radians.cos()]);
+        assert!(x < 0.0);
+        assert!(y.abs() < 0.001);
+    }
+
+    #[test]
+    fn top_edge_raised_moves_up() {
+        let radians = 5f32.to_radians();
+        let (x, y) = tilted([0.0, radians.sin(), radians.cos()]);
+        assert!(x.abs() < 0.001);
+        assert!(y < 0.0);
+    }
+
+    #[test]
+    fn stays_in_the_vial() {
+        let (x, y) = tilted([-0.7, 0.7, 0.1]);
+        assert!(x < 0.0 && y < 0.0);
+        assert!((x * x + y * y).sqrt() <= 1.0 + f32::EPSILON);
+    }
+}
@@ -0,0 +1,14 @@
+//! The parts of the gadget that don't touch the hardware, so they're built and tested on the
+//! host.
+//!
+//! The repository builds for the ESP32 by default, so the tests need a regular toolchain and the
+//! host target:
+//!
+//! ```sh
+//! cd gadget-core
+//! cargo +stable test --target x86_64-unknown-linux-gnu
+//! ```
+
+pub mod level;
+pub mod motion;
+pub mod settings;
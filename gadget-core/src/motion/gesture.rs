@@ -0,0 +1,311 @@
+//! Shake, double-tap and flip detection.
+//!
+//! The detectors only look at the samples and the time between them, so they can be fed with
+//! recorded data, see the tests.
+
+use super::MotionSample;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    Shake,
+    DoubleTap,
+    /// Put down with the screen facing the table.
+    FlipDown,
+    /// Turned back, screen up.
+    FlipUp,
+}
+
+/// Thresholds in 0.1g, so they fit the UI and NVS nicely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureConfig {
+    /// Acceleration magnitude of a shake peak.
+    pub shake: u8,
+    /// Sudden change of Z acceleration of a tap.
+    pub tap: u8,
+    /// Z acceleration to be considered flat, face up or down.
+    pub flip: u8,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            shake: 20,
+            tap: 6,
+            flip: 8,
+        }
+    }
+}
+
+/// Tunable range of each threshold.
+pub const SHAKE_RANGE: (u8, u8) = (12, 40);
+pub const TAP_RANGE: (u8, u8) = (2, 20);
+pub const FLIP_RANGE: (u8, u8) = (5, 10);
+
+impl GestureConfig {
+    pub fn sanitized(self) -> Self {
+        Self {
+            shake: self.shake.clamp(SHAKE_RANGE.0, SHAKE_RANGE.1),
+            tap: self.tap.clamp(TAP_RANGE.0, TAP_RANGE.1),
+            flip: self.flip.clamp(FLIP_RANGE.0, FLIP_RANGE.1),
+        }
+    }
+}
+
+/// Shake: a few strong peaks in a short time.
+const SHAKE_PEAKS: usize = 3;
+const SHAKE_WINDOW_MS: u32 = 1000;
+/// Peaks closer than this belong to the same swing.
+const SHAKE_PEAK_GAP_MS: u32 = 100;
+/// Double-tap: the second tap must come within this, but not too early.
+const TAP_MIN_GAP_MS: u32 = 100;
+const TAP_MAX_GAP_MS: u32 = 500;
+/// Taps are ignored while the device is being turned.
+const TAP_MAX_ROTATION: f32 = 60.0;
+/// Flip: the position must be held for a while.
+const FLIP_HOLD_MS: u32 = 500;
+/// Quiet time after a gesture, so one motion isn't reported twice.
+const COOLDOWN_MS: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Face {
+    Up,
+    Down,
+    Other,
+}
+
+#[derive(Debug)]
+pub struct GestureDetector {
+    config: GestureConfig,
+    /// Time of the current sample.
+    now_ms: u32,
+    /// End of the quiet time after the last gesture, it's wrapping like `now_ms`.
+    cooldown_until_ms: Option<u32>,
+    shake_peaks: Vec<u32>,
+    previous_z: Option<f32>,
+    last_tap_ms: Option<u32>,
+    face: Face,
+    /// Where it's heading to, and since when.
+    face_candidate: Option<(Face, u32)>,
+}
+
+impl GestureDetector {
+    pub fn new(config: GestureConfig) -> Self {
+        Self {
+            config: config.sanitized(),
+            now_ms: 0,
+            cooldown_until_ms: None,
+            shake_peaks: Vec::with_capacity(SHAKE_PEAKS),
+            previous_z: None,
+            last_tap_ms: None,
+            face: Face::Up,
+            face_candidate: None,
+        }
+    }
+
+    pub fn set_config(&mut self, config: GestureConfig) {
+        self.config = config.sanitized();
+    }
+
+    /// Feed one sample, `dt_ms` after the previous one.
+    pub fn update(&mut self, sample: &MotionSample, dt_ms: u32) -> Option<Gesture> {
+        self.now_ms = self.now_ms.wrapping_add(dt_ms);
+        // the flip detector keeps tracking the position, even in cooldown
+        let shake = self.detect_shake(sample);
+        let tap = self.detect_double_tap(sample);
+        let flip = self.detect_flip(sample);
+        let gesture = if self.in_cooldown() {
+            None
+        } else {
+            shake.or(flip).or(tap)
+        };
+        if gesture.is_some() {
+            self.cooldown_until_ms = Some(self.now_ms.wrapping_add(COOLDOWN_MS));
+        }
+        if self.in_cooldown() {
+            // the rest of the same motion doesn't count
+            self.shake_peaks.clear();
+            self.last_tap_ms = None;
+        }
+        gesture
+    }
+
+    /// Ends the cooldown once it's over, so it doesn't come back when the time wraps around.
+    fn in_cooldown(&mut self) -> bool {
+        let now = self.now_ms;
+        let active = self
+            .cooldown_until_ms
+            .is_some_and(|until| (until.wrapping_sub(now) as i32) > 0);
+        if !active {
+            self.cooldown_until_ms = None;
+        }
+        active
+    }
+
+    fn detect_shake(&mut self, sample: &MotionSample) -> Option<Gesture> {
+        let now = self.now_ms;
+        self.shake_peaks
+            .retain(|time| now.wrapping_sub(*time) <= SHAKE_WINDOW_MS);
+        if sample.accel_magnitude() * 10.0 >= self.config.shake as f32
+            && self
+                .shake_peaks
+                .last()
+                .map_or(true, |last| now.wrapping_sub(*last) >= SHAKE_PEAK_GAP_MS)
+        {
+            self.shake_peaks.push(now);
+        }
+        (self.shake_peaks.len() >= SHAKE_PEAKS).then_some(Gesture::Shake)
+    }
+
+    fn detect_double_tap(&mut self, sample: &MotionSample) -> Option<Gesture> {
+        let z = sample.accel[2];
+        let jerk = self.previous_z.map(|previous| (z - previous).abs());
+        self.previous_z = Some(z);
+        let rotation = sample
+            .gyro
+            .iter()
+            .map(|value| value.abs())
+            .fold(0.0, f32::max);
+        let tapped = jerk.is_some_and(|jerk| jerk * 10.0 >= self.config.tap as f32)
+            && rotation < TAP_MAX_ROTATION;
+        if !tapped {
+            return None;
+        }
+        let now = self.now_ms;
+        match self.last_tap_ms.map(|last| now.wrapping_sub(last)) {
+            // ringing of the same tap
+            Some(gap) if gap < TAP_MIN_GAP_MS => None,
+            Some(gap) if gap <= TAP_MAX_GAP_MS => {
+                self.last_tap_ms = None;
+                Some(Gesture::DoubleTap)
+            }
+            _ => {
+                self.last_tap_ms = Some(now);
+                None
+            }
+        }
+    }
+
+    fn detect_flip(&mut self, sample: &MotionSample) -> Option<Gesture> {
+        let threshold = self.config.flip as f32 / 10.0;
+        let z = sample.accel[2];
+        let face = if z >= threshold {
+            Face::Up
+        } else if z <= -threshold {
+            Face::Down
+        } else {
+            Face::Other
+        };
+        if face == Face::Other || face == self.face {
+            self.face_candidate = None;
+            return None;
+        }
+        match self.face_candidate {
+            Some((candidate, since)) if candidate == face => {
+                if self.now_ms.wrapping_sub(since) < FLIP_HOLD_MS {
+                    return None;
+                }
+                self.face = face;
+                self.face_candidate = None;
+                Some(if face == Face::Down {
+                    Gesture::FlipDown
+                } else {
+                    Gesture::FlipUp
+                })
+            }
+            _ => {
+                self.face_candidate = Some((face, self.now_ms));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::trace::Trace;
+
+    fn replay(detector: &mut GestureDetector, trace: &str) -> Vec<Gesture> {
+        let trace = Trace::parse(trace);
+        trace
+            .samples
+            .iter()
+            .filter_map(|sample| detector.update(sample, trace.dt_ms))
+            .collect()
+    }
+
+    fn detect(trace: &str) -> Vec<Gesture> {
+        replay(&mut GestureDetector::new(GestureConfig::default()), trace)
+    }
+
+    #[test]
+    fn flip() {
+        assert_eq!(
+            detect(include_str!("traces/flip.csv")),
+            [Gesture::FlipDown, Gesture::FlipUp]
+        );
+    }
+
+    #[test]
+    fn shake() {
+        assert_eq!(detect(include_str!("traces/shake.csv")), [Gesture::Shake]);
+    }
+
+    #[test]
+    fn double_tap() {
+        assert_eq!(
+            detect(include_str!("traces/double_tap.csv")),
+            [Gesture::DoubleTap]
+        );
+    }
+
+    #[test]
+    fn idle() {
+        assert_eq!(detect(include_str!("traces/idle.csv")), []);
+    }
+
+    #[test]
+    fn single_tap_is_not_a_double_tap() {
+        let trace = Trace::parse(include_str!("traces/double_tap.csv"));
+        let mut detector = GestureDetector::new(GestureConfig::default());
+        // only up to the ringing of the first tap
+        let first = trace
+            .samples
+            .iter()
+            .position(|sample| sample.accel[2] > 1.5)
+            .unwrap();
+        let gestures: Vec<Gesture> = trace.samples[..first + 5]
+            .iter()
+            .filter_map(|sample| detector.update(sample, trace.dt_ms))
+            .collect();
+        assert_eq!(gestures, []);
+    }
+
+    #[test]
+    fn strict_thresholds() {
+        let mut detector = GestureDetector::new(GestureConfig {
+            shake: SHAKE_RANGE.1,
+            tap: TAP_RANGE.1,
+            flip: FLIP_RANGE.1,
+        });
+        assert_eq!(replay(&mut detector, include_str!("traces/shake.csv")), []);
+        assert_eq!(
+            replay(&mut detector, include_str!("traces/double_tap.csv")),
+            []
+        );
+    }
+
+    #[test]
+    fn cooldown_across_wraparound() {
+        let mut detector = GestureDetector::new(GestureConfig::default());
+        detector.now_ms = u32::MAX - 1800;
+        assert_eq!(
+            replay(&mut detector, include_str!("traces/double_tap.csv")),
+            [Gesture::DoubleTap]
+        );
+        assert_eq!(
+            replay(&mut detector, include_str!("traces/flip.csv")),
+            [Gesture::FlipDown, Gesture::FlipUp]
+        );
+    }
+}
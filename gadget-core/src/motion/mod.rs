@@ -0,0 +1,231 @@
+//! Motion samples and what's worked out of them.
+//!
+//! Axes as mounted on the board, X points to the right edge, Y to the top edge and Z out of the
+//! screen.
+
+pub mod gesture;
+pub mod step;
+#[cfg(test)]
+mod trace;
+
+/// Rate the detectors are tuned at, and the traces are made at.
+pub const DETECTOR_RATE: u16 = 50;
+
+/// One reading, bias already removed.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MotionSample {
+    /// In g.
+    pub accel: [f32; 3],
+    /// In °/s.
+    pub gyro: [f32; 3],
+}
+
+impl MotionSample {
+    pub fn accel_magnitude(&self) -> f32 {
+        self.accel
+            .iter()
+            .map(|value| value * value)
+            .sum::<f32>()
+            .sqrt()
+    }
+}
+
+/// Orientation in degrees.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Orientation {
+    /// Positive when the top edge is raised.
+    pub pitch: f32,
+    /// Positive when the right edge is lowered.
+    pub roll: f32,
+}
+
+impl Orientation {
+    /// Orientation from gravity only, noisy when moving.
+    pub fn from_accel(accel: &[f32; 3]) -> Self {
+        // Y axis points to the top edge, X axis to the right edge, gravity reads as +1g upwards
+        let [x, y, z] = *accel;
+        Self {
+            pitch: y.atan2((x * x + z * z).sqrt()).to_degrees(),
+            roll: (-x).atan2(z).to_degrees(),
+        }
+    }
+}
+
+/// Complementary filter, the gyro for short term and gravity for long term.
+#[derive(Debug, Default)]
+pub struct OrientationFilter {
+    orientation: Option<Orientation>,
+}
+
+impl OrientationFilter {
+    /// Weight of the gyro integration.
+    const ALPHA: f32 = 0.98;
+
+    pub fn update(&mut self, sample: &MotionSample, dt: f32) -> Orientation {
+        let measured = Orientation::from_accel(&sample.accel);
+        let orientation = match self.orientation {
+            Some(previous) => Orientation {
+                // turning about +X raises the top edge, about +Y lowers the right edge
+                pitch: Self::ALPHA * (previous.pitch + sample.gyro[0] * dt)
+                    + (1.0 - Self::ALPHA) * measured.pitch,
+                roll: Self::ALPHA * (previous.roll + sample.gyro[1] * dt)
+                    + (1.0 - Self::ALPHA) * measured.roll,
+            },
+            None => measured,
+        };
+        self.orientation = Some(orientation);
+        orientation
+    }
+}
+
+/// Averages the samples down to [`DETECTOR_RATE`], so the detectors see the same rate whatever
+/// the sensor runs at. Rates above it should be multiples of it.
+#[derive(Debug, Default)]
+pub struct Decimator {
+    sum: MotionSample,
+    count: u16,
+}
+
+impl Decimator {
+    /// Feed one sample of the sensor running at `rate`, returns the average and the time it spans
+    /// once enough are in.
+    pub fn update(&mut self, sample: &MotionSample, rate: u16) -> Option<(MotionSample, u32)> {
+        for axis in 0..3 {
+            self.sum.accel[axis] += sample.accel[axis];
+            self.sum.gyro[axis] += sample.gyro[axis];
+        }
+        self.count += 1;
+        if self.count < (rate / DETECTOR_RATE).max(1) {
+            return None;
+        }
+        let count = self.count as f32;
+        let average = MotionSample {
+            accel: self.sum.accel.map(|value| value / count),
+            gyro: self.sum.gyro.map(|value| value / count),
+        };
+        let dt_ms = self.count as u32 * 1000 / rate.max(1) as u32;
+        *self = Self::default();
+        Some((average, dt_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gravity reading with the device tilted by `degrees` about one axis.
+    fn tilted(degrees: f32) -> (f32, f32) {
+        let radians = degrees.to_radians();
+        (radians.sin(), radians.cos())
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn flat_is_zero() {
+        let orientation = Orientation::from_accel(&[0.0, 0.0, 1.0]);
+        assert_near(orientation.pitch, 0.0);
+        assert_near(orientation.roll, 0.0);
+    }
+
+    #[test]
+    fn top_edge_raised_is_positive_pitch() {
+        let (sin, cos) = tilted(30.0);
+        let orientation = Orientation::from_accel(&[0.0, sin, cos]);
+        assert_near(orientation.pitch, 30.0);
+        assert_near(orientation.roll, 0.0);
+    }
+
+    #[test]
+    fn right_edge_lowered_is_positive_roll() {
+        let (sin, cos) = tilted(20.0);
+        let orientation = Orientation::from_accel(&[-sin, 0.0, cos]);
+        assert_near(orientation.pitch, 0.0);
+        assert_near(orientation.roll, 20.0);
+    }
+
+    #[test]
+    fn upright_is_right_angle_pitch() {
+        let orientation = Orientation::from_accel(&[0.0, 1.0, 0.0]);
+        assert_near(orientation.pitch, 90.0);
+    }
+
+    #[test]
+    fn gyro_turns_the_same_way_as_gravity() {
+        let mut filter = OrientationFilter::default();
+        let flat = MotionSample {
+            accel: [0.0, 0.0, 1.0],
+            gyro: [0.0; 3],
+        };
+        filter.update(&flat, 0.01);
+        // raising the top edge and lowering the right edge, before gravity catches up
+        let turning = MotionSample {
+            accel: [0.0, 0.0, 1.0],
+            gyro: [100.0, 100.0, 0.0],
+        };
+        let orientation = filter.update(&turning, 0.01);
+        assert!(orientation.pitch > 0.0);
+        assert!(orientation.roll > 0.0);
+    }
+
+    /// Every sample of `trace` repeated as if the sensor ran at `rate`, through a decimator.
+    fn resampled(trace: &trace::Trace, rate: u16) -> Vec<(MotionSample, u32)> {
+        let repeat = rate as u32 * trace.dt_ms / 1000;
+        let mut decimator = Decimator::default();
+        trace
+            .samples
+            .iter()
+            .flat_map(|sample| std::iter::repeat(sample).take(repeat as usize))
+            .filter_map(|sample| decimator.update(sample, rate))
+            .collect()
+    }
+
+    #[test]
+    fn decimated_to_the_detector_rate() {
+        let trace = trace::Trace::parse(include_str!("traces/double_tap.csv"));
+        for rate in [50, 100, 200, 500, 1000] {
+            let decimated = resampled(&trace, rate);
+            assert_eq!(decimated.len(), trace.samples.len(), "at {} Hz", rate);
+            for ((sample, dt_ms), original) in decimated.iter().zip(&trace.samples) {
+                assert_eq!(*dt_ms, 1000 / DETECTOR_RATE as u32);
+                for axis in 0..3 {
+                    assert!((sample.accel[axis] - original.accel[axis]).abs() < 1e-4);
+                    assert!((sample.gyro[axis] - original.gyro[axis]).abs() < 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decimation_averages() {
+        let mut decimator = Decimator::default();
+        let sample = |z: f32| MotionSample {
+            accel: [0.0, 0.0, z],
+            gyro: [z; 3],
+        };
+        assert_eq!(decimator.update(&sample(1.0), 100), None);
+        let (average, dt_ms) = decimator.update(&sample(2.0), 100).unwrap();
+        assert_eq!(average, sample(1.5));
+        assert_eq!(dt_ms, 20);
+    }
+
+    #[test]
+    fn gestures_at_the_runtime_rates() {
+        let trace = trace::Trace::parse(include_str!("traces/double_tap.csv"));
+        for rate in [100, 1000] {
+            let mut detector = gesture::GestureDetector::new(gesture::GestureConfig::default());
+            let gestures: Vec<gesture::Gesture> = resampled(&trace, rate)
+                .iter()
+                .filter_map(|(sample, dt_ms)| detector.update(sample, *dt_ms))
+                .collect();
+            assert_eq!(gestures, [gesture::Gesture::DoubleTap], "at {} Hz", rate);
+        }
+    }
+}
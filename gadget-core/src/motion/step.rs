@@ -0,0 +1,200 @@
+//! Step detection from the acceleration magnitude.
+//!
+//! The magnitude is smoothed and its slow baseline (gravity) is removed, every swing above
+//! [`STEP_THRESHOLD`] after dropping below [`RESET_THRESHOLD`] is a step candidate. Steps are
+//! only counted once a few of them come in a regular rhythm, so a single bump or shaking the
+//! device doesn't count. Like the gesture detectors it can be fed with recorded data on the
+//! host.
+
+use super::MotionSample;
+
+/// Time constant of the smoothing, removes the ringing of each heel strike.
+const SMOOTHING_MS: f32 = 40.0;
+/// Time constant of the baseline, follows gravity and slow changes of posture.
+const BASELINE_MS: f32 = 1000.0;
+/// Swing above the baseline in g.
+const STEP_THRESHOLD: f32 = 0.1;
+const RESET_THRESHOLD: f32 = -0.05;
+/// Stronger swings are shaking or knocking, they break the rhythm.
+const SHAKE_THRESHOLD: f32 = 1.5;
+/// Nobody walks faster than 4 steps a second.
+const STEP_MIN_GAP_MS: u32 = 250;
+/// A longer pause ends the walk.
+const STEP_MAX_GAP_MS: u32 = 2000;
+/// Steps in a row before they are counted.
+const STREAK_STEPS: u32 = 4;
+
+#[derive(Debug, Default)]
+pub struct StepDetector {
+    smoothed: Option<f32>,
+    baseline: Option<f32>,
+    /// Dropped below the reset threshold since the last candidate.
+    armed: bool,
+    /// Time since the last candidate.
+    since_step_ms: Option<u32>,
+    /// Candidates in a row, counted as steps once it reaches [`STREAK_STEPS`].
+    streak: u32,
+}
+
+impl StepDetector {
+    /// Feed one sample, `dt_ms` after the previous one. Returns the number of new steps.
+    pub fn update(&mut self, sample: &MotionSample, dt_ms: u32) -> u32 {
+        let magnitude = sample.accel_magnitude();
+        let smoothed = filter(self.smoothed, magnitude, dt_ms, SMOOTHING_MS);
+        let baseline = filter(self.baseline, magnitude, dt_ms, BASELINE_MS);
+        self.smoothed = Some(smoothed);
+        self.baseline = Some(baseline);
+
+        let since = self.since_step_ms.map(|since| since.saturating_add(dt_ms));
+        self.since_step_ms = since;
+        if since.is_some_and(|since| since > STEP_MAX_GAP_MS) {
+            self.since_step_ms = None;
+            self.streak = 0;
+        }
+
+        let swing = smoothed - baseline;
+        if swing >= SHAKE_THRESHOLD {
+            self.armed = false;
+            self.streak = 0;
+            return 0;
+        }
+        if swing <= RESET_THRESHOLD {
+            self.armed = true;
+            return 0;
+        }
+        if !self.armed || swing < STEP_THRESHOLD {
+            return 0;
+        }
+        self.armed = false;
+        if self
+            .since_step_ms
+            .replace(0)
+            .is_some_and(|since| since < STEP_MIN_GAP_MS)
+        {
+            // too fast for walking
+            self.streak = 0;
+            return 0;
+        }
+        self.streak += 1;
+        match self.streak {
+            // the streak is complete, count all of it
+            STREAK_STEPS => STREAK_STEPS,
+            streak if streak > STREAK_STEPS => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// First order low-pass filter with time constant `tau_ms`.
+fn filter(previous: Option<f32>, value: f32, dt_ms: u32, tau_ms: f32) -> f32 {
+    match previous {
+        Some(previous) => {
+            let alpha = dt_ms as f32 / (tau_ms + dt_ms as f32);
+            previous + alpha * (value - previous)
+        }
+        None => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::trace::Trace;
+
+    fn count(trace: &str) -> u32 {
+        let trace = Trace::parse(trace);
+        let mut detector = StepDetector::default();
+        trace
+            .samples
+            .iter()
+            .map(|sample| detector.update(sample, trace.dt_ms))
+            .sum()
+    }
+
+    /// A step every `period_ms`, each one a dip and a swing up.
+    fn walk(detector: &mut StepDetector, steps: u32, period_ms: u32) -> u32 {
+        const DT_MS: u32 = 20;
+        let mut counted = 0;
+        for step in 0..steps {
+            for time in (0..period_ms).step_by(DT_MS as usize) {
+                let phase = (time as f32 / period_ms as f32 + step as f32) * std::f32::consts::TAU;
+                let sample = MotionSample {
+                    accel: [0.0, 0.0, 1.0 - 0.3 * phase.cos()],
+                    gyro: [0.0; 3],
+                };
+                counted += detector.update(&sample, DT_MS);
+            }
+        }
+        counted
+    }
+
+    fn rest(detector: &mut StepDetector, duration_ms: u32) -> u32 {
+        let sample = MotionSample {
+            accel: [0.0, 0.0, 1.0],
+            gyro: [0.0; 3],
+        };
+        (0..duration_ms / 20)
+            .map(|_| detector.update(&sample, 20))
+            .sum()
+    }
+
+    #[test]
+    fn walking() {
+        let steps = count(include_str!("traces/walking.csv"));
+        assert!((33..=38).contains(&steps), "{} steps of 36", steps);
+    }
+
+    #[test]
+    fn running() {
+        let steps = count(include_str!("traces/running.csv"));
+        assert!((38..=44).contains(&steps), "{} steps of 42", steps);
+    }
+
+    #[test]
+    fn shaking() {
+        assert_eq!(count(include_str!("traces/shaking.csv")), 0);
+    }
+
+    #[test]
+    fn at_rest() {
+        assert_eq!(count(include_str!("traces/at_rest.csv")), 0);
+    }
+
+    #[test]
+    fn counted_after_a_streak() {
+        let mut detector = StepDetector::default();
+        rest(&mut detector, 2000);
+        assert_eq!(walk(&mut detector, STREAK_STEPS - 1, 500), 0);
+        // the streak is complete, every step so far is counted at once
+        assert_eq!(walk(&mut detector, 1, 500), STREAK_STEPS);
+        assert_eq!(walk(&mut detector, 5, 500), 5);
+    }
+
+    #[test]
+    fn pause_breaks_the_streak() {
+        let mut detector = StepDetector::default();
+        rest(&mut detector, 2000);
+        assert_eq!(walk(&mut detector, STREAK_STEPS - 1, 500), 0);
+        rest(&mut detector, STEP_MAX_GAP_MS + 500);
+        assert_eq!(walk(&mut detector, STREAK_STEPS - 1, 500), 0);
+        assert_eq!(walk(&mut detector, 1, 500), STREAK_STEPS);
+    }
+
+    #[test]
+    fn shake_breaks_the_streak() {
+        let mut detector = StepDetector::default();
+        rest(&mut detector, 2000);
+        // the rising edge of the knock may still pass for a step
+        assert_eq!(walk(&mut detector, STREAK_STEPS - 2, 500), 0);
+        let knock = MotionSample {
+            accel: [0.0, 0.0, 4.0],
+            gyro: [0.0; 3],
+        };
+        let counted: u32 = (0..5).map(|_| detector.update(&knock, 20)).sum();
+        assert_eq!(counted, 0);
+        // shorter than a pause, long enough for the baseline to settle
+        rest(&mut detector, 1500);
+        assert_eq!(walk(&mut detector, STREAK_STEPS - 1, 500), 0);
+        assert_eq!(walk(&mut detector, 1, 500), STREAK_STEPS);
+    }
+}
@@ -0,0 +1,45 @@
+//! Traces replayed through the detectors in tests.
+//!
+//! The files in `traces/` are synthetic, made by `traces/generate.py` in the CSV format of the IMU
+//! logger, so a capture from the device can be dropped in next to them as is.
+
+use super::MotionSample;
+
+/// A capture, the samples and the time between them.
+pub struct Trace {
+    pub samples: Vec<MotionSample>,
+    pub dt_ms: u32,
+}
+
+impl Trace {
+    pub fn parse(text: &str) -> Self {
+        let mut rate = None;
+        let mut samples = Vec::new();
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix('#') {
+                if let Some(value) = comment.trim().strip_prefix("sample_rate_hz=") {
+                    rate = value.parse::<u32>().ok();
+                }
+                continue;
+            }
+            let values: Vec<f32> = line
+                .split(',')
+                .skip(1)
+                .map_while(|value| value.parse().ok())
+                .collect();
+            // the column names
+            let [ax, ay, az, gx, gy, gz] = values[..] else {
+                continue;
+            };
+            samples.push(MotionSample {
+                accel: [ax, ay, az],
+                gyro: [gx, gy, gz],
+            });
+        }
+        let rate = rate.expect("no sample rate in the header");
+        Self {
+            samples,
+            dt_ms: 1000 / rate,
+        }
+    }
+}
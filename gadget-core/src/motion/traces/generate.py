@@ -0,0 +1,110 @@
+# Synthetic traces for the detector tests, not captures from a device.
+#
+# Each motion is modelled with seeded noise, quantized like the sensor and written in the CSV
+# format of the IMU logger. Run it from anywhere to write the CSV files next to it:
+#
+#     python3 generate.py
+
+import math, random, os
+RATE=50; DT=1.0/RATE
+out=os.path.dirname(os.path.abspath(__file__))
+
+def write(name, note, rows):
+    with open(f'{out}/{name}.csv','w') as f:
+        f.write('# synthetic trace from generate.py, in the format of an MPU6886 raw capture\n# start=0\n')
+        f.write(f'# sample_rate_hz={RATE}\n# accel_range_g=4\n# gyro_range_dps=500\n')
+        f.write('# accel_lsb_per_g=8192\n# gyro_lsb_per_dps=65.5\n')
+        f.write(f'# motion: {note}\n')
+        f.write('index,ax_g,ay_g,az_g,gx_dps,gy_dps,gz_dps\n')
+        for i,(a,g) in enumerate(rows):
+            # quantize like the sensor
+            a=[max(-4,min(4,round(v*8192)/8192)) for v in a]
+            g=[max(-500,min(500,round(v*65.5)/65.5)) for v in g]
+            f.write('%d,%.4f,%.4f,%.4f,%.3f,%.3f,%.3f\n'%(i,*a,*g))
+
+def noise(r,s): return r.gauss(0,s)
+
+def rest(r, seconds, tilt=0.0, face=1):
+    rows=[]
+    for _ in range(int(seconds*RATE)):
+        rows.append(([math.sin(tilt)+noise(r,.01), noise(r,.01), face*math.cos(tilt)+noise(r,.01)],
+                     [noise(r,.4),noise(r,.4),noise(r,.4)]))
+    return rows
+
+def turn(r, seconds, a0, a1):
+    """rotate about Y from angle a0 to a1 (rad), z=cos, x=sin"""
+    n=int(seconds*RATE); rows=[]
+    rate=math.degrees(a1-a0)/seconds
+    for i in range(n):
+        p=(i+1)/n; s=(1-math.cos(math.pi*p))/2
+        a=a0+(a1-a0)*s
+        w=rate*math.pi/2*math.sin(math.pi*p)
+        rows.append(([math.sin(a)+noise(r,.03), noise(r,.03), math.cos(a)+noise(r,.03)],
+                     [noise(r,2), w+noise(r,2), noise(r,2)]))
+    return rows
+
+# flip: face up, turned over, left face down, turned back
+r=random.Random(1)
+rows=rest(r,1.5)+turn(r,0.6,0,math.pi)+rest(r,2.0,face=-1)+turn(r,0.6,math.pi,2*math.pi)+rest(r,2.0)
+write('flip','lying face up, turned face down for 2 s, turned back up',rows)
+
+# shake: picked up, shaken left and right for about a second, put back
+r=random.Random(2)
+rows=rest(r,1.0)+turn(r,0.4,0,0.5)
+f=4.0
+for i in range(int(1.1*RATE)):
+    t=i*DT
+    ax=math.sin(0.5)+2.6*math.sin(2*math.pi*f*t)+noise(r,.08)
+    rows.append(([ax, noise(r,.1), math.cos(0.5)+noise(r,.1)],
+                 [noise(r,20), noise(r,20), 150*math.cos(2*math.pi*f*t)+noise(r,10)]))
+rows+=turn(r,0.4,0.5,0)+rest(r,2.0)
+write('shake','picked up, shaken sideways at 4 Hz for 1.1 s, put back flat',rows)
+
+# double tap: flat on the table, tapped twice on the screen
+r=random.Random(3)
+def tap(r):
+    # a spike into the table and some ringing
+    return [([noise(r,.02),noise(r,.02),1+v+noise(r,.02)],[noise(r,3),noise(r,3),noise(r,3)]) for v in (0.9,-0.35,0.12)]
+rows=rest(r,1.5)+tap(r)+rest(r,0.22)+tap(r)+rest(r,2.0)
+write('double_tap','flat on the table, the screen tapped twice 280 ms apart',rows)
+
+# idle: at rest, picked up and read in hand, put down gently
+r=random.Random(4)
+rows=rest(r,3.0)+turn(r,1.0,0,0.9)
+for i in range(int(8*RATE)):
+    t=i*DT
+    a=0.9+0.05*math.sin(2*math.pi*0.3*t)
+    rows.append(([math.sin(a)+0.02*math.sin(2*math.pi*8*t)+noise(r,.02), 0.05*math.sin(2*math.pi*0.2*t)+noise(r,.02), math.cos(a)+noise(r,.02)],
+                 [noise(r,3),noise(r,3)+4*math.cos(2*math.pi*0.3*t),noise(r,3)]))
+rows+=turn(r,1.5,0.9,0)+rest(r,3.0)
+write('idle','at rest, picked up and held for reading, put back down gently',rows)
+
+# steps: magnitude swings of each stride, device held in hand or pocket
+def stride(r, seconds, cadence, swing, jitter):
+    rows=[]; t=0.0; phase=0.0
+    for i in range(int(seconds*RATE)):
+        freq=cadence*(1+noise(r,jitter))
+        phase+=2*math.pi*freq*DT
+        v=swing*math.sin(phase)+0.3*swing*math.sin(2*phase+0.6)
+        # heel strike
+        v+=0.4*swing*max(0,math.sin(phase))**8
+        rows.append(([0.2+noise(r,.03),0.3+noise(r,.03),0.93+v+noise(r,.03)],
+                     [noise(r,15)+20*math.sin(phase),noise(r,15),noise(r,15)]))
+    return rows
+r=random.Random(5)
+rows=rest(r,2.0,tilt=0.3)+stride(r,20.0,1.8,0.35,0.02)+rest(r,2.0,tilt=0.3)
+write('walking','held in hand, 36 steps in 20 s (1.8 steps/s), at rest before and after',rows)
+r=random.Random(6)
+rows=rest(r,2.0,tilt=0.3)+stride(r,15.0,2.8,0.9,0.02)+rest(r,2.0,tilt=0.3)
+write('running','in a pocket, 42 steps in 15 s (2.8 steps/s), at rest before and after',rows)
+r=random.Random(7)
+rows=rest(r,1.0)
+for i in range(int(5*RATE)):
+    t=i*DT
+    rows.append(([2.4*math.sin(2*math.pi*5.5*t)+noise(r,.2),noise(r,.2),1+1.8*math.sin(2*math.pi*5.5*t+1)+noise(r,.2)],
+                 [noise(r,60),noise(r,60),noise(r,60)]))
+rows+=rest(r,1.0)
+write('shaking','shaken hard by hand at 5.5 Hz for 5 s, no walking',rows)
+r=random.Random(8)
+rows=rest(r,30.0,tilt=0.2)
+write('at_rest','on the desk for 30 s',rows)
@@ -0,0 +1,219 @@
+//! Settings of the main page and their stored layout.
+//!
+//! The layout is versioned, settings stored by an older firmware are migrated when they're
+//! loaded, see [`SCHEMA_VERSION`]. Reading and writing NVS is left to the firmware.
+
+/// Version of the stored layout.
+///
+/// 1. `brightness` and `power` in separate entries, without a version.
+/// 2. A single blob, the version followed by the fields in the order of [`FIELDS`].
+///
+/// Fields are only ever appended, so a newer layout starts with the fields known here.
+pub const SCHEMA_VERSION: u8 = 2;
+
+/// Highest level of each setting, the lowest is 0.
+pub const BRIGHTNESS_MAX: u8 = 4;
+/// Also the BLE power level without a limit, +9dBm.
+pub const TRANSMISSION_POWER_MAX: u8 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// Backlight level.
+    pub brightness: u8,
+    /// BLE advertising power level, see the apple juice task.
+    pub transmission_power: u8,
+}
+
+/// A stored setting, all of them are levels from 0.
+struct Field {
+    /// Name in the log.
+    key: &'static str,
+    max: u8,
+    get: fn(&Settings) -> u8,
+    set: fn(&mut Settings, u8),
+}
+
+const FIELDS: [Field; 2] = [
+    Field {
+        key: "brightness",
+        max: BRIGHTNESS_MAX,
+        get: |settings| settings.brightness,
+        set: |settings, value| settings.brightness = value,
+    },
+    Field {
+        key: "transmission_power",
+        max: TRANSMISSION_POWER_MAX,
+        get: |settings| settings.transmission_power,
+        set: |settings, value| settings.transmission_power = value,
+    },
+];
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            brightness: 2,
+            transmission_power: TRANSMISSION_POWER_MAX,
+        }
+    }
+}
+
+impl Settings {
+    /// Every field clamped into its range, the ones out of it are logged.
+    pub fn sanitized(self) -> Self {
+        let mut settings = self;
+        for field in &FIELDS {
+            let value = (field.get)(&self);
+            if value > field.max {
+                log::warn!("Setting {} out of range: {}", field.key, value);
+                (field.set)(&mut settings, field.max);
+            }
+        }
+        settings
+    }
+
+    /// The settings stored in `version`, `None` if the version is unknown.
+    pub fn decode(version: u8, values: Vec<u8>) -> Option<Self> {
+        migrate(version, values).map(|values| Self::from_fields(&values))
+    }
+
+    /// Fields in the stored order, the missing ones are the defaults and the unknown ones are
+    /// ignored.
+    fn from_fields(values: &[u8]) -> Self {
+        let mut settings = Self::default();
+        for (field, value) in FIELDS.iter().zip(values) {
+            (field.set)(&mut settings, *value);
+        }
+        settings.sanitized()
+    }
+
+    /// Fields in the stored order.
+    pub fn fields(&self) -> Vec<u8> {
+        FIELDS.iter().map(|field| (field.get)(self)).collect()
+    }
+
+    /// The blob to store over `stored`. A newer layout keeps its version and the fields unknown
+    /// here, so the firmware that wrote it still finds them.
+    pub fn encode(&self, stored: Option<(u8, &[u8])>) -> Vec<u8> {
+        let mut data = vec![SCHEMA_VERSION];
+        data.extend(self.fields());
+        if let Some((version, values)) = stored.filter(|(version, _)| *version > SCHEMA_VERSION) {
+            data[0] = version;
+            data.extend(values.iter().skip(FIELDS.len()));
+        }
+        data
+    }
+
+    /// Backlight voltage in mV.
+    pub fn backlight_voltage(&self) -> u16 {
+        2600 + self.brightness.min(BRIGHTNESS_MAX) as u16 * 100
+    }
+}
+
+/// The fields stored in `version`, brought to [`SCHEMA_VERSION`] one version at a time.
+///
+/// A newer layout is kept as it is, [`Settings::from_fields`] only takes the fields known here.
+fn migrate(version: u8, values: Vec<u8>) -> Option<Vec<u8>> {
+    match version {
+        1 => migrate(2, migrate_v1(values)),
+        SCHEMA_VERSION..=u8::MAX => Some(values),
+        _ => None,
+    }
+}
+
+/// Version 1 had the same fields, only the layout has changed.
+fn migrate_v1(values: Vec<u8>) -> Vec<u8> {
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(version: u8, values: &[u8]) -> Option<Settings> {
+        Settings::decode(version, values.to_vec())
+    }
+
+    #[test]
+    fn v1_is_migrated() {
+        assert_eq!(
+            load(1, &[3, 5]),
+            Some(Settings {
+                brightness: 3,
+                transmission_power: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn current_version() {
+        let settings = Settings {
+            brightness: 0,
+            transmission_power: 1,
+        };
+        assert_eq!(load(SCHEMA_VERSION, &settings.fields()), Some(settings));
+    }
+
+    #[test]
+    fn unknown_version() {
+        assert_eq!(load(0, &[3, 5]), None);
+    }
+
+    #[test]
+    fn newer_version_keeps_the_known_fields() {
+        assert_eq!(
+            load(SCHEMA_VERSION + 1, &[1, 2, 42, 42]),
+            Some(Settings {
+                brightness: 1,
+                transmission_power: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn newer_version_is_kept_on_store() {
+        let settings = Settings {
+            brightness: 3,
+            transmission_power: 4,
+        };
+        assert_eq!(
+            settings.encode(Some((SCHEMA_VERSION + 1, &[1, 2, 42, 43]))),
+            vec![SCHEMA_VERSION + 1, 3, 4, 42, 43]
+        );
+    }
+
+    #[test]
+    fn older_version_is_replaced_on_store() {
+        let settings = Settings {
+            brightness: 3,
+            transmission_power: 4,
+        };
+        assert_eq!(
+            settings.encode(Some((1, &[1, 2]))),
+            vec![SCHEMA_VERSION, 3, 4]
+        );
+        assert_eq!(settings.encode(None), vec![SCHEMA_VERSION, 3, 4]);
+    }
+
+    #[test]
+    fn missing_fields_are_the_defaults() {
+        assert_eq!(
+            load(SCHEMA_VERSION, &[1]),
+            Some(Settings {
+                brightness: 1,
+                ..Settings::default()
+            })
+        );
+        assert_eq!(load(SCHEMA_VERSION, &[]), Some(Settings::default()));
+    }
+
+    #[test]
+    fn out_of_range_is_clamped() {
+        assert_eq!(
+            load(SCHEMA_VERSION, &[BRIGHTNESS_MAX + 1, u8::MAX]),
+            Some(Settings {
+                brightness: BRIGHTNESS_MAX,
+                transmission_power: TRANSMISSION_POWER_MAX,
+            })
+        );
+    }
+}
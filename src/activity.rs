@@ -0,0 +1,128 @@
+//! Daily step totals.
+//!
+//! Days are counted in system time, which comes from the RTC, so a new day starts at midnight
+//! even if the device was off. Until the RTC has a valid time nothing is booked, or the steps
+//! would go to 1970-01-01. Totals of the last few days are kept in NVS, and only saved now and
+//! then to limit flash wear.
+
+use std::collections::VecDeque;
+
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::EspError,
+};
+
+use crate::clock::DateTime;
+
+/// NVS namespace of the step totals.
+pub const NVS_NAMESPACE: &str = "activity";
+const NVS_KEY_DAYS: &str = "days";
+
+/// Days kept, including today.
+pub const HISTORY_DAYS: usize = 7;
+/// Average stride length in meters, for a rough distance.
+pub const STRIDE_LENGTH: f32 = 0.7;
+
+const DAY_SIZE: usize = 8;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DailySteps {
+    /// Days since UNIX epoch.
+    pub day: u32,
+    pub steps: u32,
+}
+
+impl DailySteps {
+    fn encode(&self) -> [u8; DAY_SIZE] {
+        let mut bytes = [0u8; DAY_SIZE];
+        bytes[0..4].copy_from_slice(&self.day.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.steps.to_le_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        Self {
+            day: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            steps: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+        }
+    }
+
+    /// "MM-DD".
+    pub fn label(&self) -> String {
+        let datetime = DateTime::from_timestamp(self.day as u64 * 86400);
+        format!("{:02}-{:02}", datetime.month, datetime.day)
+    }
+}
+
+/// The day of a timestamp in seconds.
+pub fn day_of(timestamp: u64) -> u32 {
+    (timestamp / 86400) as u32
+}
+
+#[derive(Debug, Default)]
+pub struct ActivityLog {
+    /// Oldest first, the last one is today.
+    days: VecDeque<DailySteps>,
+    /// Changed since the last save.
+    dirty: bool,
+}
+
+impl ActivityLog {
+    pub fn load(nvs: &EspNvs<NvsDefault>) -> Self {
+        let mut buffer = [0u8; HISTORY_DAYS * DAY_SIZE];
+        let days = match nvs.get_blob(NVS_KEY_DAYS, &mut buffer) {
+            Ok(Some(data)) => data
+                .chunks_exact(DAY_SIZE)
+                .map(DailySteps::decode)
+                .collect(),
+            Ok(None) => VecDeque::new(),
+            Err(e) => {
+                log::warn!("Failed to load step totals: {:?}", e);
+                VecDeque::new()
+            }
+        };
+        Self { days, dirty: false }
+    }
+
+    pub fn store(&mut self, nvs: &mut EspNvs<NvsDefault>) -> Result<(), EspError> {
+        let data: Vec<u8> = self.days.iter().flat_map(DailySteps::encode).collect();
+        nvs.set_blob(NVS_KEY_DAYS, &data)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Oldest first.
+    pub fn days(&self) -> impl Iterator<Item = &DailySteps> {
+        self.days.iter()
+    }
+
+    pub fn today(&self) -> u32 {
+        self.days.back().map_or(0, |today| today.steps)
+    }
+
+    /// Start a new day if `day` is not today, returns true if it did.
+    ///
+    /// A day in the past means the clock was set back, the record of today goes on.
+    pub fn roll_over(&mut self, day: u32) -> bool {
+        if self.days.back().is_some_and(|today| today.day >= day) {
+            return false;
+        }
+        self.days.push_back(DailySteps { day, steps: 0 });
+        while self.days.len() > HISTORY_DAYS {
+            self.days.pop_front();
+        }
+        self.dirty = true;
+        true
+    }
+
+    pub fn add_steps(&mut self, steps: u32) {
+        if let Some(today) = self.days.back_mut() {
+            today.steps = today.steps.saturating_add(steps);
+            self.dirty = true;
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! User alarms and the countdown timer.
+//!
+//! The schedule is kept in system time, only the nearest alarm and the countdown are programmed
+//! into the PCF8563. Its interrupt turns the screen back on and rings, there's no sleep state to
+//! wake from. See [`crate::rtc`].
+
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::EspError,
+};
+
+use crate::{clock::DateTime, rtc::Pcf8563Registers};
+
+/// NVS namespace of the alarms.
+pub const NVS_NAMESPACE: &str = "alarms";
+const NVS_KEY_ALARMS: &str = "list";
+
+pub const MAX_ALARMS: usize = 4;
+
+const ALARM_SIZE: usize = 4;
+
+/// Repeat options offered by the UI, (label, weekday mask).
+pub const REPEAT_OPTIONS: [(&str, u8); 3] =
+    [("单次", 0), ("每天", 0b1111111), ("工作日", 0b0111110)];
+
+const WEEKDAY_NAMES: [&str; 7] = ["日", "一", "二", "三", "四", "五", "六"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Alarm {
+    pub hour: u8,
+    pub minute: u8,
+    /// Bit 0 is Sunday, an alarm without any day goes off once.
+    pub weekdays: u8,
+    pub enabled: bool,
+}
+
+impl Alarm {
+    fn encode(&self) -> [u8; ALARM_SIZE] {
+        [self.hour, self.minute, self.weekdays, self.enabled as u8]
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        Self {
+            hour: bytes[0].min(23),
+            minute: bytes[1].min(59),
+            weekdays: bytes[2] & 0b1111111,
+            enabled: bytes[3] != 0,
+        }
+    }
+
+    pub fn repeats(&self) -> bool {
+        self.weekdays != 0
+    }
+
+    /// The first time the alarm goes off after `now`, in seconds since UNIX epoch.
+    pub fn next_after(&self, now: u64) -> Option<u64> {
+        if !self.enabled {
+            return None;
+        }
+        let today = now - now % 86400;
+        let weekday = DateTime::from_timestamp(today).weekday;
+        (0..=7u64)
+            .map(|day| {
+                (
+                    (weekday as u64 + day) % 7,
+                    today + day * 86400 + self.hour as u64 * 3600 + self.minute as u64 * 60,
+                )
+            })
+            .find(|(weekday, timestamp)| {
+                *timestamp > now && (!self.repeats() || self.weekdays & (1 << weekday) != 0)
+            })
+            .map(|(_, timestamp)| timestamp)
+    }
+
+    pub fn label(&self) -> String {
+        let repeat = REPEAT_OPTIONS
+            .iter()
+            .find(|(_, weekdays)| *weekdays == self.weekdays)
+            .map(|(label, _)| label.to_string())
+            .unwrap_or_else(|| {
+                WEEKDAY_NAMES
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| self.weekdays & (1 << index) != 0)
+                    .map(|(_, name)| *name)
+                    .collect()
+            });
+        format!("{:02}:{:02} {}", self.hour, self.minute, repeat)
+    }
+}
+
+/// Why the ringer goes off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingReason {
+    Alarm(Alarm),
+    Countdown,
+}
+
+pub fn load_alarms(nvs: &EspNvs<NvsDefault>) -> Vec<Alarm> {
+    let mut buffer = [0u8; MAX_ALARMS * ALARM_SIZE];
+    match nvs.get_blob(NVS_KEY_ALARMS, &mut buffer) {
+        Ok(Some(data)) => data.chunks_exact(ALARM_SIZE).map(Alarm::decode).collect(),
+        Ok(None) => Vec::new(),
+        Err(e) => {
+            log::warn!("Failed to load alarms: {:?}", e);
+            Vec::new()
+        }
+    }
+}
+
+pub fn store_alarms(nvs: &mut EspNvs<NvsDefault>, alarms: &[Alarm]) -> Result<(), EspError> {
+    let data: Vec<u8> = alarms.iter().flat_map(Alarm::encode).collect();
+    nvs.set_blob(NVS_KEY_ALARMS, &data)
+}
+
+pub struct AlarmScheduler {
+    alarms: Vec<Alarm>,
+    /// When the countdown ends.
+    countdown: Option<u64>,
+    /// The alarm programmed into the RTC, (index, timestamp).
+    armed: Option<(usize, u64)>,
+}
+
+impl AlarmScheduler {
+    pub fn new(mut alarms: Vec<Alarm>) -> Self {
+        alarms.truncate(MAX_ALARMS);
+        Self {
+            alarms,
+            countdown: None,
+            armed: None,
+        }
+    }
+
+    pub fn alarms(&self) -> &[Alarm] {
+        &self.alarms
+    }
+
+    /// Replace the whole list, the ones over [`MAX_ALARMS`] are dropped.
+    pub fn set_alarms(&mut self, mut alarms: Vec<Alarm>) {
+        alarms.truncate(MAX_ALARMS);
+        self.alarms = alarms;
+        self.armed = None;
+    }
+
+    /// Returns false if the list is full.
+    pub fn add(&mut self, alarm: Alarm) -> bool {
+        if self.alarms.len() >= MAX_ALARMS {
+            return false;
+        }
+        self.alarms.push(alarm);
+        true
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.alarms.len() {
+            self.alarms.remove(index);
+            self.armed = None;
+        }
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(alarm) = self.alarms.get_mut(index) {
+            alarm.enabled = enabled;
+            self.armed = None;
+        }
+    }
+
+    pub fn start_countdown(&mut self, now: u64, seconds: u64) {
+        self.countdown = Some(now + seconds);
+    }
+
+    pub fn cancel_countdown(&mut self) {
+        self.countdown = None;
+    }
+
+    pub fn countdown_remaining(&self, now: u64) -> Option<u64> {
+        self.countdown.map(|deadline| deadline.saturating_sub(now))
+    }
+
+    /// The nearest alarm, (index, timestamp).
+    pub fn next_alarm(&self, now: u64) -> Option<(usize, u64)> {
+        self.alarms
+            .iter()
+            .enumerate()
+            .filter_map(|(index, alarm)| alarm.next_after(now).map(|timestamp| (index, timestamp)))
+            .min_by_key(|(_, timestamp)| *timestamp)
+    }
+
+    /// Check if anything is due, one at a time. [`Self::arm`] should be called afterwards.
+    pub fn take_due(&mut self, now: u64) -> Option<RingReason> {
+        if self.countdown.is_some_and(|deadline| deadline <= now) {
+            self.countdown = None;
+            return Some(RingReason::Countdown);
+        }
+        match self.armed {
+            Some((index, timestamp)) if timestamp <= now => {
+                self.armed = None;
+                let alarm = self.alarms.get_mut(index)?;
+                if !alarm.repeats() {
+                    alarm.enabled = false;
+                }
+                Some(RingReason::Alarm(*alarm))
+            }
+            _ => None,
+        }
+    }
+
+    /// Program the RTC with the nearest alarm and the remaining countdown.
+    pub fn arm<I2C: I2c>(
+        &mut self,
+        rtc: &mut Pcf8563Registers<I2C>,
+        now: u64,
+    ) -> Result<(), I2C::Error> {
+        // an alarm which is due but not taken yet stays armed
+        if !self.armed.is_some_and(|(_, timestamp)| timestamp <= now) {
+            self.armed = self.next_alarm(now);
+        }
+        rtc.set_alarm(self.armed.map(|(_, timestamp)| {
+            let datetime = DateTime::from_timestamp(timestamp);
+            (datetime.day, datetime.hour, datetime.minute)
+        }))?;
+        rtc.set_timer(
+            self.countdown_remaining(now)
+                .map(|seconds| seconds.min(u32::MAX as u64) as u32),
+        )
+    }
+}
@@ -6,6 +6,7 @@ use std::{
 };
 
 use super::devices::*;
+use crate::settings::TRANSMISSION_POWER_MAX;
 
 /// Simple task control commands.
 #[allow(dead_code)]
@@ -14,6 +15,8 @@ pub enum JuicyTaskControl {
     Start,
     Stop,
     SetPower(u8),
+    /// Upper bound of the power level, applied on top of `SetPower`.
+    LimitPower(u8),
     Terminate,
 }
 
@@ -49,7 +52,8 @@ pub fn spawn_applejuice_task() -> SyncSender<JuicyTaskControl> {
             // maximum power on esp32, there are higher levels on some of other chips
             .set_power(PowerType::Advertising, PowerLevel::P9)
             .unwrap();
-        let mut max_power_level: u8 = 7;
+        let mut max_power_level = TRANSMISSION_POWER_MAX;
+        let mut power_limit = TRANSMISSION_POWER_MAX;
 
         let mut task_running = false;
 
@@ -62,6 +66,9 @@ pub fn spawn_applejuice_task() -> SyncSender<JuicyTaskControl> {
                     JuicyTaskControl::SetPower(value) => {
                         max_power_level = value;
                     }
+                    JuicyTaskControl::LimitPower(value) => {
+                        power_limit = value;
+                    }
                 };
             }
 
@@ -71,7 +78,7 @@ pub fn spawn_applejuice_task() -> SyncSender<JuicyTaskControl> {
             }
 
             // TODO: randomize
-            let power_level = to_power_level(max_power_level);
+            let power_level = to_power_level(max_power_level.min(power_limit));
 
             // 4 bytes, utilize all randomness
             let random_number = rand_u32();
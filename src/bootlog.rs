@@ -0,0 +1,139 @@
+//! Boot records, to find out why a unit rebooted in the field.
+
+use core::fmt;
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::{self, EspError},
+};
+
+use crate::pmu::bootreason::{power_off_reasons, power_on_reasons};
+
+/// NVS namespace of the boot records.
+pub const NVS_NAMESPACE: &str = "bootlog";
+
+/// Number of boot records kept.
+pub const BOOT_RECORDS_KEPT: usize = 8;
+
+const RECORD_SIZE: usize = 11;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BootRecord {
+    pub boot_count: u32,
+    /// Seconds since UNIX epoch, only meaningful if the system time is set.
+    pub timestamp: u32,
+    /// Raw power-on source of AXP2101.
+    pub power_on: u8,
+    /// Raw power-off source of AXP2101, from the previous shutdown.
+    pub power_off: u8,
+    /// ESP-IDF `esp_reset_reason_t`.
+    pub reset_reason: u8,
+}
+
+pub fn reset_reason_name(reason: u8) -> &'static str {
+    match reason as sys::esp_reset_reason_t {
+        sys::esp_reset_reason_t_ESP_RST_POWERON => "power on",
+        sys::esp_reset_reason_t_ESP_RST_EXT => "external pin",
+        sys::esp_reset_reason_t_ESP_RST_SW => "software",
+        sys::esp_reset_reason_t_ESP_RST_PANIC => "panic",
+        sys::esp_reset_reason_t_ESP_RST_INT_WDT => "interrupt watchdog",
+        sys::esp_reset_reason_t_ESP_RST_TASK_WDT => "task watchdog",
+        sys::esp_reset_reason_t_ESP_RST_WDT => "other watchdog",
+        sys::esp_reset_reason_t_ESP_RST_DEEPSLEEP => "deep sleep",
+        sys::esp_reset_reason_t_ESP_RST_BROWNOUT => "brownout",
+        sys::esp_reset_reason_t_ESP_RST_SDIO => "SDIO",
+        _ => "unknown",
+    }
+}
+
+impl BootRecord {
+    /// Collect the reasons of the current boot.
+    pub fn current(boot_count: u32, timestamp: u32, power_sources: (u8, u8)) -> Self {
+        let reset_reason = unsafe { sys::esp_reset_reason() } as u8;
+        Self {
+            boot_count,
+            timestamp,
+            power_on: power_sources.0,
+            power_off: power_sources.1,
+            reset_reason,
+        }
+    }
+
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut buffer = [0u8; RECORD_SIZE];
+        buffer[0..4].copy_from_slice(&self.boot_count.to_le_bytes());
+        buffer[4..8].copy_from_slice(&self.timestamp.to_le_bytes());
+        buffer[8] = self.power_on;
+        buffer[9] = self.power_off;
+        buffer[10] = self.reset_reason;
+        buffer
+    }
+
+    fn decode(buffer: &[u8]) -> Self {
+        Self {
+            boot_count: u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]),
+            timestamp: u32::from_le_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]),
+            power_on: buffer[8],
+            power_off: buffer[9],
+            reset_reason: buffer[10],
+        }
+    }
+}
+
+impl fmt::Display for BootRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on: Vec<&str> = power_on_reasons(self.power_on).collect();
+        let off: Vec<&str> = power_off_reasons(self.power_off).collect();
+        write!(
+            f,
+            "#{} reset: {}, on: {}, last off: {}",
+            self.boot_count,
+            reset_reason_name(self.reset_reason),
+            if on.is_empty() {
+                "-".into()
+            } else {
+                on.join("+")
+            },
+            if off.is_empty() {
+                "-".into()
+            } else {
+                off.join("+")
+            },
+        )
+    }
+}
+
+/// Load the records, newest last.
+pub fn load_boot_records(nvs: &EspNvs<NvsDefault>) -> Vec<BootRecord> {
+    let mut buffer = [0u8; BOOT_RECORDS_KEPT * RECORD_SIZE];
+    match nvs.get_blob("records", &mut buffer) {
+        Ok(Some(data)) => data
+            .chunks_exact(RECORD_SIZE)
+            .map(BootRecord::decode)
+            .collect(),
+        Ok(None) => Vec::new(),
+        Err(e) => {
+            log::warn!("Failed to load boot records: {:?}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Append the record of the current boot, returning all kept records.
+pub fn append_boot_record(
+    nvs: &mut EspNvs<NvsDefault>,
+    timestamp: u32,
+    power_sources: (u8, u8),
+) -> Result<Vec<BootRecord>, EspError> {
+    let mut records = load_boot_records(nvs);
+    let boot_count = records
+        .last()
+        .map(|record| record.boot_count + 1)
+        .unwrap_or(1);
+    records.push(BootRecord::current(boot_count, timestamp, power_sources));
+    if records.len() > BOOT_RECORDS_KEPT {
+        records.drain(..records.len() - BOOT_RECORDS_KEPT);
+    }
+    let buffer: Vec<u8> = records.iter().flat_map(|record| record.encode()).collect();
+    nvs.set_blob("records", &buffer)?;
+    Ok(records)
+}
@@ -0,0 +1,101 @@
+//! Calendar helpers, UNIX timestamp <-> civil date and time (UTC).
+//!
+//! The algorithms are from <http://howardhinnant.github.io/date_algorithms.html>.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1..=12
+    pub month: u8,
+    /// 1..=31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// 0 is Sunday.
+    pub weekday: u8,
+}
+
+const WEEKDAY_NAMES: [&str; 7] = ["日", "一", "二", "三", "四", "五", "六"];
+
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        _ if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        _ => 28,
+    }
+}
+
+impl DateTime {
+    pub fn from_timestamp(timestamp: u64) -> Self {
+        let days = (timestamp / 86400) as i64;
+        let seconds = timestamp % 86400;
+        // civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+            // 1970-01-01 is Thursday
+            weekday: ((days + 4).rem_euclid(7)) as u8,
+        }
+    }
+
+    pub fn to_timestamp(&self) -> u64 {
+        // days_from_civil
+        let year = self.year as i64 - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let yoe = year - era * 400;
+        let month = self.month as i64;
+        let doy =
+            (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+        days.max(0) as u64 * 86400
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64
+    }
+
+    /// Clamp every field into its valid range, the weekday is recalculated.
+    pub fn sanitized(self) -> Self {
+        let year = self.year.clamp(2000, 2099);
+        let month = self.month.clamp(1, 12);
+        let value = Self {
+            year,
+            month,
+            day: self.day.clamp(1, days_in_month(year, month)),
+            hour: self.hour.min(23),
+            minute: self.minute.min(59),
+            second: self.second.min(59),
+            weekday: 0,
+        };
+        Self::from_timestamp(value.to_timestamp())
+    }
+
+    pub fn time_string(&self) -> String {
+        format!("{:02}:{:02}", self.hour, self.minute)
+    }
+
+    pub fn date_string(&self) -> String {
+        format!(
+            "{}-{:02}-{:02} 周{}",
+            self.year,
+            self.month,
+            self.day,
+            WEEKDAY_NAMES[self.weekday as usize % 7]
+        )
+    }
+}
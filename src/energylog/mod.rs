@@ -0,0 +1,151 @@
+//! Battery and energy history.
+//!
+//! Records are kept in a ring buffer, which is persisted to NVS in a compact binary form,
+//! and exported as CSV to the TF card.
+
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+};
+
+use crate::{
+    pmu::battery::{BatteryStatus, ChargeState},
+    powermon::PowerSample,
+};
+
+mod task;
+
+pub use task::spawn_energylog_task;
+pub use task::EnergyLogControl;
+
+/// Maximum records kept, approx. 12 hours at the default interval.
+pub const HISTORY_CAPACITY: usize = 144;
+
+/// Selectable logging intervals in seconds.
+pub const INTERVAL_OPTIONS: [u32; 4] = [60, 300, 900, 1800];
+pub const DEFAULT_INTERVAL: u32 = 300;
+
+/// NVS namespace of the history and the interval.
+pub const NVS_NAMESPACE: &str = "energylog";
+
+const RECORD_SIZE: usize = 20;
+
+/// File on the TF card the history is exported to.
+pub const EXPORT_FILE: &str = "energylog.csv";
+
+pub const CSV_HEADER: &str = "timestamp,battery_percent,battery_mv,charge_state,vbus,\
+ch1_mv,ch1_ma,ch2_mv,ch2_ma,ch3_mv,ch3_ma";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EnergyRecord {
+    /// Seconds since UNIX epoch, only meaningful after the system time is set.
+    pub timestamp: u32,
+    pub battery: BatteryStatus,
+    /// (bus voltage in mV, current in mA) of each INA3221 channel.
+    pub channels: [(u16, i16); 3],
+}
+
+impl EnergyRecord {
+    pub fn new(timestamp: u32, battery: BatteryStatus, power: Option<PowerSample>) -> Self {
+        let mut channels = [(0, 0); 3];
+        if let Some(power) = power {
+            for (channel, reading) in channels.iter_mut().zip(power.channels) {
+                *channel = (
+                    reading.bus_millivolts as u16,
+                    reading.current_milliamps as i16,
+                );
+            }
+        }
+        Self {
+            timestamp,
+            battery,
+            channels,
+        }
+    }
+
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut buffer = [0u8; RECORD_SIZE];
+        buffer[0..4].copy_from_slice(&self.timestamp.to_le_bytes());
+        buffer[4] = self.battery.percent;
+        buffer[5] = u8::from(self.battery.charge_state)
+            | (self.battery.vbus_present as u8) << 6
+            | (self.battery.present as u8) << 7;
+        buffer[6..8].copy_from_slice(&self.battery.millivolts.to_le_bytes());
+        for (index, (millivolts, milliamps)) in self.channels.iter().enumerate() {
+            let offset = 8 + index * 4;
+            buffer[offset..offset + 2].copy_from_slice(&millivolts.to_le_bytes());
+            buffer[offset + 2..offset + 4].copy_from_slice(&milliamps.to_le_bytes());
+        }
+        buffer
+    }
+
+    fn decode(buffer: &[u8]) -> Self {
+        let u16_at = |offset: usize| u16::from_le_bytes([buffer[offset], buffer[offset + 1]]);
+        let mut channels = [(0, 0); 3];
+        for (index, channel) in channels.iter_mut().enumerate() {
+            let offset = 8 + index * 4;
+            *channel = (u16_at(offset), u16_at(offset + 2) as i16);
+        }
+        Self {
+            timestamp: u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]),
+            battery: BatteryStatus {
+                present: buffer[5] & (1 << 7) != 0,
+                percent: buffer[4],
+                millivolts: u16_at(6),
+                charge_state: ChargeState::from(buffer[5] & 0b111),
+                vbus_present: buffer[5] & (1 << 6) != 0,
+            },
+            channels,
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut line = format!(
+            "{},{},{},{:?},{}",
+            self.timestamp,
+            self.battery.percent,
+            self.battery.millivolts,
+            self.battery.charge_state,
+            self.battery.vbus_present as u8,
+        );
+        for (millivolts, milliamps) in self.channels {
+            line.push_str(&format!(",{},{}", millivolts, milliamps));
+        }
+        line
+    }
+}
+
+/// Load the stored logging interval in seconds.
+pub fn load_interval(nvs: &EspNvs<NvsDefault>) -> u32 {
+    nvs.get_u32("interval")
+        .ok()
+        .flatten()
+        .unwrap_or(DEFAULT_INTERVAL)
+}
+
+/// Serialize the history for persistence.
+pub fn encode_history(history: &VecDeque<EnergyRecord>) -> Vec<u8> {
+    history.iter().flat_map(|record| record.encode()).collect()
+}
+
+/// Restore the history, trailing partial records are ignored.
+pub fn decode_history(buffer: &[u8]) -> VecDeque<EnergyRecord> {
+    let mut history: VecDeque<EnergyRecord> = buffer
+        .chunks_exact(RECORD_SIZE)
+        .map(EnergyRecord::decode)
+        .collect();
+    while history.len() > HISTORY_CAPACITY {
+        history.pop_front();
+    }
+    history
+}
+
+/// Write the header and a line for each record.
+pub fn write_csv<W: Write>(mut writer: W, records: &[EnergyRecord]) -> io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for record in records {
+        writeln!(writer, "{}", record.to_csv())?;
+    }
+    writer.flush()
+}
@@ -0,0 +1,144 @@
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::{
+    hal::delay::FreeRtos as FreeRtosDelay,
+    nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault},
+};
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{self, SyncSender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    inputevent::InputEvent,
+    pmu::{battery::read_battery_status, Axp2101Registers},
+    powermon::PowerSample,
+    utils::now_timestamp,
+};
+
+use super::{
+    decode_history, encode_history, load_interval, EnergyRecord, HISTORY_CAPACITY, NVS_NAMESPACE,
+    RECORD_SIZE,
+};
+
+/// Persist every N records to limit flash wear.
+const PERSIST_EVERY: usize = 3;
+
+/// Simple task control commands.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnergyLogControl {
+    /// Latest INA3221 readings, attached to the next record.
+    PowerSample(PowerSample),
+    /// Logging interval in seconds.
+    SetInterval(u32),
+    Clear,
+    Terminate,
+}
+
+fn load_history(nvs: &EspNvs<NvsDefault>) -> VecDeque<EnergyRecord> {
+    let mut buffer = vec![0u8; HISTORY_CAPACITY * RECORD_SIZE];
+    match nvs.get_blob("records", &mut buffer) {
+        Ok(Some(data)) => decode_history(data),
+        Ok(None) => VecDeque::with_capacity(HISTORY_CAPACITY),
+        Err(e) => {
+            log::warn!("Failed to load energy history: {:?}", e);
+            VecDeque::with_capacity(HISTORY_CAPACITY)
+        }
+    }
+}
+
+fn save_history(nvs: &mut EspNvs<NvsDefault>, history: &VecDeque<EnergyRecord>) {
+    if let Err(e) = nvs.set_blob("records", &encode_history(history)) {
+        log::error!("Failed to save energy history: {:?}", e);
+    }
+}
+
+/// Spawn the energy logging task and return a control handle.
+///
+/// The whole history is delivered as [`InputEvent::EnergyLog`] whenever it changes.
+#[inline]
+pub fn spawn_energylog_task<I2C>(
+    mut registers: Axp2101Registers<I2C>,
+    nvs_partition: EspDefaultNvsPartition,
+    sender: SyncSender<InputEvent>,
+) -> SyncSender<EnergyLogControl>
+where
+    I2C: I2c + Send + 'static,
+{
+    let (control_sender, receiver) = mpsc::sync_channel::<EnergyLogControl>(8);
+    let _ = thread::spawn(move || {
+        let mut nvs = match EspNvs::new(nvs_partition, NVS_NAMESPACE, true) {
+            Ok(nvs) => nvs,
+            Err(e) => {
+                log::error!("Failed to open energy log storage: {:?}", e);
+                return;
+            }
+        };
+        let mut history = load_history(&nvs);
+        let mut interval = load_interval(&nvs);
+        let mut latest_power: Option<PowerSample> = None;
+        let mut unsaved = 0usize;
+        let mut last_record: Option<Instant> = None;
+        let _ = sender.send(InputEvent::EnergyLog(history.iter().copied().collect()));
+
+        loop {
+            for event in receiver.try_iter() {
+                match event {
+                    EnergyLogControl::PowerSample(sample) => latest_power = Some(sample),
+                    EnergyLogControl::SetInterval(value) => {
+                        interval = value.max(1);
+                        if let Err(e) = nvs.set_u32("interval", interval) {
+                            log::error!("Failed to save energy log interval: {:?}", e);
+                        }
+                    }
+                    EnergyLogControl::Clear => {
+                        history.clear();
+                        save_history(&mut nvs, &history);
+                        unsaved = 0;
+                        let _ = sender.send(InputEvent::EnergyLog(Vec::new()));
+                    }
+                    EnergyLogControl::Terminate => {
+                        if unsaved > 0 {
+                            save_history(&mut nvs, &history);
+                        }
+                        return;
+                    }
+                };
+            }
+
+            let due = last_record
+                .map(|last| last.elapsed() >= Duration::from_secs(interval as u64))
+                .unwrap_or(true);
+            if due {
+                last_record = Some(Instant::now());
+                match read_battery_status(&mut registers) {
+                    Ok(battery) => {
+                        if history.len() == HISTORY_CAPACITY {
+                            history.pop_front();
+                        }
+                        history.push_back(EnergyRecord::new(
+                            now_timestamp(),
+                            battery,
+                            // only a sample taken since the previous record belongs to this one
+                            latest_power.take(),
+                        ));
+                        unsaved += 1;
+                        if unsaved >= PERSIST_EVERY {
+                            save_history(&mut nvs, &history);
+                            unsaved = 0;
+                        }
+                        let _ = sender
+                            .try_send(InputEvent::EnergyLog(history.iter().copied().collect()));
+                    }
+                    Err(e) => log::warn!("Failed to read battery status: {:?}", e),
+                }
+            }
+
+            FreeRtosDelay::delay_ms(1000);
+        }
+    });
+
+    control_sender
+}
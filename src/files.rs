@@ -0,0 +1,274 @@
+//! Directory listing and file operations for the file browser.
+//!
+//! Everything goes through `std::fs`, so it works on any mounted filesystem. The browser is
+//! kept inside its root, usually [`crate::storage::MOUNT_POINT`].
+
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{clock::DateTime, storage::format_size};
+
+/// What opens a file, chosen by the extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Viewer {
+    Text,
+    Image,
+    Csv,
+}
+
+/// Registered viewers, extensions in lower case.
+const VIEWERS: [(&str, Viewer); 9] = [
+    ("txt", Viewer::Text),
+    ("log", Viewer::Text),
+    ("md", Viewer::Text),
+    ("ini", Viewer::Text),
+    ("csv", Viewer::Csv),
+    ("bmp", Viewer::Image),
+    ("png", Viewer::Image),
+    ("jpg", Viewer::Image),
+    ("jpeg", Viewer::Image),
+];
+
+pub fn viewer_for(path: &Path) -> Option<Viewer> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    VIEWERS
+        .iter()
+        .find(|(candidate, _)| *candidate == extension)
+        .map(|(_, viewer)| *viewer)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    /// Seconds since UNIX epoch.
+    pub modified: Option<u64>,
+}
+
+impl Entry {
+    /// Size and date, "1.5 MB  2024-05-01 12:00".
+    pub fn detail(&self) -> String {
+        let date = self
+            .modified
+            .map(|timestamp| {
+                let datetime = DateTime::from_timestamp(timestamp);
+                format!(
+                    "{:04}-{:02}-{:02} {}",
+                    datetime.year,
+                    datetime.month,
+                    datetime.day,
+                    datetime.time_string()
+                )
+            })
+            .unwrap_or_default();
+        if self.is_dir {
+            date
+        } else {
+            format!("{}  {}", format_size(self.size), date)
+        }
+    }
+}
+
+/// Folders first, then by name.
+pub fn list(directory: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries: Vec<Entry> = fs::read_dir(directory)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            Some(Entry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: metadata.is_dir(),
+                size: metadata.len(),
+                modified: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map(|duration| duration.as_secs()),
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        b.is_dir
+            .cmp(&a.is_dir)
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+    Ok(entries)
+}
+
+/// A name that stays in the same folder.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+/// Delete a file, or a folder with everything in it.
+pub fn delete(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Rename within the same folder, an existing file is never replaced.
+pub fn rename(path: &Path, name: &str) -> io::Result<PathBuf> {
+    if !is_valid_name(name) {
+        return Err(io::ErrorKind::InvalidInput.into());
+    }
+    let target = path.with_file_name(name);
+    if target.exists() {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+/// Rows shown by the CSV viewer at most, the rest is left on the card.
+pub const CSV_MAX_ROWS: usize = 500;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CsvTable {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// There were more than [`CSV_MAX_ROWS`] rows.
+    pub truncated: bool,
+}
+
+/// Read a CSV file, the first row is the header.
+///
+/// Lines starting with `#` are comments, as written by the IMU logger.
+pub fn read_csv(path: &Path) -> io::Result<CsvTable> {
+    let mut table = CsvTable::default();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if table.header.is_empty() {
+            table.header = split_csv_line(line);
+        } else if table.rows.len() < CSV_MAX_ROWS {
+            table.rows.push(split_csv_line(line));
+        } else {
+            table.truncated = true;
+            break;
+        }
+    }
+    Ok(table)
+}
+
+/// Split on commas, quoted fields may have commas and `""` in them.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// The browser position, never above `root`.
+#[derive(Debug)]
+pub struct FileBrowser {
+    root: PathBuf,
+    current: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl FileBrowser {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            current: root.clone(),
+            root,
+            entries: Vec::new(),
+        }
+    }
+
+    /// The current folder relative to the root, "/" at the root.
+    pub fn display_path(&self) -> String {
+        let relative = self
+            .current
+            .strip_prefix(&self.root)
+            .unwrap_or(&self.current);
+        format!("/{}", relative.to_string_lossy())
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, index: usize) -> Option<&Entry> {
+        self.entries.get(index)
+    }
+
+    pub fn path_of(&self, index: usize) -> Option<PathBuf> {
+        self.entry(index)
+            .map(|entry| self.current.join(&entry.name))
+    }
+
+    /// Files in the current folder opened by `viewer`.
+    pub fn paths_for(&self, viewer: Viewer) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.is_dir)
+            .map(|entry| self.current.join(&entry.name))
+            .filter(|path| viewer_for(path) == Some(viewer))
+            .collect()
+    }
+
+    /// Read the current folder again, back to the root if it's gone.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        self.entries = match list(&self.current) {
+            Ok(entries) => entries,
+            Err(_) if self.current != self.root => {
+                self.current = self.root.clone();
+                list(&self.current)?
+            }
+            Err(e) => {
+                self.entries.clear();
+                return Err(e);
+            }
+        };
+        Ok(())
+    }
+
+    /// Go into the folder at `index`, returns false if it's not a folder.
+    ///
+    /// Call [`Self::refresh`] afterwards.
+    pub fn enter(&mut self, index: usize) -> bool {
+        match self.entry(index) {
+            Some(entry) if entry.is_dir => {
+                self.current = self.current.join(&entry.name);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Go to the parent folder, returns false at the root.
+    ///
+    /// Call [`Self::refresh`] afterwards.
+    pub fn up(&mut self) -> bool {
+        if self.current == self.root {
+            return false;
+        }
+        self.current.pop();
+        true
+    }
+}
@@ -0,0 +1,31 @@
+//! Vibration motor feedback.
+//!
+//! The motor is driven by the DLDO1 rail of AXP2101, see [`crate::pmu::rails`].
+
+mod task;
+
+pub use task::spawn_haptics_task;
+
+/// Motor drive voltage, higher is stronger.
+pub const MOTOR_MILLIVOLTS: u16 = 3000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HapticPattern {
+    /// A short click, for touch buttons.
+    Tap,
+    Double,
+    Error,
+    Long,
+}
+
+impl HapticPattern {
+    /// Alternating on/off durations in ms, starting with on.
+    pub fn steps(self) -> &'static [u32] {
+        match self {
+            HapticPattern::Tap => &[40],
+            HapticPattern::Double => &[40, 80, 40],
+            HapticPattern::Error => &[80, 60, 80, 60, 80],
+            HapticPattern::Long => &[400],
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::hal::delay::FreeRtos as FreeRtosDelay;
+use std::{
+    sync::mpsc::{self, SyncSender},
+    thread,
+};
+
+use crate::pmu::rails::{Rail, RailManager};
+
+use super::{HapticPattern, MOTOR_MILLIVOLTS};
+
+/// Spawn the haptics task and return a handle to play patterns.
+///
+/// Requests arriving while a pattern is playing are dropped, so feedback never lags behind.
+#[inline]
+pub fn spawn_haptics_task<I2C>(mut rails: RailManager<I2C>) -> SyncSender<HapticPattern>
+where
+    I2C: I2c + Send + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel::<HapticPattern>(2);
+    let _ = thread::spawn(move || {
+        if let Err(e) = rails.set_voltage(Rail::Dldo1, MOTOR_MILLIVOLTS) {
+            log::error!("Failed to configure vibration motor: {:?}", e);
+            return;
+        }
+
+        for pattern in receiver.iter() {
+            for (index, duration) in pattern.steps().iter().enumerate() {
+                let result = if index % 2 == 0 {
+                    rails.enable(Rail::Dldo1)
+                } else {
+                    rails.disable(Rail::Dldo1)
+                };
+                if let Err(e) = result {
+                    log::warn!("Failed to drive vibration motor: {:?}", e);
+                }
+                FreeRtosDelay::delay_ms(*duration);
+            }
+            // always stop the motor at the end
+            let _ = rails.disable(Rail::Dldo1);
+            // drop stale requests
+            for _ in receiver.try_iter() {}
+        }
+    });
+
+    sender
+}
@@ -0,0 +1,490 @@
+//! Image decoding for the image viewer.
+//!
+//! BMP, PNG and JPEG files are decoded and scaled down to fit the screen. Large buffers end up
+//! in PSRAM, which is shared with everything else, so decoding is kept within
+//! [`DECODE_BUDGET`]: BMP and non-interlaced PNG are streamed row by row, and JPEG is scaled
+//! down by the decoder itself before the pixels are produced.
+//!
+//! Decoding a large JPEG from the card takes seconds, so it's done by [`spawn_image_task`], off
+//! the super loop.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use slint::{Rgb8Pixel, SharedPixelBuffer};
+
+mod task;
+
+pub use task::spawn_image_task;
+pub use task::{DecodedImage, ImageControl};
+
+/// Images are scaled down to fit in this, the size of the panel.
+pub const MAX_WIDTH: u32 = 320;
+pub const MAX_HEIGHT: u32 = 240;
+/// Bytes the decoders may allocate for a full frame.
+pub const DECODE_BUDGET: usize = 2 * 1024 * 1024;
+/// Images wider than this are refused, to bound the row buffers.
+const MAX_SOURCE_WIDTH: u32 = 16384;
+/// Time each image is shown in a slideshow.
+pub const SLIDESHOW_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageError {
+    Io(io::ErrorKind),
+    /// A format or a variant of it which can't be decoded.
+    Unsupported,
+    /// Decoding would take more memory than [`DECODE_BUDGET`].
+    TooLarge,
+    Corrupt,
+}
+
+impl core::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ImageError::Io(kind) => write!(f, "读取失败: {}", kind),
+            ImageError::Unsupported => write!(f, "不支持的图片格式"),
+            ImageError::TooLarge => write!(f, "图片太大"),
+            ImageError::Corrupt => write!(f, "图片已损坏"),
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData => ImageError::Corrupt,
+            kind => ImageError::Io(kind),
+        }
+    }
+}
+
+impl From<png::DecodingError> for ImageError {
+    fn from(e: png::DecodingError) -> Self {
+        match e {
+            png::DecodingError::IoError(e) => e.into(),
+            png::DecodingError::LimitsExceeded => ImageError::TooLarge,
+            png::DecodingError::Format(_) => ImageError::Corrupt,
+            png::DecodingError::Parameter(_) => ImageError::Unsupported,
+        }
+    }
+}
+
+impl From<jpeg_decoder::Error> for ImageError {
+    fn from(e: jpeg_decoder::Error) -> Self {
+        match e {
+            jpeg_decoder::Error::Io(e) => e.into(),
+            jpeg_decoder::Error::Unsupported(_) => ImageError::Unsupported,
+            jpeg_decoder::Error::Format(_) | jpeg_decoder::Error::Internal(_) => {
+                ImageError::Corrupt
+            }
+        }
+    }
+}
+
+/// The size of a `width`x`height` image scaled down to fit the screen, aspect ratio kept.
+pub fn fit_size(width: u32, height: u32) -> (u32, u32) {
+    if width <= MAX_WIDTH && height <= MAX_HEIGHT {
+        return (width.max(1), height.max(1));
+    }
+    // compare MAX_WIDTH / width against MAX_HEIGHT / height
+    if width as u64 * MAX_HEIGHT as u64 >= height as u64 * MAX_WIDTH as u64 {
+        let scaled = (height as u64 * MAX_WIDTH as u64 / width as u64) as u32;
+        (MAX_WIDTH, scaled.max(1))
+    } else {
+        let scaled = (width as u64 * MAX_HEIGHT as u64 / height as u64) as u32;
+        (scaled.max(1), MAX_HEIGHT)
+    }
+}
+
+/// Box filter, takes source rows top down in RGB and averages them into the target.
+struct Scaler {
+    source_width: u32,
+    source_height: u32,
+    buffer: SharedPixelBuffer<Rgb8Pixel>,
+    /// Source rows taken.
+    row: u32,
+    /// Target row being accumulated.
+    target_row: u32,
+    /// Sums of R, G, B and the count of each target pixel of the row.
+    sums: Vec<[u32; 4]>,
+}
+
+impl Scaler {
+    fn new(source_width: u32, source_height: u32) -> Self {
+        let (width, height) = fit_size(source_width, source_height);
+        Self {
+            source_width,
+            source_height,
+            buffer: SharedPixelBuffer::new(width, height),
+            row: 0,
+            target_row: 0,
+            sums: vec![[0; 4]; width as usize],
+        }
+    }
+
+    fn push_row(&mut self, rgb: &[u8]) {
+        if self.row >= self.source_height {
+            return;
+        }
+        let width = self.buffer.width();
+        let target_row =
+            (self.row as u64 * self.buffer.height() as u64 / self.source_height as u64) as u32;
+        if target_row != self.target_row {
+            self.flush();
+            self.target_row = target_row;
+        }
+        for (x, pixel) in rgb
+            .chunks_exact(3)
+            .take(self.source_width as usize)
+            .enumerate()
+        {
+            let target_x = (x as u64 * width as u64 / self.source_width as u64) as usize;
+            let sum = &mut self.sums[target_x];
+            sum[0] += pixel[0] as u32;
+            sum[1] += pixel[1] as u32;
+            sum[2] += pixel[2] as u32;
+            sum[3] += 1;
+        }
+        self.row += 1;
+    }
+
+    fn flush(&mut self) {
+        let width = self.buffer.width() as usize;
+        let start = self.target_row as usize * width;
+        let pixels = &mut self.buffer.make_mut_slice()[start..start + width];
+        for (pixel, sum) in pixels.iter_mut().zip(self.sums.iter_mut()) {
+            if sum[3] > 0 {
+                *pixel = Rgb8Pixel::new(
+                    (sum[0] / sum[3]) as u8,
+                    (sum[1] / sum[3]) as u8,
+                    (sum[2] / sum[3]) as u8,
+                );
+            }
+            *sum = [0; 4];
+        }
+    }
+
+    fn finish(mut self) -> SharedPixelBuffer<Rgb8Pixel> {
+        self.flush();
+        self.buffer
+    }
+}
+
+/// Decode an image file and scale it down to fit the screen, the format is told by the content.
+pub fn decode(path: &Path) -> Result<SharedPixelBuffer<Rgb8Pixel>, ImageError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    reader.rewind()?;
+    match magic {
+        [b'B', b'M', ..] => decode_bmp(reader),
+        [0x89, b'P', b'N', b'G'] => decode_png(reader),
+        [0xff, 0xd8, 0xff, _] => decode_jpeg(reader),
+        _ => Err(ImageError::Unsupported),
+    }
+}
+
+fn decode_png(reader: BufReader<File>) -> Result<SharedPixelBuffer<Rgb8Pixel>, ImageError> {
+    let mut decoder = png::Decoder::new_with_limits(
+        reader,
+        png::Limits {
+            bytes: DECODE_BUDGET,
+        },
+    );
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let (width, height, interlaced) = {
+        let info = reader.info();
+        (info.width, info.height, info.interlaced)
+    };
+    if width > MAX_SOURCE_WIDTH {
+        return Err(ImageError::TooLarge);
+    }
+    let (color_type, _) = reader.output_color_type();
+    let mut scaler = Scaler::new(width, height);
+    let mut rgb = Vec::with_capacity(width as usize * 3);
+    if interlaced {
+        // the passes only make up the image at the end, so it's the whole frame
+        if reader.output_buffer_size() > DECODE_BUDGET {
+            return Err(ImageError::TooLarge);
+        }
+        let mut frame = vec![0u8; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut frame)?;
+        for row in frame.chunks_exact(info.line_size) {
+            png_row_to_rgb(row, color_type, &mut rgb)?;
+            scaler.push_row(&rgb);
+        }
+    } else {
+        while let Some(row) = reader.next_row()? {
+            png_row_to_rgb(row.data(), color_type, &mut rgb)?;
+            scaler.push_row(&rgb);
+        }
+    }
+    Ok(scaler.finish())
+}
+
+/// Transparent pixels are put on black, the background of the viewer.
+fn png_row_to_rgb(
+    row: &[u8],
+    color_type: png::ColorType,
+    rgb: &mut Vec<u8>,
+) -> Result<(), ImageError> {
+    let blend = |value: u8, alpha: u8| (value as u16 * alpha as u16 / 255) as u8;
+    rgb.clear();
+    match color_type {
+        png::ColorType::Rgb => rgb.extend_from_slice(row),
+        png::ColorType::Rgba => rgb.extend(
+            row.chunks_exact(4)
+                .flat_map(|pixel| [0, 1, 2].map(|channel| blend(pixel[channel], pixel[3]))),
+        ),
+        png::ColorType::Grayscale => rgb.extend(row.iter().flat_map(|&value| [value; 3])),
+        png::ColorType::GrayscaleAlpha => rgb.extend(
+            row.chunks_exact(2)
+                .flat_map(|pixel| [blend(pixel[0], pixel[1]); 3]),
+        ),
+        // expanded by the decoder
+        png::ColorType::Indexed => return Err(ImageError::Unsupported),
+    }
+    Ok(())
+}
+
+fn decode_jpeg(reader: BufReader<File>) -> Result<SharedPixelBuffer<Rgb8Pixel>, ImageError> {
+    let mut decoder = jpeg_decoder::Decoder::new(reader);
+    decoder.set_max_decoding_buffer_size(DECODE_BUDGET);
+    decoder.read_info()?;
+    let info = decoder.info().ok_or(ImageError::Corrupt)?;
+    // the coefficients of progressive images are kept at full size, 2 bytes each
+    if info.coding_process == jpeg_decoder::CodingProcess::DctProgressive
+        && info.width as usize * info.height as usize * info.pixel_format.pixel_bytes() * 2
+            > DECODE_BUDGET
+    {
+        return Err(ImageError::TooLarge);
+    }
+    // the IDCT scales by 1/2, 1/4 or 1/8, to no less than asked
+    let (width, height) = decoder.scale(MAX_WIDTH as u16, MAX_HEIGHT as u16)?;
+    if width as usize * height as usize * info.pixel_format.pixel_bytes() > DECODE_BUDGET {
+        return Err(ImageError::TooLarge);
+    }
+    let pixels = decoder.decode()?;
+    let (width, height) = (width as u32, height as u32);
+    let mut scaler = Scaler::new(width, height);
+    let mut rgb = Vec::with_capacity(width as usize * 3);
+    let row_size = width as usize * info.pixel_format.pixel_bytes();
+    for row in pixels.chunks_exact(row_size).take(height as usize) {
+        rgb.clear();
+        match info.pixel_format {
+            jpeg_decoder::PixelFormat::RGB24 => rgb.extend_from_slice(row),
+            jpeg_decoder::PixelFormat::L8 => rgb.extend(row.iter().flat_map(|&value| [value; 3])),
+            // big endian
+            jpeg_decoder::PixelFormat::L16 => {
+                rgb.extend(row.chunks_exact(2).flat_map(|value| [value[0]; 3]))
+            }
+            // Adobe writes it inverted
+            jpeg_decoder::PixelFormat::CMYK32 => rgb.extend(row.chunks_exact(4).flat_map(|cmyk| {
+                [0, 1, 2].map(|channel| (cmyk[channel] as u16 * cmyk[3] as u16 / 255) as u8)
+            })),
+        }
+        scaler.push_row(&rgb);
+    }
+    Ok(scaler.finish())
+}
+
+const BMP_FILE_HEADER_SIZE: u64 = 14;
+const BMP_RGB: u32 = 0;
+const BMP_BITFIELDS: u32 = 3;
+
+/// A color channel of a BMP pixel, by its mask.
+#[derive(Debug, Clone, Copy)]
+struct Channel {
+    mask: u32,
+    shift: u32,
+}
+
+impl Channel {
+    fn new(mask: u32) -> Self {
+        Self {
+            mask,
+            shift: mask.trailing_zeros().min(31),
+        }
+    }
+
+    fn extract(self, pixel: u32) -> u8 {
+        let max = self.mask >> self.shift;
+        if max == 0 {
+            return 0;
+        }
+        ((((pixel & self.mask) >> self.shift) as u64 * 255) / max as u64) as u8
+    }
+}
+
+/// Uncompressed BMP, in 1, 4 and 8 bits with a palette, or 16, 24 and 32 bits.
+fn decode_bmp(mut reader: BufReader<File>) -> Result<SharedPixelBuffer<Rgb8Pixel>, ImageError> {
+    let mut header = [0u8; 54];
+    reader.read_exact(&mut header)?;
+    let u16_at = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+    let data_offset = u32_at(10) as u64;
+    let dib_size = u32_at(14);
+    // the OS/2 header is too old to bother
+    if dib_size < 40 {
+        return Err(ImageError::Unsupported);
+    }
+    let width = u32_at(18) as i32;
+    let height = u32_at(22) as i32;
+    let bits = u16_at(28);
+    let compression = u32_at(30);
+    let colors_used = u32_at(46);
+    if width <= 0 || height == 0 {
+        return Err(ImageError::Corrupt);
+    }
+    let top_down = height < 0;
+    let (width, height) = (width as u32, height.unsigned_abs());
+    if width > MAX_SOURCE_WIDTH {
+        return Err(ImageError::TooLarge);
+    }
+
+    let channels = match (bits, compression) {
+        (16, BMP_RGB) => Some([0x7c00, 0x03e0, 0x001f].map(Channel::new)),
+        (32, BMP_RGB) => Some([0x00ff_0000, 0x0000_ff00, 0x0000_00ff].map(Channel::new)),
+        (16 | 32, BMP_BITFIELDS) => {
+            // right after the basic header, part of it in the newer versions
+            let mut masks = [0u8; 12];
+            reader.seek(SeekFrom::Start(BMP_FILE_HEADER_SIZE + 40))?;
+            reader.read_exact(&mut masks)?;
+            Some([0, 4, 8].map(|offset| {
+                Channel::new(u32::from_le_bytes(
+                    masks[offset..offset + 4].try_into().unwrap(),
+                ))
+            }))
+        }
+        (1 | 4 | 8 | 24, BMP_RGB) => None,
+        _ => return Err(ImageError::Unsupported),
+    };
+    let palette = if bits <= 8 {
+        let count = match colors_used {
+            0 => 1 << bits,
+            count => count.min(1 << bits),
+        } as usize;
+        let mut entries = vec![0u8; count * 4];
+        reader.seek(SeekFrom::Start(BMP_FILE_HEADER_SIZE + dib_size as u64))?;
+        reader.read_exact(&mut entries)?;
+        // BGR0
+        entries
+            .chunks_exact(4)
+            .map(|entry| [entry[2], entry[1], entry[0]])
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let stride = (width as usize * bits as usize).div_ceil(32) * 4;
+    let mut row = vec![0u8; stride];
+    let mut rgb = Vec::with_capacity(width as usize * 3);
+    let mut scaler = Scaler::new(width, height);
+    for y in 0..height {
+        let stored_row = if top_down { y } else { height - 1 - y };
+        reader.seek(SeekFrom::Start(
+            data_offset + stored_row as u64 * stride as u64,
+        ))?;
+        reader.read_exact(&mut row)?;
+        rgb.clear();
+        match bits {
+            1 | 4 | 8 => {
+                let per_byte = 8 / bits as usize;
+                let mask = ((1u16 << bits) - 1) as u8;
+                for x in 0..width as usize {
+                    let byte = row[x / per_byte];
+                    let shift = 8 - bits as usize * (x % per_byte + 1);
+                    let index = ((byte >> shift) & mask) as usize;
+                    rgb.extend_from_slice(palette.get(index).unwrap_or(&[0; 3]));
+                }
+            }
+            24 => rgb.extend(
+                row.chunks_exact(3)
+                    .take(width as usize)
+                    .flat_map(|bgr| [bgr[2], bgr[1], bgr[0]]),
+            ),
+            _ => {
+                let channels = channels.ok_or(ImageError::Unsupported)?;
+                let bytes = bits as usize / 8;
+                rgb.extend(
+                    row.chunks_exact(bytes)
+                        .take(width as usize)
+                        .flat_map(|pixel| {
+                            let mut value = [0u8; 4];
+                            value[..bytes].copy_from_slice(pixel);
+                            let value = u32::from_le_bytes(value);
+                            channels.map(|channel| channel.extract(value))
+                        }),
+                );
+            }
+        }
+        scaler.push_row(&rgb);
+    }
+    Ok(scaler.finish())
+}
+
+/// The images of a folder, and the one shown.
+#[derive(Debug, Default)]
+pub struct ImageList {
+    paths: Vec<PathBuf>,
+    index: usize,
+    /// When the slideshow moves on, since start.
+    slideshow_next: Option<Duration>,
+}
+
+impl ImageList {
+    pub fn new(paths: Vec<PathBuf>, index: usize) -> Self {
+        Self {
+            index: index.min(paths.len().saturating_sub(1)),
+            paths,
+            slideshow_next: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<&Path> {
+        self.paths.get(self.index).map(PathBuf::as_path)
+    }
+
+    /// "3/12".
+    pub fn position(&self) -> String {
+        format!("{}/{}", self.index + 1, self.paths.len())
+    }
+
+    /// Wraps around at the end.
+    pub fn next(&mut self) {
+        if !self.paths.is_empty() {
+            self.index = (self.index + 1) % self.paths.len();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if !self.paths.is_empty() {
+            self.index = (self.index + self.paths.len() - 1) % self.paths.len();
+        }
+    }
+
+    pub fn is_slideshow(&self) -> bool {
+        self.slideshow_next.is_some()
+    }
+
+    /// `now` is the time since start.
+    pub fn set_slideshow(&mut self, enabled: bool, now: Duration) {
+        self.slideshow_next = enabled.then(|| now + SLIDESHOW_INTERVAL);
+    }
+
+    /// Returns true when it's time for the next image of the slideshow, and starts over.
+    pub fn take_due(&mut self, now: Duration) -> bool {
+        match self.slideshow_next {
+            Some(next) if now >= next => {
+                self.slideshow_next = Some(now + SLIDESHOW_INTERVAL);
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, SyncSender},
+    thread,
+};
+
+use slint::{Rgb8Pixel, SharedPixelBuffer};
+
+use crate::inputevent::InputEvent;
+
+use super::{decode, ImageError};
+
+/// The default pthread stack is too small for the decoders.
+const STACK_SIZE: usize = 16 * 1024;
+
+/// Simple task control commands.
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageControl {
+    Decode(PathBuf),
+    Terminate,
+}
+
+/// An image ready to be shown, or why it isn't.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedImage {
+    pub path: PathBuf,
+    pub result: Result<SharedPixelBuffer<Rgb8Pixel>, ImageError>,
+}
+
+/// Spawn the image decoding task and return a control handle.
+///
+/// Each image is delivered as [`InputEvent::Image`]. Requests queued while decoding are skipped
+/// but the latest, when flipping through a folder only the last one is shown anyway.
+#[inline]
+pub fn spawn_image_task(sender: SyncSender<InputEvent>) -> SyncSender<ImageControl> {
+    let (control_sender, receiver) = mpsc::sync_channel::<ImageControl>(4);
+    let _ = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            while let Ok(control) = receiver.recv() {
+                let control = receiver.try_iter().last().unwrap_or(control);
+                match control {
+                    ImageControl::Decode(path) => {
+                        let result = decode(&path);
+                        if sender
+                            .send(InputEvent::Image(DecodedImage { path, result }))
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    ImageControl::Terminate => return,
+                }
+            }
+        });
+    control_sender
+}
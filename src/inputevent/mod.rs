@@ -5,6 +5,19 @@ use slint::{
     LogicalPosition,
 };
 
+use crate::{
+    energylog::EnergyRecord,
+    images::DecodedImage,
+    motion::{
+        gesture::Gesture, logger::LoggerEvent, screen::ScreenOrientation, Bias, CalibrationError,
+        MotionData,
+    },
+    powermon::PowerSample,
+    rtc::RtcEvent,
+    textview::ConversionEvent,
+    thermal::ThermalReading,
+};
+
 pub mod tasks;
 
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
@@ -43,6 +56,19 @@ impl From<FtPoint> for Point {
 pub enum InputEvent {
     WindowEvent(WindowEvent),
     Pmu(AxpIrqReason),
+    PowerMonitor(PowerSample),
+    EnergyLog(Vec<EnergyRecord>),
+    Thermal(ThermalReading),
+    Rtc(RtcEvent),
+    Motion(MotionData),
+    MotionCalibration(Result<Bias, CalibrationError>),
+    Gesture(Gesture),
+    ScreenOrientation(ScreenOrientation),
+    /// New steps since the last event.
+    Steps(u32),
+    ImuLog(LoggerEvent),
+    Image(DecodedImage),
+    FontConversion(ConversionEvent),
 }
 
 impl From<Point> for WindowEvent {
@@ -1,4 +1,7 @@
-use std::sync::mpsc;
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    mpsc, Arc, Mutex,
+};
 use std::{thread, thread::JoinHandle};
 
 use axp2101::Axp2101;
@@ -9,7 +12,7 @@ use ft6336::Ft6336;
 use slint::platform::{PointerEventButton, WindowEvent};
 use slint::LogicalPosition;
 
-use crate::utils::block_for_interrupt;
+use crate::{motion::screen::ScreenOrientation, rtc::Pcf8563Registers, utils::block_for_interrupt};
 
 use super::InputEvent;
 
@@ -17,12 +20,62 @@ const TOUCH_BTN_LEFT: slint::platform::Key = slint::platform::Key::F1;
 const TOUCH_BTN_CENTER: slint::platform::Key = slint::platform::Key::F2;
 const TOUCH_BTN_RIGHT: slint::platform::Key = slint::platform::Key::F3;
 const TOUCH_POINT_COUNT_MAXIMUM: usize = 2;
+/// Samples of a touch button chord, 50ms apart.
+const TOUCH_CHORD_CHECKS: usize = 20;
+
+/// Check if the key text comes from one of the touch buttons.
+pub fn is_touch_button_key(text: &str) -> bool {
+    [TOUCH_BTN_LEFT, TOUCH_BTN_CENTER, TOUCH_BTN_RIGHT]
+        .into_iter()
+        .any(|key| slint::SharedString::from(key).as_str() == text)
+}
+
+/// The key of the touch button at `x`, 320px sliced to 3 buttons.
+fn touch_button_key(x: u16, orientation: ScreenOrientation) -> slint::platform::Key {
+    let (first, last) = if orientation.buttons_reversed() {
+        (TOUCH_BTN_RIGHT, TOUCH_BTN_LEFT)
+    } else {
+        (TOUCH_BTN_LEFT, TOUCH_BTN_RIGHT)
+    };
+    if (0..107).contains(&x) {
+        first
+    } else if (107..214).contains(&x) {
+        TOUCH_BTN_CENTER
+    } else {
+        last
+    }
+}
+
+/// Whether the left and right touch buttons are both held, checked for a while so brushing them
+/// doesn't count. The orientation doesn't matter, they swap places when it's reversed.
+pub fn outer_touch_buttons_held<I2C: I2c>(touch_panel: &mut Ft6336<I2C>) -> bool {
+    (0..TOUCH_CHORD_CHECKS).all(|_| {
+        let held = match touch_panel.touch_points_iter() {
+            Ok(points) => {
+                let keys: Vec<slint::SharedString> = points
+                    .filter(|point| point.y >= 240)
+                    .map(|point| touch_button_key(point.x, ScreenOrientation::Landscape).into())
+                    .collect();
+                [TOUCH_BTN_LEFT, TOUCH_BTN_RIGHT]
+                    .into_iter()
+                    .all(|key| keys.contains(&key.into()))
+            }
+            Err(e) => {
+                log::warn!("Failed to read touch panel: {:?}", e);
+                false
+            }
+        };
+        FreeRtosDelay::delay_ms(50);
+        held
+    })
+}
 
 /// The thread for touch events processing.
 #[inline]
 pub fn touch_event_task<I2C, PIN>(
     mut touch_panel: Ft6336<I2C>,
     mut touch_interrupt: PinDriver<'static, PIN, Input>,
+    screen_orientation: Arc<AtomicU8>,
     sender: mpsc::SyncSender<InputEvent>,
 ) -> JoinHandle<()>
 where
@@ -41,6 +94,10 @@ where
     Due to a hardware limitation, the touch panel cannot distinguish touches with equal
     Y-axis cordinates.
     The touch controller cannot handle multi-touch accurately anyway.
+
+    When the screen is rotated, the visible space is mapped to the window by
+    `screen_orientation`. It's only read when a touch begins, so a drag always
+    ends in the same coordinates it started with.
     */
 
     thread::spawn(move || {
@@ -48,6 +105,7 @@ where
         touch_panel.interrupt_by_pulse().unwrap();
         loop {
             block_for_interrupt(&mut touch_interrupt, InterruptType::NegEdge);
+            let orientation = ScreenOrientation::from(screen_orientation.load(Ordering::Relaxed));
 
             // when interrupt triggered, enter polling mode, until all released.
             // maximum 2 touches, and is also ensured by touch driver
@@ -86,7 +144,8 @@ where
                         }
 
                         // update pointer location
-                        let position = LogicalPosition::new(point.x as f32, point.y as f32);
+                        let (x, y) = orientation.map_point(point.x, point.y);
+                        let position = LogicalPosition::new(x as f32, y as f32);
                         // press/move based on last status
                         let new_pointer_event = if last_status[point.index as usize] {
                             // already activated
@@ -115,19 +174,8 @@ where
                             last_position[point.index as usize] = (point.x, point.y);
 
                             // touch buttons, map to keys rather than pointer events
-                            // 320px sliced to 3 buttons
-                            let new_key_event = if (0..107).contains(&point.x) {
-                                WindowEvent::KeyPressed {
-                                    text: TOUCH_BTN_LEFT.into(),
-                                }
-                            } else if (107..214).contains(&point.x) {
-                                WindowEvent::KeyPressed {
-                                    text: TOUCH_BTN_CENTER.into(),
-                                }
-                            } else {
-                                WindowEvent::KeyPressed {
-                                    text: TOUCH_BTN_RIGHT.into(),
-                                }
+                            let new_key_event = WindowEvent::KeyPressed {
+                                text: touch_button_key(point.x, orientation).into(),
                             };
 
                             sender.send(InputEvent::WindowEvent(new_key_event)).unwrap();
@@ -147,10 +195,8 @@ where
                     if pointer_index == Some(i as u8) {
                         // release pointer event
                         pointer_index = None; // also clear pointer_index, required if N > 2
-                        let position = LogicalPosition::new(
-                            last_position[i].0 as f32,
-                            last_position[i].1 as f32,
-                        );
+                        let (x, y) = orientation.map_point(last_position[i].0, last_position[i].1);
+                        let position = LogicalPosition::new(x as f32, y as f32);
                         let release_event = WindowEvent::PointerReleased {
                             position,
                             button: PointerEventButton::Left,
@@ -162,18 +208,8 @@ where
                             .unwrap();
                     } else {
                         // release key
-                        let release_event = if (0..107).contains(&last_position[i].0) {
-                            WindowEvent::KeyReleased {
-                                text: TOUCH_BTN_LEFT.into(),
-                            }
-                        } else if (107..214).contains(&last_position[i].0) {
-                            WindowEvent::KeyReleased {
-                                text: TOUCH_BTN_CENTER.into(),
-                            }
-                        } else {
-                            WindowEvent::KeyReleased {
-                                text: TOUCH_BTN_RIGHT.into(),
-                            }
+                        let release_event = WindowEvent::KeyReleased {
+                            text: touch_button_key(last_position[i].0, orientation).into(),
                         };
                         sender.send(InputEvent::WindowEvent(release_event)).unwrap();
                     }
@@ -195,9 +231,12 @@ where
 }
 
 /// The thread for PMU events processing.
+///
+/// The RTC interrupt shares the same line, so its events are collected here as well.
 #[inline]
 pub fn pmu_event_task<I2C, PIN>(
     mut pmu: Axp2101<I2C>,
+    rtc: &'static Mutex<Pcf8563Registers<I2C>>,
     mut pmu_interrupt: PinDriver<'static, PIN, Input>,
     sender: mpsc::SyncSender<InputEvent>,
 ) -> JoinHandle<()>
@@ -219,6 +258,15 @@ where
             for event in events.into_iter() {
                 let _ = sender.send(InputEvent::Pmu(event));
             }
+            let events = rtc.lock().unwrap().take_events();
+            match events {
+                Ok(events) => {
+                    for event in events {
+                        let _ = sender.send(InputEvent::Rtc(event));
+                    }
+                }
+                Err(e) => log::warn!("Failed to read RTC interrupts: {:?}", e),
+            }
             FreeRtosDelay::delay_ms(50);
         }
     })
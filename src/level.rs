@@ -0,0 +1,35 @@
+//! Bubble level and inclinometer.
+//!
+//! The readings are worked out in [`gadget_core::level`]. The zero point is measured on a
+//! reference surface and kept in NVS here, so a slightly tilted table or case can be compensated.
+
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::EspError,
+};
+
+pub use gadget_core::level::{bubble_position, is_level, Inclinometer, MAX_ZERO_DEGREES};
+
+use crate::motion::Orientation;
+
+/// NVS namespace of the zero point.
+pub const NVS_NAMESPACE: &str = "level";
+const NVS_KEY_ZERO: &str = "zero";
+
+pub fn load_zero(nvs: &EspNvs<NvsDefault>) -> Orientation {
+    let mut buffer = [0u8; 8];
+    match nvs.get_blob(NVS_KEY_ZERO, &mut buffer) {
+        Ok(Some(data)) if data.len() == 8 => Orientation {
+            pitch: f32::from_le_bytes(data[0..4].try_into().unwrap()),
+            roll: f32::from_le_bytes(data[4..8].try_into().unwrap()),
+        },
+        _ => Orientation::default(),
+    }
+}
+
+pub fn store_zero(nvs: &mut EspNvs<NvsDefault>, zero: Orientation) -> Result<(), EspError> {
+    let mut data = [0u8; 8];
+    data[0..4].copy_from_slice(&zero.pitch.to_le_bytes());
+    data[4..8].copy_from_slice(&zero.roll.to_le_bytes());
+    nvs.set_blob(NVS_KEY_ZERO, &data)
+}
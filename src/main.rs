@@ -1,14 +1,25 @@
 use esp_idf_svc::hal::{
     delay::{Ets as EtsDelay, FreeRtos as FreeRtosDelay},
-    gpio::{PinDriver, Pull},
+    gpio::{AnyIOPin, PinDriver, Pull},
     i2c,
+    i2s::{
+        config::{DataBitWidth, StdConfig},
+        I2sDriver,
+    },
     peripherals::Peripherals,
     spi,
     units::FromValueType as _,
 };
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
 use std::{
     boxed::Box,
-    sync::{mpsc, Mutex},
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 
 use display_interface_spi::SPIInterface;
@@ -19,33 +30,80 @@ use mipidsi::{
     Builder as MipiBuilder,
 };
 
-use axp2101::{
-    irq::IrqReason, Aldo2, Axp2101, Bldo1, ChargeLedControl, ChargeLedPattern, Dcdc1,
-    Regulator as _, RegulatorPin,
-};
+use axp2101::{irq::IrqReason, Axp2101, ChargeLedControl, ChargeLedPattern};
 use ft6336::Ft6336;
-use ina3221::Ina3221;
-use mpu6886::Mpu6886;
-use pcf8563::Pcf8563;
 
+mod bootlog;
+mod clock;
 mod platform;
 mod utils;
 // TODOs
+mod activity;
+mod alarms;
 mod applejuice;
+mod energylog;
+mod files;
+mod haptics;
+mod images;
 mod inputevent;
-
+mod level;
+mod motion;
+mod pages;
+mod pmu;
+mod powermon;
+mod ringer;
+mod rtc;
+mod settings;
+mod stopwatch;
+mod storage;
+mod textview;
+mod thermal;
+
+use alarms::AlarmScheduler;
 use applejuice::{spawn_applejuice_task, JuicyTaskControl};
+use clock::DateTime;
+use energylog::{spawn_energylog_task, EnergyLogControl};
+use haptics::{spawn_haptics_task, HapticPattern};
+use images::spawn_image_task;
 use inputevent::{
-    tasks::{pmu_event_task, touch_event_task},
+    tasks::{is_touch_button_key, outer_touch_buttons_held, pmu_event_task, touch_event_task},
     InputEvent,
 };
 
-use platform::{DisplayWrapper, M5Core2V11GadgetPlatform};
-use slint::platform::software_renderer::MinimalSoftwareWindow;
+use motion::{
+    gesture::{self, Gesture},
+    gesture_subscriber,
+    screen::{self, ScreenOrientation},
+    screen_orientation_subscriber, spawn_motion_task, step_subscriber, MotionConfig, MotionControl,
+    Mpu6886Sampler,
+};
+use platform::{duration_since_start, DisplayWrapper, M5Core2V11GadgetPlatform};
+use pmu::{
+    battery::{self, read_battery_status, ChargeState},
+    bootreason::read_power_sources,
+    charger::{self, ChargerConfig},
+    led::{LedController, LedState},
+    rails::{Rail, RailManager, RailPin},
+    Axp2101Registers,
+};
+use powermon::{spawn_powermon_task, Ina3221Sampler};
+use ringer::spawn_ringer_task;
+use rtc::Pcf8563Registers;
+use settings::TRANSMISSION_POWER_MAX;
+use slint::platform::{
+    software_renderer::{MinimalSoftwareWindow, RenderingRotation},
+    WindowEvent,
+};
+use storage::SdCard;
+use thermal::{spawn_thermal_task, ThermalConfig, ThermalLevel};
 
 slint::include_modules!();
 
 const INPUT_BUFFER_SIZE: usize = 32;
+/// Transmit power level limit when the system is warm, 0dBm.
+const THERMAL_POWER_LIMIT: u8 = 4;
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const LOW_BATTERY_PERCENT: u8 = 15;
 
 #[allow(unused_mut, unused_variables)]
 fn main() {
@@ -55,6 +113,7 @@ fn main() {
     log::info!("Initializing peripherals...");
 
     let peripherals = Peripherals::take().unwrap();
+    let nvs_partition = EspDefaultNvsPartition::take().unwrap();
 
     // Initialize I2C and related devices
     let i2c_sda = peripherals.pins.gpio21;
@@ -62,35 +121,66 @@ fn main() {
     let i2c_config = i2c::I2cConfig::default().baudrate(400u32.kHz().into());
     let i2c_bus = i2c::I2cDriver::new(peripherals.i2c0, i2c_sda, i2c_scl, &i2c_config).unwrap();
     let mutex_i2c_bus_boxed = Box::new(Mutex::new(i2c_bus));
-    let mutex_i2c_bus = Box::leak(mutex_i2c_bus_boxed);
+    let mutex_i2c_bus: &'static Mutex<_> = Box::leak(mutex_i2c_bus_boxed);
 
     // all built-in I2C devices
     let mut pmu = Axp2101::new(SharedI2cBus::new(mutex_i2c_bus));
-    let mut rtc = Pcf8563::new(SharedI2cBus::new(mutex_i2c_bus));
+    let mut rtc = Pcf8563Registers::new(SharedI2cBus::new(mutex_i2c_bus));
     let mut touch_panel = Ft6336::new(SharedI2cBus::new(mutex_i2c_bus));
-    let mut inertial = Mpu6886::new(SharedI2cBus::new(mutex_i2c_bus));
-    let mut voltmon = Ina3221::new(SharedI2cBus::new(mutex_i2c_bus));
+    let inertial = Mpu6886Sampler::new(SharedI2cBus::new(mutex_i2c_bus));
+    let voltmon = Ina3221Sampler::new(SharedI2cBus::new(mutex_i2c_bus));
 
     // check axp status and turn on 3V3 bus
-    {
+    let mut charger_config = {
         match pmu.chip_id() {
             Ok(chip_id) => log::info!("AXP2101 found, ID {}", chip_id),
             Err(e) => panic!("AXP2101 initialization failure! {:?}", e),
         };
+        let mut rails = RailManager::new(SharedI2cBus::new(mutex_i2c_bus));
         // 3.3V dcdc1 for esp32
-        let mut dcdc1 = Dcdc1::new(SharedI2cBus::new(mutex_i2c_bus));
-        dcdc1.set_voltage(3300).unwrap();
-        dcdc1.enable().unwrap();
+        rails.request(Rail::Dcdc1, 3300).unwrap();
         // 3.3v dcdc3 for esp32 and peripherals
-        let mut dcdc3 = Dcdc1::new(SharedI2cBus::new(mutex_i2c_bus));
-        dcdc3.set_voltage(3300).unwrap();
-        dcdc3.enable().unwrap();
-        // turn on led
+        rails.request(Rail::Dcdc3, 3300).unwrap();
+        // logic high level for LCD reset
+        rails.set_voltage(Rail::Aldo2, 3300).unwrap();
+        // turn on led, the pattern is managed by LedController later
         pmu.set_chgled_control(ChargeLedControl::Manual).unwrap();
         pmu.set_chgled_en(true).unwrap();
         pmu.set_chgled_manually(ChargeLedPattern::Low).unwrap();
+        // charger configuration, from NVS or the defaults
+        let charger_nvs = EspNvs::new(nvs_partition.clone(), charger::NVS_NAMESPACE, true).unwrap();
+        let charger_config = ChargerConfig::load(&charger_nvs);
+        let mut pmu_registers = Axp2101Registers::new(SharedI2cBus::new(mutex_i2c_bus));
+        charger_config.apply(&mut pmu_registers).unwrap();
+        log::info!("Charger configured: {:?}", charger_config);
+        // battery voltage and temperature measurements
+        battery::enable_adc(&mut pmu_registers).unwrap();
+        // docked mode
+        battery::enable_vbus_interrupts(&mut pmu_registers).unwrap();
+        charger_config
     };
 
+    // record why we are here
+    let power_sources =
+        read_power_sources(&mut Axp2101Registers::new(SharedI2cBus::new(mutex_i2c_bus)))
+            .unwrap_or_default();
+    // system time from RTC, before anything timestamped
+    let clock_valid = rtc::sync_system_time(&mut rtc).unwrap_or_else(|e| {
+        log::error!("Failed to read RTC: {:?}", e);
+        false
+    });
+    let boot_records = EspNvs::new(nvs_partition.clone(), bootlog::NVS_NAMESPACE, true)
+        .and_then(|mut nvs| {
+            bootlog::append_boot_record(&mut nvs, utils::now_timestamp(), power_sources)
+        })
+        .unwrap_or_else(|e| {
+            log::error!("Failed to save boot record: {:?}", e);
+            Vec::new()
+        });
+    if let Some(record) = boot_records.last() {
+        log::info!("Boot record: {}", record);
+    }
+
     // Initialize SPI, allocated at runtime
     let spi_bus = {
         let spi_sdo = peripherals.pins.gpio23;
@@ -102,16 +192,23 @@ fn main() {
                 spi_sck,
                 spi_sdo,
                 Some(spi_sdi),
-                &spi::SpiDriverConfig::new(),
+                // the SD card requires DMA
+                &spi::SpiDriverConfig::new().dma(spi::Dma::Auto(4096)),
             )
             .unwrap(),
         );
         Box::leak(spi_bus_boxed)
     };
 
-    // TODO: SD card on the SPI bus
-    // TODO: SD mount/unmount
-    // let tfcard_cs = peripherals.pins.gpio4;
+    // TF card on the SPI bus, its CS must be driven before the LCD starts talking
+    let sd_card = Rc::new(RefCell::new(SdCard::new(
+        spi_bus,
+        peripherals.pins.gpio4.into(),
+    )));
+    let sd_card_error = sd_card.borrow_mut().mount().err();
+    if let Some(e) = sd_card_error {
+        log::warn!("SD card not available: {:?}", e);
+    }
 
     // LCD on the SPI bus
     let mut display = {
@@ -122,8 +219,10 @@ fn main() {
         let lcd_cs = peripherals.pins.gpio5;
         let display_spi_bus =
             spi::SpiDeviceDriver::new(spi_bus, Some(lcd_cs), &display_spi_config).unwrap();
-        let aldo2 = Aldo2::new(SharedI2cBus::new(mutex_i2c_bus));
-        let lcd_rst = RegulatorPin::new(aldo2);
+        let lcd_rst = RailPin::new(
+            RailManager::new(SharedI2cBus::new(mutex_i2c_bus)),
+            Rail::Aldo2,
+        );
         let lcd_dc = PinDriver::output(peripherals.pins.gpio15).unwrap();
         let display_interface = SPIInterface::new(display_spi_bus, lcd_dc);
         MipiBuilder::new(ILI9342CRgb565, display_interface)
@@ -134,13 +233,42 @@ fn main() {
             .init(&mut EtsDelay)
             .unwrap()
     };
-    let mut lcd_backlight = Bldo1::new(SharedI2cBus::new(mutex_i2c_bus));
+    let mut rails = RailManager::new(SharedI2cBus::new(mutex_i2c_bus));
+
+    // holding both outer touch buttons at boot brings back the defaults of everything
+    let factory_reset = outer_touch_buttons_held(&mut touch_panel);
+    if factory_reset {
+        log::warn!("Factory reset requested at boot");
+        if let Err(e) = settings::wipe(&pages::settings::FACTORY_RESET_NAMESPACES) {
+            log::error!("Failed to wipe stored settings: {:?}", e);
+        }
+        // the charger is already configured, the rest loads after this
+        charger_config = ChargerConfig::default();
+        if let Err(e) =
+            charger_config.apply(&mut Axp2101Registers::new(SharedI2cBus::new(mutex_i2c_bus)))
+        {
+            log::error!("Failed to apply default charger config: {:?}", e);
+        }
+    }
+
+    // settings of the main page, saved by the super loop a while after they change
+    let settings = EspNvs::new(nvs_partition.clone(), settings::NVS_NAMESPACE, true)
+        .map(|mut nvs| settings::load(&mut nvs))
+        .unwrap_or_default();
+    log::info!("Settings: {:?}", settings);
 
     // display prefilling, backlight on
     // TODO: handle error properly
     // display.clear(Rgb565::BLACK).unwrap();
-    lcd_backlight.set_voltage(2800).unwrap();
-    lcd_backlight.enable().unwrap();
+    rails
+        .request(Rail::Bldo1, settings.backlight_voltage())
+        .unwrap();
+
+    log::info!("Power rails:");
+    match rails.dump() {
+        Ok(states) => states.iter().for_each(|state| log::info!("  {}", state)),
+        Err(e) => log::warn!("Failed to read power rails: {:?}", e),
+    }
 
     let psram_initialized: bool = unsafe { esp_idf_svc::sys::esp_psram_is_initialized() };
     log::info!("PSRAM initialized: {}", psram_initialized);
@@ -153,20 +281,129 @@ fn main() {
     // communication channel / event queue
     let (inputevent_tx, inputevent_rx) = mpsc::sync_channel::<InputEvent>(INPUT_BUFFER_SIZE);
     let inputevent_tx_pmu = inputevent_tx.clone();
+    let inputevent_tx_powermon = inputevent_tx.clone();
+    let inputevent_tx_energylog = inputevent_tx.clone();
+    let inputevent_tx_thermal = inputevent_tx.clone();
+    let inputevent_tx_motion = inputevent_tx.clone();
+    let inputevent_tx_gesture = inputevent_tx.clone();
+    let inputevent_tx_screen = inputevent_tx.clone();
+    let inputevent_tx_steps = inputevent_tx.clone();
+    let inputevent_tx_images = inputevent_tx.clone();
+    let inputevent_tx_font = inputevent_tx.clone();
     let inputevent_tx_touch = inputevent_tx;
 
+    // the PMU task clears the RTC interrupt flags while the main thread arms them, each
+    // read-modify-write of the control register is done under this lock
+    let shared_rtc: &'static Mutex<_> = Box::leak(Box::new(Mutex::new(rtc)));
+
     // thread for reading PMU events
     let mut pmu_interrupt = PinDriver::input(peripherals.pins.gpio19).unwrap();
     pmu_interrupt.set_pull(Pull::Up).unwrap();
-    let _t_input_pmu = pmu_event_task(pmu, pmu_interrupt, inputevent_tx_pmu);
+    let _t_input_pmu = pmu_event_task(pmu, shared_rtc, pmu_interrupt, inputevent_tx_pmu);
 
     // thread for reading touch events
     let touch_interrupt = PinDriver::input(peripherals.pins.gpio39).unwrap();
-    let _t_input_touch = touch_event_task(touch_panel, touch_interrupt, inputevent_tx_touch);
+    // the orientation lock is kept across reboots, it's applied by the super loop
+    let screen_lock = Rc::new(Cell::new(
+        EspNvs::new(nvs_partition.clone(), screen::NVS_NAMESPACE, false)
+            .ok()
+            .and_then(|nvs| screen::load_lock(&nvs)),
+    ));
+    let screen_orientation = Arc::new(AtomicU8::new(ScreenOrientation::Landscape as u8));
+    let _t_input_touch = touch_event_task(
+        touch_panel,
+        touch_interrupt,
+        screen_orientation.clone(),
+        inputevent_tx_touch,
+    );
+
+    log::info!("Initializing power monitor...");
+    let powermon_control = spawn_powermon_task(voltmon, inputevent_tx_powermon);
+
+    log::info!("Initializing energy logger...");
+    let energylog_interval = EspNvs::new(nvs_partition.clone(), energylog::NVS_NAMESPACE, false)
+        .map(|nvs| energylog::load_interval(&nvs))
+        .unwrap_or(energylog::DEFAULT_INTERVAL);
+    let energylog_control = spawn_energylog_task(
+        Axp2101Registers::new(SharedI2cBus::new(mutex_i2c_bus)),
+        nvs_partition.clone(),
+        inputevent_tx_energylog,
+    );
 
     log::info!("Initializing (evil) apple juice...");
     FreeRtosDelay::delay_ms(10);
     let juicy_control = spawn_applejuice_task();
+    juicy_control
+        .send(JuicyTaskControl::SetPower(settings.transmission_power))
+        .unwrap();
+
+    log::info!("Initializing thermal monitor...");
+    let thermal_config = EspNvs::new(nvs_partition.clone(), thermal::NVS_NAMESPACE, false)
+        .map(|nvs| ThermalConfig::load(&nvs))
+        .unwrap_or_default();
+    let thermal_control = spawn_thermal_task(
+        Axp2101Registers::new(SharedI2cBus::new(mutex_i2c_bus)),
+        thermal_config,
+        inputevent_tx_thermal,
+    );
+
+    log::info!("Initializing motion sensor...");
+    let motion_control = spawn_motion_task(
+        inertial,
+        // the orientation filter gets every sample, the detectors an average of two
+        MotionConfig {
+            sample_rate: 100,
+            ..Default::default()
+        },
+        nvs_partition.clone(),
+        inputevent_tx_motion,
+    );
+    let gesture_config = Arc::new(Mutex::new(
+        EspNvs::new(nvs_partition.clone(), gesture::NVS_NAMESPACE, false)
+            .map(|nvs| gesture::load_config(&nvs))
+            .unwrap_or_default(),
+    ));
+    motion_control
+        .send(MotionControl::Subscribe(gesture_subscriber(
+            gesture_config.clone(),
+            inputevent_tx_gesture,
+        )))
+        .unwrap();
+    motion_control
+        .send(MotionControl::Subscribe(screen_orientation_subscriber(
+            inputevent_tx_screen,
+        )))
+        .unwrap();
+    motion_control
+        .send(MotionControl::Subscribe(step_subscriber(
+            inputevent_tx_steps,
+        )))
+        .unwrap();
+
+    log::info!("Initializing haptics...");
+    let haptics = spawn_haptics_task(RailManager::new(SharedI2cBus::new(mutex_i2c_bus)));
+
+    log::info!("Initializing speaker...");
+    let ringer = {
+        let config = StdConfig::philips(ringer::SAMPLE_RATE, DataBitWidth::Bits16);
+        let speaker = I2sDriver::new_std_tx(
+            peripherals.i2s0,
+            &config,
+            peripherals.pins.gpio12,
+            peripherals.pins.gpio2,
+            Option::<AnyIOPin>::None,
+            peripherals.pins.gpio0,
+        )
+        .unwrap();
+        spawn_ringer_task(
+            speaker,
+            RailManager::new(SharedI2cBus::new(mutex_i2c_bus)),
+            haptics.clone(),
+        )
+    };
+
+    log::info!("Initializing image decoder...");
+    let image_decoder = spawn_image_task(inputevent_tx_images);
 
     log::info!("Initializing slint...");
 
@@ -180,40 +417,126 @@ fn main() {
     .unwrap();
 
     // prepare buffer and configure root window size
-    let mut line_buffer = [slint::platform::software_renderer::Rgb565Pixel(0); 320];
-    window.set_size(slint::PhysicalSize::new(320, 240));
+    // the buffer is always a line of the panel, the window is turned with the screen
+    let mut line_buffer =
+        [slint::platform::software_renderer::Rgb565Pixel(0); screen::PANEL_WIDTH as usize];
+    let mut rendered_orientation = ScreenOrientation::Landscape;
+    window.set_size(slint::PhysicalSize::new(
+        screen::PANEL_WIDTH as u32,
+        screen::PANEL_HEIGHT as u32,
+    ));
 
     // UI configuration
     // This is merely an app view, different from the window.
     let app_ui = GadgetMainWindow::new().unwrap();
-    app_ui.on_shutdown(|| {
+    pages::bind(&app_ui, &haptics);
+    let main_page = pages::juice::bind(
+        &app_ui,
+        settings,
+        mutex_i2c_bus,
+        &juicy_control,
+        &nvs_partition,
+    );
+    pages::charger::bind(
+        &app_ui,
+        &charger_config,
+        Axp2101Registers::new(SharedI2cBus::new(mutex_i2c_bus)),
+        &nvs_partition,
+        &haptics,
+    );
+    let mut power_monitor = pages::power_monitor::bind(&app_ui);
+    let energy_log = pages::energy_log::bind(
+        &app_ui,
+        energylog_interval,
+        &energylog_control,
+        &sd_card,
+        &haptics,
+    );
+    pages::thermal::bind(&app_ui, &thermal_config, thermal_control, &nvs_partition);
+    pages::clock::bind(&app_ui, clock_valid, shared_rtc, &haptics);
+    let alarms = pages::alarms::bind(
+        &app_ui,
+        AlarmScheduler::new(
+            EspNvs::new(nvs_partition.clone(), alarms::NVS_NAMESPACE, true)
+                .map(|nvs| alarms::load_alarms(&nvs))
+                .unwrap_or_default(),
+        ),
+        shared_rtc,
+        &nvs_partition,
+        &ringer,
+        &haptics,
+    );
+    let stopwatch = pages::stopwatch::bind(&app_ui);
+    pages::motion::bind(
+        &app_ui,
+        &motion_control,
+        &screen_lock,
+        &screen_orientation,
+        &nvs_partition,
+    );
+    pages::gesture::bind(&app_ui, &gesture_config, &nvs_partition);
+    let level = Rc::new(pages::level::bind(&app_ui, &nvs_partition, &haptics));
+    // step totals, saved now and then by the super loop, and before power off
+    let activity = Rc::new(pages::activity::bind(&app_ui, clock_valid, &nvs_partition));
+    pages::logger::bind(&app_ui, &motion_control, &sd_card, &nvs_partition, &haptics);
+    pages::storage::bind(&app_ui, &sd_card, sd_card_error, &haptics);
+    let files = pages::files::bind(
+        &app_ui,
+        &sd_card,
+        image_decoder,
+        inputevent_tx_font,
+        &nvs_partition,
+        &haptics,
+    );
+    pages::settings::bind(
+        &app_ui,
+        factory_reset,
+        pages::settings::Configs {
+            main: main_page.clone(),
+            gesture: gesture_config.clone(),
+            screen_lock: screen_lock.clone(),
+            level: level.clone(),
+            alarms: alarms.clone(),
+        },
+        &sd_card,
+        &nvs_partition,
+        &haptics,
+    );
+    pages::diagnostics::bind(&app_ui, &boot_records, mutex_i2c_bus);
+
+    let shutdown_main_page = main_page.clone();
+    let shutdown_activity = activity.clone();
+    let shutdown_files = files.clone();
+    app_ui.on_shutdown(move || {
+        shutdown_activity.save_pending();
+        shutdown_files.save_pending();
+        shutdown_main_page.save_pending();
         Axp2101::new(SharedI2cBus::new(mutex_i2c_bus))
             .power_off()
             .unwrap();
     });
-    app_ui.on_update_brightness(|brightness| {
-        let level = (brightness as u16) % 5;
-        let voltage = 2600 + level * 100;
-        Bldo1::new(SharedI2cBus::new(mutex_i2c_bus))
-            .set_voltage(voltage)
-            .unwrap();
-    });
-    let juicy_enable = juicy_control.clone();
-    let juicy_disable = juicy_control.clone();
-    app_ui.on_enable_jammer(move || {
-        juicy_enable.send(JuicyTaskControl::Start).unwrap();
-    });
-    app_ui.on_disable_jammer(move || {
-        juicy_disable.send(JuicyTaskControl::Stop).unwrap();
-    });
-    app_ui.on_update_transmission_power(move |value| {
-        juicy_control
-            .send(JuicyTaskControl::SetPower(value as u8))
-            .unwrap();
-    });
 
     // some state variables
     let mut lock_screen = false;
+    let mut thermal_level = ThermalLevel::Normal;
+    let mut last_status_poll: Option<Instant> = None;
+    let mut led = LedController::new(Axp2101Registers::new(SharedI2cBus::new(mutex_i2c_bus)));
+    let mut status_registers = Axp2101Registers::new(SharedI2cBus::new(mutex_i2c_bus));
+    let dock = app_ui.global::<Dock>();
+    let clock_ui = app_ui.global::<Clock>();
+    let alarms_ui = app_ui.global::<Alarms>();
+    let mut clock_timestamp = 0u32;
+    let mut wake_up = false;
+    // the screen was locked by putting the device face down
+    let mut locked_by_flip = false;
+    // the orientation reported by the motion sensor, and whether a pointer is down
+    let mut detected_orientation = rendered_orientation;
+    let mut pointer_pressed = false;
+    let mut shown_page = app_ui.get_current_page();
+    // docked mode follows USB power, the initial state is read once, then driven by PMU events
+    let mut dock_request = read_battery_status(&mut status_registers)
+        .ok()
+        .map(|status| status.vbus_present);
 
     // The event loop(super loop)
     log::info!("Starting super loop...");
@@ -224,6 +547,21 @@ fn main() {
             match event {
                 InputEvent::WindowEvent(event) => {
                     if !lock_screen {
+                        if let WindowEvent::KeyPressed { text, .. } = &event {
+                            if is_touch_button_key(text) {
+                                // any touch button silences the ringer
+                                if alarms_ui.get_ringing() {
+                                    alarms_ui.invoke_stop_ringing();
+                                    continue;
+                                }
+                                let _ = haptics.try_send(HapticPattern::Tap);
+                            }
+                        }
+                        match event {
+                            WindowEvent::PointerPressed { .. } => pointer_pressed = true,
+                            WindowEvent::PointerReleased { .. } => pointer_pressed = false,
+                            _ => {}
+                        }
                         window.dispatch_event(event);
                     }
                 }
@@ -232,18 +570,15 @@ fn main() {
                     match event {
                         IrqReason::PowerKeyEventShort => {
                             lock_screen = !lock_screen;
+                            locked_by_flip = false;
                             if !lock_screen {
-                                lcd_backlight.enable().unwrap();
+                                rails.enable(Rail::Bldo1).unwrap();
                             } else {
-                                lcd_backlight.disable().unwrap();
+                                rails.disable(Rail::Bldo1).unwrap();
                             }
                         }
-                        IrqReason::BatteryPercentWarnLevel2 => {
-                            // low power alert
-                            Axp2101::new(SharedI2cBus::new(mutex_i2c_bus))
-                                .set_chgled_manually(ChargeLedPattern::OneHertz)
-                                .unwrap();
-                        }
+                        IrqReason::VbusInsert => dock_request = Some(true),
+                        IrqReason::VbusRemove => dock_request = Some(false),
                         IrqReason::BatteryPercentWarnLevel1 => {
                             // shutdown
                             Axp2101::new(SharedI2cBus::new(mutex_i2c_bus))
@@ -253,10 +588,202 @@ fn main() {
                         _ => {}
                     };
                 }
+                InputEvent::PowerMonitor(sample) => {
+                    let _ = energylog_control.try_send(EnergyLogControl::PowerSample(sample));
+                    power_monitor.show(&sample);
+                }
+                InputEvent::Thermal(reading) => {
+                    pages::thermal::show_reading(&app_ui, &reading);
+                    if reading.level != thermal_level {
+                        let power_limit = if reading.level >= ThermalLevel::Warm {
+                            THERMAL_POWER_LIMIT
+                        } else {
+                            TRANSMISSION_POWER_MAX
+                        };
+                        let _ = juicy_control.send(JuicyTaskControl::LimitPower(power_limit));
+                        main_page.set_dimmed(reading.level >= ThermalLevel::Hot);
+                        thermal_level = reading.level;
+                        led.set(LedState::Error, thermal_level == ThermalLevel::Critical);
+                    }
+                    // keep the radio off, even if started again by user
+                    if thermal_level == ThermalLevel::Critical && app_ui.get_jammer_running() {
+                        let _ = juicy_control.send(JuicyTaskControl::Stop);
+                        app_ui.set_jammer_running(false);
+                        let _ = haptics.try_send(HapticPattern::Error);
+                    }
+                }
+                InputEvent::EnergyLog(records) => {
+                    energy_log.show(records);
+                }
+                InputEvent::Motion(data) => {
+                    pages::motion::show_data(&app_ui, &data);
+                    level.update(data.orientation);
+                }
+                InputEvent::MotionCalibration(result) => {
+                    pages::motion::show_calibration(&app_ui, &result, &haptics);
+                }
+                InputEvent::Gesture(gesture) => {
+                    pages::gesture::show_gesture(&app_ui, gesture);
+                    match gesture {
+                        Gesture::FlipDown if !lock_screen => {
+                            lock_screen = true;
+                            locked_by_flip = true;
+                            rails.disable(Rail::Bldo1).unwrap();
+                        }
+                        Gesture::FlipUp if locked_by_flip => {
+                            locked_by_flip = false;
+                            wake_up = true;
+                        }
+                        Gesture::Shake => wake_up = true,
+                        Gesture::DoubleTap if alarms_ui.get_ringing() => {
+                            alarms_ui.invoke_stop_ringing();
+                        }
+                        _ => {}
+                    }
+                }
+                InputEvent::ScreenOrientation(orientation) => {
+                    detected_orientation = orientation;
+                }
+                InputEvent::Image(decoded) => files.show_decoded(decoded),
+                InputEvent::FontConversion(event) => files.show_conversion(event),
+                InputEvent::ImuLog(event) => pages::logger::show_event(&app_ui, event),
+                InputEvent::Steps(steps) if clock_ui.get_valid() => activity.add_steps(steps),
+                InputEvent::Steps(_) => {}
+                InputEvent::Rtc(event) => {
+                    log::info!("RTC event: {:?}", event);
+                    // woken up by the RTC, check the schedule right now
+                    wake_up = true;
+                    clock_timestamp = 0;
+                }
+            }
+        }
+        if wake_up {
+            wake_up = false;
+            if lock_screen {
+                lock_screen = false;
+                rails.enable(Rail::Bldo1).unwrap();
+            }
+        }
+
+        if let Some(docked) = dock_request.take() {
+            if docked != main_page.is_docked() {
+                log::info!("Docked mode: {}", docked);
+                app_ui.set_docked(docked);
+                if docked {
+                    // keep the screen on
+                    lock_screen = false;
+                    rails.enable(Rail::Bldo1).unwrap();
+                }
+                main_page.set_docked(docked);
+                // refresh the face immediately
+                clock_timestamp = 0;
+                last_status_poll = None;
             }
         }
 
+        // periodic status, mainly for the LED
+        if last_status_poll
+            .map(|last| last.elapsed() >= STATUS_POLL_INTERVAL)
+            .unwrap_or(true)
+        {
+            last_status_poll = Some(Instant::now());
+            match read_battery_status(&mut status_registers) {
+                Ok(status) => {
+                    dock.set_battery(
+                        if status.charge_state.is_charging() {
+                            format!("{}% 充电中", status.percent)
+                        } else {
+                            format!("{}%", status.percent)
+                        }
+                        .into(),
+                    );
+                    led.set(LedState::Charging, status.charge_state.is_charging());
+                    led.set(
+                        LedState::Full,
+                        status.vbus_present && status.charge_state == ChargeState::Done,
+                    );
+                    // the only source of the low battery state, the PMU warning level 2 is
+                    // left alone so the two never disagree
+                    led.set(
+                        LedState::LowBattery,
+                        !status.vbus_present && status.percent <= LOW_BATTERY_PERCENT,
+                    );
+                }
+                Err(e) => log::warn!("Failed to read battery status: {:?}", e),
+            }
+        }
+        let now = utils::now_timestamp();
+        if now != clock_timestamp {
+            // also true after changes of the system time
+            let minute_changed = now / 60 != clock_timestamp / 60;
+            clock_timestamp = now;
+            let datetime = DateTime::from_timestamp(now as u64);
+            let time = if clock_ui.get_valid() {
+                datetime.time_string()
+            } else {
+                "--:--".into()
+            };
+            clock_ui.set_now(time.clone().into());
+            if main_page.is_docked() {
+                dock.set_time(time.into());
+                dock.set_date(datetime.date_string().into());
+            }
+
+            // alarms and countdown
+            if alarms.update(now as u64, minute_changed) {
+                wake_up = true;
+            }
+
+            if minute_changed && clock_ui.get_valid() {
+                activity.update(now as u64);
+            }
+        }
+        // settings are saved once they stop changing
+        main_page.save_if_due();
+        // stopwatch and countdown keep running when locked
+        let now = duration_since_start();
+        if stopwatch.update(now) {
+            alarms.ring("计时结束");
+            wake_up = true;
+        }
+
+        // the slideshow stops once the files page is left, and pauses while locked
+        let page = app_ui.get_current_page();
+        if page != shown_page && shown_page == app_ui.global::<Pages>().get_files() {
+            files.leave(now);
+        }
+        shown_page = page;
+        if !lock_screen {
+            files.update(now);
+        }
+
+        led.set(LedState::RadioActive, app_ui.get_jammer_running());
+        led.set(LedState::Sleeping, lock_screen);
+        if let Err(e) = led.update() {
+            log::warn!("Failed to update charge LED: {:?}", e);
+        }
+
+        // never turn the screen in the middle of a drag, the touch task keeps the old mapping
+        // until the pointer is released
+        let orientation = screen_lock.get().unwrap_or(detected_orientation);
+        let rotation = (orientation != rendered_orientation && !pointer_pressed).then(|| {
+            rendered_orientation = orientation;
+            screen_orientation.store(orientation as u8, Ordering::Relaxed);
+            let (width, height) = orientation.logical_size();
+            window.set_size(slint::PhysicalSize::new(width, height));
+            window.request_redraw();
+            // the content is turned against the device
+            match orientation {
+                ScreenOrientation::Landscape => RenderingRotation::NoRotation,
+                ScreenOrientation::Portrait => RenderingRotation::Rotate270,
+                ScreenOrientation::LandscapeFlipped => RenderingRotation::Rotate180,
+                ScreenOrientation::PortraitFlipped => RenderingRotation::Rotate90,
+            }
+        });
         window.draw_if_needed(|renderer| {
+            if let Some(rotation) = rotation {
+                renderer.set_rendering_rotation(rotation);
+            }
             renderer.render_by_line(DisplayWrapper::new(&mut display, &mut line_buffer));
         });
 
@@ -0,0 +1,31 @@
+//! Shake, double-tap and flip detection, see [`gadget_core::motion::gesture`]. The thresholds are
+//! kept in NVS.
+
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::EspError,
+};
+
+pub use gadget_core::motion::gesture::{
+    Gesture, GestureConfig, GestureDetector, FLIP_RANGE, SHAKE_RANGE, TAP_RANGE,
+};
+
+/// NVS namespace of the thresholds.
+pub const NVS_NAMESPACE: &str = "gesture";
+
+pub fn load_config(nvs: &EspNvs<NvsDefault>) -> GestureConfig {
+    let default = GestureConfig::default();
+    let get = |key: &str, fallback: u8| nvs.get_u8(key).ok().flatten().unwrap_or(fallback);
+    GestureConfig {
+        shake: get("shake", default.shake),
+        tap: get("tap", default.tap),
+        flip: get("flip", default.flip),
+    }
+    .sanitized()
+}
+
+pub fn store_config(nvs: &mut EspNvs<NvsDefault>, config: &GestureConfig) -> Result<(), EspError> {
+    nvs.set_u8("shake", config.shake)?;
+    nvs.set_u8("tap", config.tap)?;
+    nvs.set_u8("flip", config.flip)
+}
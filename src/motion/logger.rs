@@ -0,0 +1,308 @@
+//! Raw accel and gyro capture to files, for vibration tests.
+//!
+//! Samples are read from the MPU6886 FIFO, so rates up to [`super::MAX_FIFO_RATE`] are possible.
+//! Each file starts with a header describing the ranges and the sample rate.
+//!
+//! CSV files have `#` comment lines as the header, followed by one sample per line in g and °/s.
+//! A `# overflow` line marks samples lost.
+//!
+//! Binary files are little endian, a 32 bytes header:
+//!
+//! | offset | type     | content                         |
+//! |--------|----------|---------------------------------|
+//! | 0      | [u8; 4]  | magic `IMU1`                    |
+//! | 4      | u16      | sample rate in Hz               |
+//! | 6      | u16      | accel full scale in g           |
+//! | 8      | u16      | gyro full scale in °/s          |
+//! | 10     | u16      | reserved                        |
+//! | 12     | u32      | start time, seconds since epoch |
+//! | 16     | f32      | accel LSB per g                 |
+//! | 20     | f32      | gyro LSB per °/s                |
+//! | 24     | [u8; 8]  | reserved                        |
+//!
+//! followed by samples of 6 i16, accel XYZ then gyro XYZ in sensor units. A sample with all
+//! fields `i16::MIN` marks samples lost.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::EspError,
+};
+
+use crate::clock::DateTime;
+
+use super::{AccelRange, GyroRange, MotionConfig, RawSample};
+
+/// Selectable rates of the logger.
+pub const LOG_RATES: [u16; 5] = [50, 100, 200, 500, 1000];
+
+/// The ranges and the rate picked on the logger page, kept in the namespace of the sensor.
+const NVS_KEY_CONFIG: &str = "logger";
+
+const BINARY_MAGIC: &[u8; 4] = b"IMU1";
+const BINARY_HEADER_SIZE: usize = 32;
+const GAP_MARKER: RawSample = RawSample {
+    accel: [i16::MIN; 3],
+    gyro: [i16::MIN; 3],
+};
+/// SD cards like large writes.
+const BUFFER_SIZE: usize = 8192;
+/// Captures started in the same second get a numbered suffix, up to this many.
+const MAX_SAME_NAME: u32 = 100;
+
+/// What the logger page offers first.
+pub fn default_config() -> MotionConfig {
+    MotionConfig {
+        sample_rate: LOG_RATES[1],
+        fifo: true,
+        ..Default::default()
+    }
+}
+
+/// The ranges and the rate picked last time, or the defaults.
+pub fn load_config(nvs: &EspNvs<NvsDefault>) -> MotionConfig {
+    let mut buffer = [0u8; 4];
+    match nvs.get_blob(NVS_KEY_CONFIG, &mut buffer) {
+        Ok(Some([accel, gyro, rate_low, rate_high])) => {
+            let default = default_config();
+            let rate = u16::from_le_bytes([*rate_low, *rate_high]);
+            MotionConfig {
+                accel_range: AccelRange::ALL
+                    .get(*accel as usize)
+                    .copied()
+                    .unwrap_or(default.accel_range),
+                gyro_range: GyroRange::ALL
+                    .get(*gyro as usize)
+                    .copied()
+                    .unwrap_or(default.gyro_range),
+                sample_rate: if LOG_RATES.contains(&rate) {
+                    rate
+                } else {
+                    default.sample_rate
+                },
+                fifo: true,
+            }
+        }
+        _ => default_config(),
+    }
+}
+
+pub fn store_config(nvs: &mut EspNvs<NvsDefault>, config: &MotionConfig) -> Result<(), EspError> {
+    let rate = config.sample_rate.to_le_bytes();
+    nvs.set_blob(
+        NVS_KEY_CONFIG,
+        &[
+            config.accel_range as u8,
+            config.gyro_range as u8,
+            rate[0],
+            rate[1],
+        ],
+    )
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Csv,
+    Binary,
+}
+
+impl LogFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            LogFormat::Csv => "csv",
+            LogFormat::Binary => "bin",
+        }
+    }
+}
+
+/// Reported to the super loop as [`crate::inputevent::InputEvent::ImuLog`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoggerEvent {
+    Progress {
+        samples: u64,
+        dropped: u32,
+    },
+    /// The file is closed, with the final counts or what went wrong.
+    Finished(Result<LogSummary, io::ErrorKind>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogSummary {
+    pub path: PathBuf,
+    pub samples: u64,
+    /// Times samples were lost.
+    pub dropped: u32,
+}
+
+pub struct ImuLogger {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    format: LogFormat,
+    config: MotionConfig,
+    samples: u64,
+    dropped: u32,
+}
+
+impl ImuLogger {
+    /// Create a new file in `directory` named after `timestamp`, and write the header.
+    pub fn create(
+        directory: &Path,
+        format: LogFormat,
+        config: MotionConfig,
+        timestamp: u64,
+    ) -> io::Result<Self> {
+        let config = MotionConfig {
+            fifo: true,
+            ..config
+        }
+        .sanitized();
+        fs::create_dir_all(directory)?;
+        let datetime = DateTime::from_timestamp(timestamp);
+        let stem = format!(
+            "imu_{:04}{:02}{:02}_{:02}{:02}{:02}",
+            datetime.year,
+            datetime.month,
+            datetime.day,
+            datetime.hour,
+            datetime.minute,
+            datetime.second
+        );
+        let (path, file) = create_new(directory, &stem, format.extension())?;
+        let mut writer = BufWriter::with_capacity(BUFFER_SIZE, file);
+        match format {
+            LogFormat::Csv => {
+                writeln!(writer, "# MPU6886 raw capture")?;
+                writeln!(writer, "# start={}", timestamp)?;
+                writeln!(writer, "# sample_rate_hz={}", config.sample_rate)?;
+                writeln!(
+                    writer,
+                    "# accel_range_g={}",
+                    config.accel_range.full_scale()
+                )?;
+                writeln!(
+                    writer,
+                    "# gyro_range_dps={}",
+                    config.gyro_range.full_scale()
+                )?;
+                writeln!(
+                    writer,
+                    "# accel_lsb_per_g={}",
+                    config.accel_range.sensitivity()
+                )?;
+                writeln!(
+                    writer,
+                    "# gyro_lsb_per_dps={}",
+                    config.gyro_range.sensitivity()
+                )?;
+                writeln!(writer, "index,ax_g,ay_g,az_g,gx_dps,gy_dps,gz_dps")?;
+            }
+            LogFormat::Binary => {
+                let mut header = [0u8; BINARY_HEADER_SIZE];
+                header[0..4].copy_from_slice(BINARY_MAGIC);
+                header[4..6].copy_from_slice(&config.sample_rate.to_le_bytes());
+                header[6..8].copy_from_slice(&config.accel_range.full_scale().to_le_bytes());
+                header[8..10].copy_from_slice(&config.gyro_range.full_scale().to_le_bytes());
+                header[12..16].copy_from_slice(&(timestamp as u32).to_le_bytes());
+                header[16..20].copy_from_slice(&config.accel_range.sensitivity().to_le_bytes());
+                header[20..24].copy_from_slice(&config.gyro_range.sensitivity().to_le_bytes());
+                writer.write_all(&header)?;
+            }
+        }
+        Ok(Self {
+            writer,
+            path,
+            format,
+            config,
+            samples: 0,
+            dropped: 0,
+        })
+    }
+
+    /// The sensor configuration while logging.
+    pub fn config(&self) -> MotionConfig {
+        self.config
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    pub fn write(&mut self, sample: &RawSample) -> io::Result<()> {
+        match self.format {
+            LogFormat::Csv => {
+                let sample = sample.to_sample(&self.config);
+                let [ax, ay, az] = sample.accel;
+                let [gx, gy, gz] = sample.gyro;
+                writeln!(
+                    self.writer,
+                    "{},{:.4},{:.4},{:.4},{:.3},{:.3},{:.3}",
+                    self.samples, ax, ay, az, gx, gy, gz
+                )?;
+            }
+            LogFormat::Binary => self.write_binary(sample)?,
+        }
+        self.samples += 1;
+        Ok(())
+    }
+
+    /// Mark samples lost at this point.
+    pub fn write_gap(&mut self) -> io::Result<()> {
+        self.dropped += 1;
+        match self.format {
+            LogFormat::Csv => writeln!(self.writer, "# overflow"),
+            LogFormat::Binary => self.write_binary(&GAP_MARKER),
+        }
+    }
+
+    fn write_binary(&mut self, sample: &RawSample) -> io::Result<()> {
+        let mut bytes = [0u8; 12];
+        for (chunk, value) in bytes
+            .chunks_exact_mut(2)
+            .zip(sample.accel.iter().chain(sample.gyro.iter()))
+        {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        self.writer.write_all(&bytes)
+    }
+
+    /// Flush and close the file.
+    pub fn finish(self) -> io::Result<LogSummary> {
+        let file = self.writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok(LogSummary {
+            path: self.path,
+            samples: self.samples,
+            dropped: self.dropped,
+        })
+    }
+}
+
+/// Create `stem.extension` in `directory`, or `stem_1.extension` and so on when taken. Never
+/// truncates an existing capture, which happens when the clock is not set after a reboot.
+fn create_new(directory: &Path, stem: &str, extension: &str) -> io::Result<(PathBuf, File)> {
+    for suffix in 0..MAX_SAME_NAME {
+        let path = match suffix {
+            0 => directory.join(format!("{}.{}", stem, extension)),
+            _ => directory.join(format!("{}_{}.{}", stem, suffix, extension)),
+        };
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("too many captures named {}", stem),
+    ))
+}
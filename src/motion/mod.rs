@@ -0,0 +1,415 @@
+//! Motion sensing with the MPU6886 on board.
+//!
+//! Only the few registers required for sampling are used, see the MPU6886 datasheet. The
+//! sensor is mounted flat, Z axis points out of the screen.
+
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::EspError,
+};
+
+pub mod gesture;
+pub mod logger;
+pub mod screen;
+mod task;
+
+pub use gadget_core::motion::{MotionSample, Orientation, OrientationFilter};
+pub use task::spawn_motion_task;
+pub use task::{
+    gesture_subscriber, screen_orientation_subscriber, step_subscriber, MotionControl, Subscriber,
+};
+
+/// The 7-bit I2C address of MPU6886.
+pub const MPU6886_ADDRESS: u8 = 0x68;
+
+/// NVS namespace of the calibration data, and the choices of the logger.
+pub const NVS_NAMESPACE: &str = "motion";
+const NVS_KEY_BIAS: &str = "bias";
+
+const REG_SAMPLE_RATE_DIVIDER: u8 = 0x19;
+const REG_CONFIG: u8 = 0x1A;
+const REG_GYRO_CONFIG: u8 = 0x1B;
+const REG_ACCEL_CONFIG: u8 = 0x1C;
+const REG_ACCEL_CONFIG_2: u8 = 0x1D;
+const REG_FIFO_ENABLE: u8 = 0x23;
+const REG_ACCEL_OUT: u8 = 0x3B;
+const REG_USER_CONTROL: u8 = 0x6A;
+const REG_POWER_MANAGEMENT_1: u8 = 0x6B;
+const REG_POWER_MANAGEMENT_2: u8 = 0x6C;
+const REG_FIFO_COUNT: u8 = 0x72;
+const REG_FIFO_DATA: u8 = 0x74;
+const REG_WHO_AM_I: u8 = 0x75;
+
+const WHO_AM_I: u8 = 0x19;
+const DEVICE_RESET: u8 = 1 << 7;
+/// Auto select the best clock source.
+const CLOCK_AUTO: u8 = 0x01;
+/// Gyro DLPF at 176Hz, internal sample rate 1kHz.
+const GYRO_DLPF: u8 = 0x01;
+/// Accel DLPF at 218Hz.
+const ACCEL_DLPF: u8 = 0x01;
+/// Stop writing when the FIFO is full, rather than overwriting the oldest data.
+const FIFO_MODE_STOP: u8 = 1 << 6;
+/// Accel, temperature and gyro go into the FIFO, the same layout as the output registers.
+const FIFO_ACCEL_GYRO: u8 = (1 << 4) | (1 << 3);
+const USER_FIFO_ENABLE: u8 = 1 << 6;
+const USER_FIFO_RESET: u8 = 1 << 2;
+/// FIFO size in bytes.
+const FIFO_SIZE: usize = 1024;
+/// Bytes of one sample, accel XYZ, temperature, gyro XYZ.
+const SAMPLE_SIZE: usize = 14;
+/// Samples read from the FIFO in one I2C transaction.
+const FIFO_BURST: usize = 8;
+
+/// Fastest rate when polling, the sampling task is limited by the 100Hz kernel tick.
+pub const MAX_POLLING_RATE: u16 = 100;
+/// Fastest rate through the FIFO, the internal sample rate.
+pub const MAX_FIFO_RATE: u16 = 1000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AccelRange {
+    G2,
+    #[default]
+    G4,
+    G8,
+    G16,
+}
+
+impl AccelRange {
+    pub const ALL: [AccelRange; 4] = [
+        AccelRange::G2,
+        AccelRange::G4,
+        AccelRange::G8,
+        AccelRange::G16,
+    ];
+
+    fn raw(self) -> u8 {
+        (self as u8) << 3
+    }
+
+    /// Full scale in g.
+    pub fn full_scale(self) -> u16 {
+        2 << self as u16
+    }
+
+    /// LSB per g.
+    pub fn sensitivity(self) -> f32 {
+        match self {
+            AccelRange::G2 => 16384.0,
+            AccelRange::G4 => 8192.0,
+            AccelRange::G8 => 4096.0,
+            AccelRange::G16 => 2048.0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GyroRange {
+    Dps250,
+    #[default]
+    Dps500,
+    Dps1000,
+    Dps2000,
+}
+
+impl GyroRange {
+    pub const ALL: [GyroRange; 4] = [
+        GyroRange::Dps250,
+        GyroRange::Dps500,
+        GyroRange::Dps1000,
+        GyroRange::Dps2000,
+    ];
+
+    fn raw(self) -> u8 {
+        (self as u8) << 3
+    }
+
+    /// Full scale in °/s.
+    pub fn full_scale(self) -> u16 {
+        250 << self as u16
+    }
+
+    /// LSB per °/s.
+    pub fn sensitivity(self) -> f32 {
+        match self {
+            GyroRange::Dps250 => 131.0,
+            GyroRange::Dps500 => 65.5,
+            GyroRange::Dps1000 => 32.8,
+            GyroRange::Dps2000 => 16.4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionConfig {
+    pub accel_range: AccelRange,
+    pub gyro_range: GyroRange,
+    /// 4~100Hz when polling, up to 1kHz through the FIFO.
+    pub sample_rate: u16,
+    /// Read samples in batches from the FIFO, instead of one at a time.
+    pub fifo: bool,
+}
+
+impl Default for MotionConfig {
+    fn default() -> Self {
+        Self {
+            accel_range: AccelRange::default(),
+            gyro_range: GyroRange::default(),
+            sample_rate: 50,
+            fifo: false,
+        }
+    }
+}
+
+impl MotionConfig {
+    pub fn sanitized(self) -> Self {
+        Self {
+            sample_rate: if self.fifo {
+                self.sample_rate.clamp(4, MAX_FIFO_RATE)
+            } else {
+                self.sample_rate.clamp(4, MAX_POLLING_RATE)
+            },
+            ..self
+        }
+    }
+
+    /// Interval of the sampling task.
+    pub fn interval_ms(&self) -> u32 {
+        1000 / self.sample_rate.max(1) as u32
+    }
+
+    /// How long the task may sleep, the FIFO holds about 70 samples.
+    pub fn task_interval_ms(&self) -> u32 {
+        if self.fifo {
+            (FIFO_SIZE / SAMPLE_SIZE / 2) as u32 * 1000 / self.sample_rate.max(1) as u32
+        } else {
+            self.interval_ms()
+        }
+        .clamp(10, 250)
+    }
+}
+
+/// Reading in sensor units.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RawSample {
+    pub accel: [i16; 3],
+    pub gyro: [i16; 3],
+}
+
+impl RawSample {
+    /// From the output registers or the FIFO.
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let value = |index: usize| i16::from_be_bytes([bytes[index * 2], bytes[index * 2 + 1]]);
+        Self {
+            accel: [0, 1, 2].map(value),
+            gyro: [4, 5, 6].map(value),
+        }
+    }
+
+    /// In physical units, no bias removed.
+    pub fn to_sample(&self, config: &MotionConfig) -> MotionSample {
+        let accel_scale = config.accel_range.sensitivity();
+        let gyro_scale = config.gyro_range.sensitivity();
+        MotionSample {
+            accel: self.accel.map(|value| value as f32 / accel_scale),
+            gyro: self.gyro.map(|value| value as f32 / gyro_scale),
+        }
+    }
+}
+
+/// What subscribers receive.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MotionData {
+    /// Averaged down to the detector rate.
+    pub sample: MotionSample,
+    /// Time since the previous sample.
+    pub dt_ms: u32,
+    /// Filtered at the rate of the sensor.
+    pub orientation: Orientation,
+}
+
+/// Offsets measured at rest, subtracted from every reading.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Bias {
+    pub accel: [f32; 3],
+    pub gyro: [f32; 3],
+}
+
+const BIAS_SIZE: usize = 24;
+
+impl Bias {
+    pub fn load(nvs: &EspNvs<NvsDefault>) -> Self {
+        let mut buffer = [0u8; BIAS_SIZE];
+        match nvs.get_blob(NVS_KEY_BIAS, &mut buffer) {
+            Ok(Some(data)) if data.len() == BIAS_SIZE => {
+                let mut values = data
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()));
+                let mut next = || values.next().unwrap_or_default();
+                Self {
+                    accel: [next(), next(), next()],
+                    gyro: [next(), next(), next()],
+                }
+            }
+            _ => Self::default(),
+        }
+    }
+
+    pub fn store(&self, nvs: &mut EspNvs<NvsDefault>) -> Result<(), EspError> {
+        let data: Vec<u8> = self
+            .accel
+            .iter()
+            .chain(self.gyro.iter())
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        nvs.set_blob(NVS_KEY_BIAS, &data)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationError {
+    /// The device moved during calibration.
+    Moving,
+    /// The device is not lying flat, screen up.
+    NotLevel,
+    Sensor,
+}
+
+/// Number of samples averaged for calibration.
+pub const CALIBRATION_SAMPLES: usize = 100;
+
+/// Find the bias from readings taken at rest, lying flat with the screen up.
+///
+/// The samples must be raw, without any bias removed.
+pub fn calibrate(samples: &[MotionSample]) -> Result<Bias, CalibrationError> {
+    if samples.is_empty() {
+        return Err(CalibrationError::Sensor);
+    }
+    let count = samples.len() as f32;
+    let mut mean = MotionSample::default();
+    for sample in samples {
+        for axis in 0..3 {
+            mean.accel[axis] += sample.accel[axis] / count;
+            mean.gyro[axis] += sample.gyro[axis] / count;
+        }
+    }
+    let moving = samples.iter().any(|sample| {
+        (0..3).any(|axis| {
+            (sample.accel[axis] - mean.accel[axis]).abs() > 0.05
+                || (sample.gyro[axis] - mean.gyro[axis]).abs() > 3.0
+        })
+    });
+    if moving {
+        return Err(CalibrationError::Moving);
+    }
+    if (mean.accel[2] - 1.0).abs() > 0.2 || mean.accel[0].abs() > 0.2 || mean.accel[1].abs() > 0.2 {
+        return Err(CalibrationError::NotLevel);
+    }
+    Ok(Bias {
+        accel: [mean.accel[0], mean.accel[1], mean.accel[2] - 1.0],
+        gyro: mean.gyro,
+    })
+}
+
+/// Raw register access to MPU6886.
+pub struct Mpu6886Sampler<I2C> {
+    i2c: I2C,
+    config: MotionConfig,
+}
+
+impl<I2C> Mpu6886Sampler<I2C>
+where
+    I2C: I2c,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            i2c,
+            config: MotionConfig::default(),
+        }
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), I2C::Error> {
+        self.i2c.write(MPU6886_ADDRESS, &[register, value])
+    }
+
+    pub fn who_am_i(&mut self) -> Result<u8, I2C::Error> {
+        let mut buffer = [0u8; 1];
+        self.i2c
+            .write_read(MPU6886_ADDRESS, &[REG_WHO_AM_I], &mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    /// Reset the sensor and apply the configuration.
+    pub fn init(&mut self, config: MotionConfig) -> Result<(), I2C::Error> {
+        let id = self.who_am_i()?;
+        if id != WHO_AM_I {
+            log::warn!("Unexpected MPU6886 ID {:#04x}", id);
+        }
+        self.write_register(REG_POWER_MANAGEMENT_1, DEVICE_RESET)?;
+        // the datasheet doesn't say how long, 10ms is enough in practice
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        self.write_register(REG_POWER_MANAGEMENT_1, CLOCK_AUTO)?;
+        // all axes on
+        self.write_register(REG_POWER_MANAGEMENT_2, 0)?;
+        self.configure(config)
+    }
+
+    pub fn configure(&mut self, config: MotionConfig) -> Result<(), I2C::Error> {
+        let config = config.sanitized();
+        self.write_register(REG_USER_CONTROL, 0)?;
+        self.write_register(REG_FIFO_ENABLE, 0)?;
+        self.write_register(REG_CONFIG, GYRO_DLPF | FIFO_MODE_STOP)?;
+        self.write_register(REG_ACCEL_CONFIG_2, ACCEL_DLPF)?;
+        self.write_register(REG_GYRO_CONFIG, config.gyro_range.raw())?;
+        self.write_register(REG_ACCEL_CONFIG, config.accel_range.raw())?;
+        // 1kHz / (1 + divider)
+        let divider = (1000 / config.sample_rate - 1).min(255) as u8;
+        self.write_register(REG_SAMPLE_RATE_DIVIDER, divider)?;
+        if config.fifo {
+            self.write_register(REG_USER_CONTROL, USER_FIFO_RESET)?;
+            self.write_register(REG_FIFO_ENABLE, FIFO_ACCEL_GYRO)?;
+            self.write_register(REG_USER_CONTROL, USER_FIFO_ENABLE)?;
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    pub fn config(&self) -> MotionConfig {
+        self.config
+    }
+
+    /// Read accel and gyro at once.
+    pub fn sample(&mut self) -> Result<RawSample, I2C::Error> {
+        // accel XYZ, temperature, gyro XYZ, all big endian
+        let mut buffer = [0u8; SAMPLE_SIZE];
+        self.i2c
+            .write_read(MPU6886_ADDRESS, &[REG_ACCEL_OUT], &mut buffer)?;
+        Ok(RawSample::from_bytes(&buffer))
+    }
+
+    /// Move the samples in the FIFO to `samples`, returns false if it overflowed.
+    ///
+    /// After an overflow the FIFO is reset, as the data may no longer be aligned to samples.
+    pub fn read_fifo(&mut self, samples: &mut Vec<RawSample>) -> Result<bool, I2C::Error> {
+        let mut count = [0u8; 2];
+        self.i2c
+            .write_read(MPU6886_ADDRESS, &[REG_FIFO_COUNT], &mut count)?;
+        let count = (u16::from_be_bytes(count) & 0x1FFF) as usize;
+        if count + SAMPLE_SIZE > FIFO_SIZE {
+            self.write_register(REG_USER_CONTROL, USER_FIFO_ENABLE | USER_FIFO_RESET)?;
+            return Ok(false);
+        }
+        let mut buffer = [0u8; SAMPLE_SIZE * FIFO_BURST];
+        let mut remaining = count / SAMPLE_SIZE;
+        while remaining > 0 {
+            let burst = remaining.min(FIFO_BURST);
+            let bytes = &mut buffer[..burst * SAMPLE_SIZE];
+            self.i2c
+                .write_read(MPU6886_ADDRESS, &[REG_FIFO_DATA], bytes)?;
+            samples.extend(bytes.chunks_exact(SAMPLE_SIZE).map(RawSample::from_bytes));
+            remaining -= burst;
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,162 @@
+//! Screen orientation from the gravity direction.
+//!
+//! The angle of gravity in the screen plane selects the orientation. Each orientation only
+//! accepts angles within [`ACCEPT_DEGREES`] of its own axis and must be held for a while, so
+//! the screen doesn't flicker around 45°.
+
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::EspError,
+};
+
+use super::MotionSample;
+
+/// NVS namespace of the display settings.
+pub const NVS_NAMESPACE: &str = "display";
+/// 0 follows the device, otherwise the locked orientation plus one.
+const NVS_KEY_LOCK: &str = "rotlock";
+
+/// How the content is shown, seen by the user.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenOrientation {
+    /// Touch buttons below the screen.
+    #[default]
+    Landscape,
+    /// Turned clockwise, touch buttons on the left.
+    Portrait,
+    /// Touch buttons above the screen.
+    LandscapeFlipped,
+    /// Turned counterclockwise, touch buttons on the right.
+    PortraitFlipped,
+}
+
+/// Physical size of the panel.
+pub const PANEL_WIDTH: u16 = 320;
+pub const PANEL_HEIGHT: u16 = 240;
+
+/// The orientation is only changed within this angle from the axis.
+const ACCEPT_DEGREES: f32 = 30.0;
+/// Gravity in the screen plane must be strong enough, otherwise the device lies flat.
+const MINIMUM_TILT: f32 = 0.5;
+const HOLD_MS: u32 = 300;
+
+impl ScreenOrientation {
+    const ALL: [ScreenOrientation; 4] = [
+        ScreenOrientation::Landscape,
+        ScreenOrientation::Portrait,
+        ScreenOrientation::LandscapeFlipped,
+        ScreenOrientation::PortraitFlipped,
+    ];
+
+    pub fn is_portrait(self) -> bool {
+        matches!(
+            self,
+            ScreenOrientation::Portrait | ScreenOrientation::PortraitFlipped
+        )
+    }
+
+    /// Clockwise rotation of the device in degrees, the content is turned the other way.
+    pub fn degrees(self) -> f32 {
+        self as u8 as f32 * 90.0
+    }
+
+    /// The touch buttons are seen from right to left, or from bottom to top.
+    pub fn buttons_reversed(self) -> bool {
+        matches!(
+            self,
+            ScreenOrientation::LandscapeFlipped | ScreenOrientation::PortraitFlipped
+        )
+    }
+
+    /// Size of the window, (width, height).
+    pub fn logical_size(self) -> (u32, u32) {
+        if self.is_portrait() {
+            (PANEL_HEIGHT as u32, PANEL_WIDTH as u32)
+        } else {
+            (PANEL_WIDTH as u32, PANEL_HEIGHT as u32)
+        }
+    }
+
+    /// Map a point on the panel to the window.
+    pub fn map_point(self, x: u16, y: u16) -> (u16, u16) {
+        let x = x.min(PANEL_WIDTH - 1);
+        let y = y.min(PANEL_HEIGHT - 1);
+        match self {
+            ScreenOrientation::Landscape => (x, y),
+            ScreenOrientation::Portrait => (PANEL_HEIGHT - 1 - y, x),
+            ScreenOrientation::LandscapeFlipped => (PANEL_WIDTH - 1 - x, PANEL_HEIGHT - 1 - y),
+            ScreenOrientation::PortraitFlipped => (y, PANEL_WIDTH - 1 - x),
+        }
+    }
+
+    /// The orientation whose axis is nearest, if close enough.
+    fn from_gravity(sample: &MotionSample) -> Option<Self> {
+        // Y axis points to the top edge, X axis to the right edge
+        let [x, y, _] = sample.accel;
+        if (x * x + y * y).sqrt() < MINIMUM_TILT {
+            return None;
+        }
+        // 0° when upright, increasing when turned clockwise
+        let angle = (-x).atan2(y).to_degrees().rem_euclid(360.0);
+        Self::ALL.into_iter().find(|orientation| {
+            let difference = (angle - orientation.degrees()).rem_euclid(360.0);
+            difference <= ACCEPT_DEGREES || difference >= 360.0 - ACCEPT_DEGREES
+        })
+    }
+}
+
+impl From<u8> for ScreenOrientation {
+    fn from(value: u8) -> Self {
+        Self::ALL[value as usize % 4]
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ScreenOrientationDetector {
+    current: ScreenOrientation,
+    /// Where it's heading to, and for how long.
+    candidate: Option<(ScreenOrientation, u32)>,
+}
+
+impl ScreenOrientationDetector {
+    /// Feed one sample, `dt_ms` after the previous one. Returns the new orientation on change.
+    pub fn update(&mut self, sample: &MotionSample, dt_ms: u32) -> Option<ScreenOrientation> {
+        let orientation = match ScreenOrientation::from_gravity(sample) {
+            Some(orientation) if orientation != self.current => orientation,
+            _ => {
+                self.candidate = None;
+                return None;
+            }
+        };
+        let held = match self.candidate {
+            Some((candidate, held)) if candidate == orientation => held + dt_ms,
+            _ => 0,
+        };
+        if held >= HOLD_MS {
+            self.current = orientation;
+            self.candidate = None;
+            Some(orientation)
+        } else {
+            self.candidate = Some((orientation, held));
+            None
+        }
+    }
+}
+
+/// The orientation the screen is locked at, if any.
+pub fn load_lock(nvs: &EspNvs<NvsDefault>) -> Option<ScreenOrientation> {
+    match nvs.get_u8(NVS_KEY_LOCK).ok().flatten() {
+        Some(value) if value > 0 => Some(ScreenOrientation::from(value - 1)),
+        _ => None,
+    }
+}
+
+pub fn store_lock(
+    nvs: &mut EspNvs<NvsDefault>,
+    lock: Option<ScreenOrientation>,
+) -> Result<(), EspError> {
+    nvs.set_u8(
+        NVS_KEY_LOCK,
+        lock.map_or(0, |orientation| orientation as u8 + 1),
+    )
+}
@@ -0,0 +1,295 @@
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::{
+    hal::delay::FreeRtos as FreeRtosDelay,
+    nvs::{EspDefaultNvsPartition, EspNvs},
+};
+use gadget_core::motion::{step::StepDetector, Decimator, DETECTOR_RATE};
+use std::{
+    sync::{
+        mpsc::{self, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::inputevent::InputEvent;
+
+use super::{
+    calibrate,
+    gesture::{GestureConfig, GestureDetector},
+    logger::{ImuLogger, LoggerEvent},
+    screen::ScreenOrientationDetector,
+    Bias, MotionConfig, MotionData, MotionSample, Mpu6886Sampler, OrientationFilter,
+    CALIBRATION_SAMPLES, NVS_NAMESPACE,
+};
+
+/// Rate of [`InputEvent::Motion`], the UI doesn't need every sample.
+const UI_RATE: u16 = 10;
+/// Interval of [`LoggerEvent::Progress`].
+const LOGGER_PROGRESS_MS: u32 = 1000;
+
+/// Called with every sample at [`DETECTOR_RATE`] in the motion task, returns false to
+/// unsubscribe.
+pub type Subscriber = Box<dyn FnMut(&MotionData) -> bool + Send>;
+
+/// Simple task control commands.
+#[allow(dead_code)]
+pub enum MotionControl {
+    Subscribe(Subscriber),
+    SetConfig(MotionConfig),
+    /// Measure the bias, the device must be at rest, lying flat with the screen up.
+    Calibrate,
+    /// Write raw samples to the logger, the sensor runs with its configuration meanwhile.
+    StartLogging(ImuLogger),
+    StopLogging,
+    Terminate,
+}
+
+/// Spawn the motion task and return a control handle.
+///
+/// Subscribers get the samples averaged down to [`DETECTOR_RATE`], whatever the sensor runs at,
+/// while the super loop gets [`InputEvent::Motion`] at a lower rate, and
+/// [`InputEvent::MotionCalibration`] when a calibration is done. The logger reports through
+/// [`InputEvent::ImuLog`].
+#[inline]
+pub fn spawn_motion_task<I2C>(
+    mut sampler: Mpu6886Sampler<I2C>,
+    config: MotionConfig,
+    nvs_partition: EspDefaultNvsPartition,
+    sender: SyncSender<InputEvent>,
+) -> SyncSender<MotionControl>
+where
+    I2C: I2c + Send + 'static,
+{
+    let (control_sender, receiver) = mpsc::sync_channel::<MotionControl>(4);
+    let _ = thread::spawn(move || {
+        if let Err(e) = sampler.init(config) {
+            log::error!("Failed to initialize MPU6886: {:?}", e);
+            return;
+        }
+        let mut bias = match EspNvs::new(nvs_partition.clone(), NVS_NAMESPACE, true) {
+            Ok(nvs) => Bias::load(&nvs),
+            Err(e) => {
+                log::warn!("Failed to open motion storage: {:?}", e);
+                Bias::default()
+            }
+        };
+        log::info!("Motion bias: {:?}", bias);
+        let mut subscribers: Vec<Subscriber> = Vec::new();
+        let mut filter = OrientationFilter::default();
+        let mut decimator = Decimator::default();
+        let mut calibration: Option<Vec<MotionSample>> = None;
+        let mut count = 0u32;
+        let mut logger: Option<ImuLogger> = None;
+        // the configuration to go back to after logging
+        let mut normal_config = config;
+        let mut since_progress_ms = 0u32;
+        let mut raw_samples = Vec::new();
+
+        loop {
+            for event in receiver.try_iter() {
+                match event {
+                    MotionControl::Subscribe(subscriber) => subscribers.push(subscriber),
+                    MotionControl::SetConfig(value) => {
+                        normal_config = value;
+                        if logger.is_some() {
+                            continue;
+                        }
+                        if let Err(e) = sampler.configure(value) {
+                            log::error!("Failed to configure MPU6886: {:?}", e);
+                        }
+                    }
+                    MotionControl::Calibrate => {
+                        calibration = Some(Vec::with_capacity(CALIBRATION_SAMPLES))
+                    }
+                    MotionControl::StartLogging(value) => {
+                        if let Some(previous) = logger.replace(value) {
+                            let _ = sender.send(InputEvent::ImuLog(LoggerEvent::Finished(
+                                previous.finish().map_err(|e| e.kind()),
+                            )));
+                        }
+                        let config = logger.as_ref().unwrap().config();
+                        log::info!("IMU logging started: {:?}", config);
+                        if let Err(e) = sampler.configure(config) {
+                            log::error!("Failed to configure MPU6886: {:?}", e);
+                        }
+                        since_progress_ms = 0;
+                    }
+                    MotionControl::StopLogging => {
+                        if let Some(value) = logger.take() {
+                            stop_logging(&mut sampler, value, normal_config, &sender, Ok(()));
+                        }
+                    }
+                    MotionControl::Terminate => {
+                        if let Some(value) = logger.take() {
+                            stop_logging(&mut sampler, value, normal_config, &sender, Ok(()));
+                        }
+                        return;
+                    }
+                };
+            }
+            let config = sampler.config();
+
+            let result = if config.fifo {
+                sampler.read_fifo(&mut raw_samples)
+            } else {
+                sampler.sample().map(|raw| {
+                    raw_samples.push(raw);
+                    true
+                })
+            };
+            match result {
+                Ok(true) => {}
+                Ok(false) => {
+                    log::warn!("MPU6886 FIFO overflow");
+                    if let Some(Err(e)) = logger.as_mut().map(ImuLogger::write_gap) {
+                        let value = logger.take().unwrap();
+                        stop_logging(&mut sampler, value, normal_config, &sender, Err(e));
+                    }
+                }
+                Err(e) => log::warn!("Failed to read MPU6886: {:?}", e),
+            }
+
+            for raw in raw_samples.drain(..) {
+                if let Some(Err(e)) = logger.as_mut().map(|logger| logger.write(&raw)) {
+                    let value = logger.take().unwrap();
+                    stop_logging(&mut sampler, value, normal_config, &sender, Err(e));
+                }
+                // no bias removed yet
+                let raw = raw.to_sample(&config);
+                if let Some(samples) = calibration.as_mut() {
+                    samples.push(raw);
+                    if samples.len() >= CALIBRATION_SAMPLES {
+                        let result = calibrate(samples);
+                        calibration = None;
+                        match result {
+                            Ok(value) => {
+                                log::info!("Motion calibrated: {:?}", value);
+                                bias = value;
+                                if let Err(e) =
+                                    EspNvs::new(nvs_partition.clone(), NVS_NAMESPACE, true)
+                                        .and_then(|mut nvs| bias.store(&mut nvs))
+                                {
+                                    log::error!("Failed to save motion bias: {:?}", e);
+                                }
+                            }
+                            Err(e) => log::warn!("Motion calibration failed: {:?}", e),
+                        }
+                        let _ = sender.try_send(InputEvent::MotionCalibration(result));
+                    }
+                }
+
+                let sample = MotionSample {
+                    accel: [0, 1, 2].map(|axis| raw.accel[axis] - bias.accel[axis]),
+                    gyro: [0, 1, 2].map(|axis| raw.gyro[axis] - bias.gyro[axis]),
+                };
+                let orientation = filter.update(&sample, config.interval_ms() as f32 / 1000.0);
+                // the detectors are tuned at one rate, the logger may run much faster
+                let Some((sample, dt_ms)) = decimator.update(&sample, config.sample_rate) else {
+                    continue;
+                };
+                let data = MotionData {
+                    sample,
+                    dt_ms,
+                    orientation,
+                };
+                subscribers.retain_mut(|subscriber| subscriber(&data));
+                count = count.wrapping_add(1);
+                if count % (DETECTOR_RATE / UI_RATE) as u32 == 0 {
+                    let _ = sender.try_send(InputEvent::Motion(data));
+                }
+            }
+
+            if let Some(logger) = logger.as_ref() {
+                since_progress_ms += config.task_interval_ms();
+                if since_progress_ms >= LOGGER_PROGRESS_MS {
+                    since_progress_ms = 0;
+                    let _ = sender.try_send(InputEvent::ImuLog(LoggerEvent::Progress {
+                        samples: logger.samples(),
+                        dropped: logger.dropped(),
+                    }));
+                }
+            }
+
+            FreeRtosDelay::delay_ms(config.task_interval_ms());
+        }
+    });
+
+    control_sender
+}
+
+/// A subscriber which reports gestures as [`InputEvent::Gesture`].
+///
+/// The thresholds can be changed at any time through `config`.
+pub fn gesture_subscriber(
+    config: Arc<Mutex<GestureConfig>>,
+    sender: SyncSender<InputEvent>,
+) -> Subscriber {
+    let mut detector = GestureDetector::new(*config.lock().unwrap());
+    Box::new(move |data| {
+        detector.set_config(*config.lock().unwrap());
+        if let Some(gesture) = detector.update(&data.sample, data.dt_ms) {
+            log::info!("Gesture: {:?}", gesture);
+            if let Err(mpsc::TrySendError::Disconnected(_)) =
+                sender.try_send(InputEvent::Gesture(gesture))
+            {
+                return false;
+            }
+        }
+        true
+    })
+}
+
+/// A subscriber which reports orientation changes as [`InputEvent::ScreenOrientation`].
+pub fn screen_orientation_subscriber(sender: SyncSender<InputEvent>) -> Subscriber {
+    let mut detector = ScreenOrientationDetector::default();
+    Box::new(move |data| {
+        if let Some(orientation) = detector.update(&data.sample, data.dt_ms) {
+            log::info!("Screen orientation: {:?}", orientation);
+            if let Err(mpsc::TrySendError::Disconnected(_)) =
+                sender.try_send(InputEvent::ScreenOrientation(orientation))
+            {
+                return false;
+            }
+        }
+        true
+    })
+}
+
+/// A subscriber which reports new steps as [`InputEvent::Steps`].
+pub fn step_subscriber(sender: SyncSender<InputEvent>) -> Subscriber {
+    let mut detector = StepDetector::default();
+    // steps not delivered yet because the queue was full
+    let mut pending = 0u32;
+    Box::new(move |data| {
+        pending += detector.update(&data.sample, data.dt_ms);
+        if pending == 0 {
+            return true;
+        }
+        match sender.try_send(InputEvent::Steps(pending)) {
+            Ok(()) => pending = 0,
+            Err(mpsc::TrySendError::Full(_)) => {}
+            Err(mpsc::TrySendError::Disconnected(_)) => return false,
+        }
+        true
+    })
+}
+
+/// Close the file, report it, and put the sensor back to `config`.
+fn stop_logging<I2C: I2c>(
+    sampler: &mut Mpu6886Sampler<I2C>,
+    logger: ImuLogger,
+    config: MotionConfig,
+    sender: &SyncSender<InputEvent>,
+    result: std::io::Result<()>,
+) {
+    let result = result.and_then(|_| logger.finish()).map_err(|e| {
+        log::error!("IMU logging failed: {:?}", e);
+        e.kind()
+    });
+    log::info!("IMU logging stopped: {:?}", result);
+    let _ = sender.send(InputEvent::ImuLog(LoggerEvent::Finished(result)));
+    if let Err(e) = sampler.configure(config) {
+        log::error!("Failed to configure MPU6886: {:?}", e);
+    }
+}
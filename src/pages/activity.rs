@@ -0,0 +1,120 @@
+//! Activity page, the steps of today and the days before.
+
+use std::{
+    cell::{Cell, RefCell},
+    time::{Duration, Instant},
+};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel, Weak};
+
+use crate::{
+    activity::{self, ActivityLog},
+    utils, Activity, GadgetMainWindow,
+};
+
+/// Step totals are saved at most this often, unless a new day begins.
+const SAVE_INTERVAL: Duration = Duration::from_secs(600);
+
+fn show(activity_ui: &Activity, activity_log: &ActivityLog) {
+    let today = activity_log.today();
+    activity_ui.set_today(today.min(i32::MAX as u32) as i32);
+    activity_ui
+        .set_distance(format!("{:.2} km", today as f32 * activity::STRIDE_LENGTH / 1000.0).into());
+    let best = activity_log.days().max_by_key(|day| day.steps).copied();
+    let maximum = best.map_or(0, |best| best.steps).max(1) as f32;
+    activity_ui.set_history(ModelRc::new(VecModel::from(
+        activity_log
+            .days()
+            .map(|day| day.steps as f32 / maximum)
+            .collect::<Vec<_>>(),
+    )));
+    let mut labels: Vec<SharedString> = activity_log.days().map(|day| day.label().into()).collect();
+    labels.resize(activity::HISTORY_DAYS, SharedString::default());
+    activity_ui.set_labels(ModelRc::new(VecModel::from(labels)));
+    activity_ui.set_best(
+        best.filter(|best| best.steps > 0)
+            .map(|best| {
+                format!(
+                    "{}天内最多: {} {} 步",
+                    activity::HISTORY_DAYS,
+                    best.label(),
+                    best.steps
+                )
+            })
+            .unwrap_or_default()
+            .into(),
+    );
+}
+
+/// The step totals, saved now and then, and before power off.
+pub struct ActivityPage {
+    ui: Weak<GadgetMainWindow>,
+    log: RefCell<ActivityLog>,
+    saved: Cell<Instant>,
+    nvs_partition: EspDefaultNvsPartition,
+}
+
+impl ActivityPage {
+    fn save(&self, activity_log: &mut ActivityLog) {
+        match EspNvs::new(self.nvs_partition.clone(), activity::NVS_NAMESPACE, true) {
+            Ok(mut nvs) => {
+                if let Err(e) = activity_log.store(&mut nvs) {
+                    log::error!("Failed to save step totals: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to open activity storage: {:?}", e),
+        }
+        self.saved.set(Instant::now());
+    }
+
+    /// Book new steps, only with a valid clock.
+    pub fn add_steps(&self, steps: u32) {
+        let mut activity_log = self.log.borrow_mut();
+        activity_log.add_steps(steps);
+        show(&self.ui.unwrap().global::<Activity>(), &activity_log);
+    }
+
+    /// A new day of steps starts at midnight, the totals are saved right away then, otherwise
+    /// once in a while. Called every minute with a valid clock.
+    pub fn update(&self, now: u64) {
+        let mut activity_log = self.log.borrow_mut();
+        if activity_log.roll_over(activity::day_of(now)) {
+            show(&self.ui.unwrap().global::<Activity>(), &activity_log);
+            self.save(&mut activity_log);
+        } else if activity_log.is_dirty() && self.saved.get().elapsed() >= SAVE_INTERVAL {
+            self.save(&mut activity_log);
+        }
+    }
+
+    /// Save the steps not saved yet, before power off.
+    pub fn save_pending(&self) {
+        let mut activity_log = self.log.borrow_mut();
+        if activity_log.is_dirty() {
+            self.save(&mut activity_log);
+        }
+    }
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    clock_valid: bool,
+    nvs_partition: &EspDefaultNvsPartition,
+) -> ActivityPage {
+    let mut activity_log = EspNvs::new(nvs_partition.clone(), activity::NVS_NAMESPACE, false)
+        .map(|nvs| ActivityLog::load(&nvs))
+        .unwrap_or_default();
+    // without a valid clock there's no telling which day it is, steps aren't booked until it's set
+    if clock_valid {
+        activity_log.roll_over(activity::day_of(utils::now_timestamp() as u64));
+    }
+    let activity_ui = ui.global::<Activity>();
+    activity_ui.set_history_capacity(activity::HISTORY_DAYS as i32);
+    show(&activity_ui, &activity_log);
+    ActivityPage {
+        ui: ui.as_weak(),
+        log: RefCell::new(activity_log),
+        saved: Cell::new(Instant::now()),
+        nvs_partition: nvs_partition.clone(),
+    }
+}
@@ -0,0 +1,234 @@
+//! Alarms page, the list of alarms and the countdown, both kept on the RTC so the device wakes up
+//! in time.
+
+use std::{cell::RefCell, rc::Rc, sync::mpsc::SyncSender};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel, Weak};
+
+use crate::{
+    alarms::{self, Alarm, AlarmScheduler, RingReason, REPEAT_OPTIONS},
+    clock::DateTime,
+    haptics::HapticPattern,
+    ringer::RingerControl,
+    utils, AlarmView, Alarms, GadgetMainWindow,
+};
+
+use super::SharedRtc;
+
+pub struct AlarmsPage {
+    ui: Weak<GadgetMainWindow>,
+    scheduler: RefCell<AlarmScheduler>,
+    model: Rc<VecModel<AlarmView>>,
+    shared_rtc: &'static SharedRtc,
+    nvs_partition: EspDefaultNvsPartition,
+    ringer: SyncSender<RingerControl>,
+}
+
+impl AlarmsPage {
+    fn save(&self, alarms: &[Alarm]) {
+        match EspNvs::new(self.nvs_partition.clone(), alarms::NVS_NAMESPACE, true) {
+            Ok(mut nvs) => {
+                if let Err(e) = alarms::store_alarms(&mut nvs, alarms) {
+                    log::error!("Failed to save alarms: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to open alarm storage: {:?}", e),
+        }
+    }
+
+    /// Program the RTC, so the device wakes up in time.
+    fn arm(&self, scheduler: &mut AlarmScheduler) {
+        let mut rtc = self.shared_rtc.lock().unwrap();
+        if let Err(e) = scheduler.arm(&mut *rtc, utils::now_timestamp() as u64) {
+            log::warn!("Failed to program RTC alarm: {:?}", e);
+        }
+    }
+
+    /// Save and arm the alarms after a change, then show them.
+    fn changed(&self, scheduler: &mut AlarmScheduler) {
+        self.save(scheduler.alarms());
+        self.arm(scheduler);
+    }
+
+    fn show(&self) {
+        let app_ui = self.ui.unwrap();
+        let alarms_ui = app_ui.global::<Alarms>();
+        let scheduler = self.scheduler.borrow();
+        self.model.set_vec(
+            scheduler
+                .alarms()
+                .iter()
+                .map(|alarm| AlarmView {
+                    label: alarm.label().into(),
+                    enabled: alarm.enabled,
+                })
+                .collect::<Vec<_>>(),
+        );
+        let next_alarm = match scheduler.next_alarm(utils::now_timestamp() as u64) {
+            Some((index, timestamp)) => format!(
+                "下一个 {} {}",
+                DateTime::from_timestamp(timestamp).date_string(),
+                scheduler.alarms()[index].label()
+            ),
+            None => "没有闹钟".into(),
+        };
+        alarms_ui.set_next_alarm(next_alarm.into());
+    }
+
+    pub fn alarms(&self) -> Vec<Alarm> {
+        self.scheduler.borrow().alarms().to_vec()
+    }
+
+    /// Replace the whole list, it's saved right away.
+    pub fn replace(&self, alarms: Vec<Alarm>) {
+        let mut scheduler = self.scheduler.borrow_mut();
+        scheduler.set_alarms(alarms);
+        self.changed(&mut scheduler);
+        drop(scheduler);
+        self.show();
+    }
+
+    /// Show the ring face with `label` and start the ringer, any touch button stops it.
+    pub fn ring(&self, label: &str) {
+        let app_ui = self.ui.unwrap();
+        let alarms_ui = app_ui.global::<Alarms>();
+        alarms_ui.set_ring_label(label.into());
+        alarms_ui.set_ringing(true);
+        let _ = self.ringer.send(RingerControl::Ring);
+    }
+
+    /// Ring whatever is due at `now`, true if it does. The RTC is armed for the next one and the
+    /// list is shown again when the minute changes, since the next alarm may have moved on.
+    pub fn update(&self, now: u64, minute_changed: bool) -> bool {
+        let app_ui = self.ui.unwrap();
+        let alarms_ui = app_ui.global::<Alarms>();
+        let mut scheduler = self.scheduler.borrow_mut();
+        let due = scheduler.take_due(now);
+        if let Some(reason) = due {
+            log::info!("Ringing: {:?}", reason);
+            let label = match reason {
+                RingReason::Alarm(alarm) => {
+                    // a one-shot alarm is disabled now
+                    self.save(scheduler.alarms());
+                    format!("闹钟 {:02}:{:02}", alarm.hour, alarm.minute)
+                }
+                RingReason::Countdown => {
+                    alarms_ui.set_countdown_running(false);
+                    "倒计时结束".into()
+                }
+            };
+            self.ring(&label);
+        }
+        if let Some(remaining) = scheduler.countdown_remaining(now) {
+            alarms_ui.set_countdown_remaining(
+                format!("{:02}:{:02}", remaining / 60, remaining % 60).into(),
+            );
+        }
+        if due.is_some() || minute_changed {
+            self.arm(&mut scheduler);
+            drop(scheduler);
+            self.show();
+        }
+        due.is_some()
+    }
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    scheduler: AlarmScheduler,
+    shared_rtc: &'static SharedRtc,
+    nvs_partition: &EspDefaultNvsPartition,
+    ringer: &SyncSender<RingerControl>,
+    haptics: &SyncSender<HapticPattern>,
+) -> Rc<AlarmsPage> {
+    let alarms_ui = ui.global::<Alarms>();
+    alarms_ui.set_repeat_options(ModelRc::new(VecModel::from(
+        REPEAT_OPTIONS
+            .iter()
+            .map(|(label, _)| SharedString::from(*label))
+            .collect::<Vec<_>>(),
+    )));
+    let model = Rc::new(VecModel::<AlarmView>::default());
+    alarms_ui.set_alarms(model.clone().into());
+    let page = Rc::new(AlarmsPage {
+        ui: ui.as_weak(),
+        scheduler: RefCell::new(scheduler),
+        model,
+        shared_rtc,
+        nvs_partition: nvs_partition.clone(),
+        ringer: ringer.clone(),
+    });
+    page.arm(&mut page.scheduler.borrow_mut());
+    page.show();
+    let app_ui_weak = ui.as_weak();
+    let add_page = page.clone();
+    let haptics = haptics.clone();
+    alarms_ui.on_add(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let alarms_ui = app_ui.global::<Alarms>();
+        let alarm = Alarm {
+            hour: alarms_ui.get_hour().clamp(0, 23) as u8,
+            minute: alarms_ui.get_minute().clamp(0, 59) as u8,
+            weekdays: usize::try_from(alarms_ui.get_repeat_index())
+                .ok()
+                .and_then(|index| REPEAT_OPTIONS.get(index))
+                .map(|(_, weekdays)| *weekdays)
+                .unwrap_or(0),
+            enabled: true,
+        };
+        let mut scheduler = add_page.scheduler.borrow_mut();
+        if !scheduler.add(alarm) {
+            let _ = haptics.try_send(HapticPattern::Error);
+            return;
+        }
+        add_page.changed(&mut scheduler);
+        drop(scheduler);
+        add_page.show();
+    });
+    let remove_page = page.clone();
+    alarms_ui.on_remove(move |index| {
+        let mut scheduler = remove_page.scheduler.borrow_mut();
+        scheduler.remove(index as usize);
+        remove_page.changed(&mut scheduler);
+        drop(scheduler);
+        remove_page.show();
+    });
+    let enable_page = page.clone();
+    alarms_ui.on_set_enabled(move |index, enabled| {
+        let mut scheduler = enable_page.scheduler.borrow_mut();
+        scheduler.set_enabled(index as usize, enabled);
+        enable_page.changed(&mut scheduler);
+        drop(scheduler);
+        enable_page.show();
+    });
+    let app_ui_weak = ui.as_weak();
+    let countdown_page = page.clone();
+    alarms_ui.on_start_countdown(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let alarms_ui = app_ui.global::<Alarms>();
+        let minutes = alarms_ui.get_countdown_minutes().max(1) as u64;
+        let mut scheduler = countdown_page.scheduler.borrow_mut();
+        scheduler.start_countdown(utils::now_timestamp() as u64, minutes * 60);
+        countdown_page.arm(&mut scheduler);
+        alarms_ui.set_countdown_running(true);
+    });
+    let app_ui_weak = ui.as_weak();
+    let cancel_page = page.clone();
+    alarms_ui.on_cancel_countdown(move || {
+        let mut scheduler = cancel_page.scheduler.borrow_mut();
+        scheduler.cancel_countdown();
+        cancel_page.arm(&mut scheduler);
+        app_ui_weak
+            .unwrap()
+            .global::<Alarms>()
+            .set_countdown_running(false);
+    });
+    let app_ui_weak = ui.as_weak();
+    let ringer = ringer.clone();
+    alarms_ui.on_stop_ringing(move || {
+        let _ = ringer.send(RingerControl::Stop);
+        app_ui_weak.unwrap().global::<Alarms>().set_ringing(false);
+    });
+    page
+}
@@ -0,0 +1,127 @@
+//! Charger settings page, applied to the PMU and saved when the apply button is pressed.
+
+use std::{cell::RefCell, sync::mpsc::SyncSender};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel};
+
+use crate::{
+    haptics::HapticPattern,
+    pmu::{
+        charger::{
+            self, option_index, ChargerConfig, CONSTANT_CURRENT_OPTIONS,
+            INPUT_CURRENT_LIMIT_OPTIONS, PRECHARGE_CURRENT_OPTIONS, TERMINATION_VOLTAGE_OPTIONS,
+        },
+        Axp2101Registers,
+    },
+    ChargerSettings, GadgetMainWindow,
+};
+
+use super::I2cDevice;
+
+/// Build the labels of a `(value, raw)` option table for the UI.
+fn option_labels(options: &[(u16, u8)], unit: &str) -> ModelRc<SharedString> {
+    let labels: Vec<SharedString> = options
+        .iter()
+        .map(|(value, _)| format!("{}{}", value, unit).into())
+        .collect();
+    ModelRc::new(VecModel::from(labels))
+}
+
+/// Pick the value selected in the UI, `fallback` is used if nothing is selected.
+fn option_at(options: &[(u16, u8)], index: i32, fallback: u16) -> u16 {
+    usize::try_from(index)
+        .ok()
+        .and_then(|index| options.get(index))
+        .map(|(value, _)| *value)
+        .unwrap_or(fallback)
+}
+
+/// Select the options of `config`, it's applied by the apply button.
+pub fn show_config(settings: &ChargerSettings, config: &ChargerConfig) {
+    settings
+        .set_constant_current_index(
+            option_index(&CONSTANT_CURRENT_OPTIONS, config.constant_current) as i32,
+        );
+    settings.set_termination_voltage_index(option_index(
+        &TERMINATION_VOLTAGE_OPTIONS,
+        config.termination_voltage,
+    ) as i32);
+    settings.set_precharge_current_index(option_index(
+        &PRECHARGE_CURRENT_OPTIONS,
+        config.precharge_current,
+    ) as i32);
+    settings.set_input_current_limit_index(option_index(
+        &INPUT_CURRENT_LIMIT_OPTIONS,
+        config.input_current_limit,
+    ) as i32);
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    config: &ChargerConfig,
+    registers: Axp2101Registers<I2cDevice>,
+    nvs_partition: &EspDefaultNvsPartition,
+    haptics: &SyncSender<HapticPattern>,
+) {
+    let charger_settings = ui.global::<ChargerSettings>();
+    charger_settings.set_constant_current_options(option_labels(&CONSTANT_CURRENT_OPTIONS, "mA"));
+    charger_settings
+        .set_termination_voltage_options(option_labels(&TERMINATION_VOLTAGE_OPTIONS, "mV"));
+    charger_settings.set_precharge_current_options(option_labels(&PRECHARGE_CURRENT_OPTIONS, "mA"));
+    charger_settings
+        .set_input_current_limit_options(option_labels(&INPUT_CURRENT_LIMIT_OPTIONS, "mA"));
+    show_config(&charger_settings, config);
+    let app_ui_weak = ui.as_weak();
+    let registers = RefCell::new(registers);
+    let nvs_partition = nvs_partition.clone();
+    let haptics = haptics.clone();
+    charger_settings.on_apply(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let settings = app_ui.global::<ChargerSettings>();
+        let fallback = ChargerConfig::default();
+        let config = ChargerConfig {
+            constant_current: option_at(
+                &CONSTANT_CURRENT_OPTIONS,
+                settings.get_constant_current_index(),
+                fallback.constant_current,
+            ),
+            termination_voltage: option_at(
+                &TERMINATION_VOLTAGE_OPTIONS,
+                settings.get_termination_voltage_index(),
+                fallback.termination_voltage,
+            ),
+            precharge_current: option_at(
+                &PRECHARGE_CURRENT_OPTIONS,
+                settings.get_precharge_current_index(),
+                fallback.precharge_current,
+            ),
+            input_current_limit: option_at(
+                &INPUT_CURRENT_LIMIT_OPTIONS,
+                settings.get_input_current_limit_index(),
+                fallback.input_current_limit,
+            ),
+        }
+        .sanitized();
+        let mut registers = registers.borrow_mut();
+        if let Err(e) = config.apply(&mut *registers) {
+            log::error!("Failed to configure charger: {:?}", e);
+            let _ = haptics.try_send(HapticPattern::Error);
+            return;
+        }
+        match ChargerConfig::read(&mut *registers) {
+            Ok(actual) => log::info!("Charger configured: {:?}", actual),
+            Err(e) => log::warn!("Failed to read back charger configuration: {:?}", e),
+        }
+        match EspNvs::new(nvs_partition.clone(), charger::NVS_NAMESPACE, true) {
+            Ok(mut nvs) => {
+                if let Err(e) = config.store(&mut nvs) {
+                    log::error!("Failed to save charger configuration: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to open charger storage: {:?}", e),
+        }
+        show_config(&settings, &config);
+        let _ = haptics.try_send(HapticPattern::Double);
+    });
+}
@@ -0,0 +1,58 @@
+//! Clock page, setting the RTC and the system time.
+
+use std::sync::mpsc::SyncSender;
+
+use slint::ComponentHandle;
+
+use crate::{clock::DateTime, haptics::HapticPattern, rtc, utils, Clock, GadgetMainWindow};
+
+use super::SharedRtc;
+
+/// Fill the fields with the current time.
+fn load(ui: &GadgetMainWindow) {
+    let clock_ui = ui.global::<Clock>();
+    let datetime = DateTime::from_timestamp(utils::now_timestamp() as u64).sanitized();
+    clock_ui.set_year(datetime.year as i32);
+    clock_ui.set_month(datetime.month as i32);
+    clock_ui.set_day(datetime.day as i32);
+    clock_ui.set_hour(datetime.hour as i32);
+    clock_ui.set_minute(datetime.minute as i32);
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    valid: bool,
+    shared_rtc: &'static SharedRtc,
+    haptics: &SyncSender<HapticPattern>,
+) {
+    let clock_ui = ui.global::<Clock>();
+    clock_ui.set_valid(valid);
+    load(ui);
+    let app_ui_weak = ui.as_weak();
+    clock_ui.on_load(move || load(&app_ui_weak.unwrap()));
+    let app_ui_weak = ui.as_weak();
+    let haptics = haptics.clone();
+    clock_ui.on_apply(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let clock_ui = app_ui.global::<Clock>();
+        let to_field = |value: i32| value.clamp(0, u8::MAX as i32) as u8;
+        let datetime = DateTime {
+            year: clock_ui.get_year().clamp(0, u16::MAX as i32) as u16,
+            month: to_field(clock_ui.get_month()),
+            day: to_field(clock_ui.get_day()),
+            hour: to_field(clock_ui.get_hour()),
+            minute: to_field(clock_ui.get_minute()),
+            second: 0,
+            weekday: 0,
+        };
+        if let Err(e) = rtc::set_time(&mut shared_rtc.lock().unwrap(), &datetime) {
+            log::error!("Failed to set RTC: {:?}", e);
+            let _ = haptics.try_send(HapticPattern::Error);
+            return;
+        }
+        log::info!("Time set: {:?}", datetime.sanitized());
+        clock_ui.set_valid(true);
+        load(&app_ui);
+        let _ = haptics.try_send(HapticPattern::Double);
+    });
+}
@@ -0,0 +1,32 @@
+//! Diagnostics page, the boot records and the state of the power rails.
+
+use embedded_hal_bus::i2c::MutexDevice as SharedI2cBus;
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel};
+
+use crate::{bootlog::BootRecord, pmu::rails::RailManager, Diagnostics, GadgetMainWindow};
+
+use super::I2cBus;
+
+pub fn bind(ui: &GadgetMainWindow, boot_records: &[BootRecord], i2c_bus: &'static I2cBus) {
+    let boot_record_lines: Vec<SharedString> = boot_records
+        .iter()
+        .rev()
+        .map(|record| record.to_string().into())
+        .collect();
+    let app_ui_weak = ui.as_weak();
+    let refresh = move || {
+        let mut lines = vec![SharedString::from("启动记录:")];
+        lines.extend(boot_record_lines.iter().cloned());
+        lines.push("电源轨:".into());
+        match RailManager::new(SharedI2cBus::new(i2c_bus)).dump() {
+            Ok(states) => lines.extend(states.iter().map(|state| state.to_string().into())),
+            Err(e) => lines.push(format!("{:?}", e).into()),
+        }
+        app_ui_weak
+            .unwrap()
+            .global::<Diagnostics>()
+            .set_lines(ModelRc::new(VecModel::from(lines)));
+    };
+    refresh();
+    ui.global::<Diagnostics>().on_refresh(refresh);
+}
@@ -0,0 +1,155 @@
+//! Energy log page, the battery history from the task, its interval and the CSV export.
+
+use std::{cell::RefCell, fs::File, io::BufWriter, path::Path, rc::Rc, sync::mpsc::SyncSender};
+
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel, Weak};
+
+use crate::{
+    energylog::{
+        self, EnergyLogControl, EnergyRecord, DEFAULT_INTERVAL, HISTORY_CAPACITY, INTERVAL_OPTIONS,
+    },
+    haptics::HapticPattern,
+    pmu::battery::ChargeState,
+    storage::{self, SdCard},
+    EnergyLog, GadgetMainWindow,
+};
+
+fn charge_state_label(state: ChargeState) -> &'static str {
+    match state {
+        ChargeState::Trickle | ChargeState::PreCharge => "预充电",
+        ChargeState::ConstantCurrent | ChargeState::ConstantVoltage => "充电中",
+        ChargeState::Done => "已充满",
+        ChargeState::NotCharging => "放电中",
+    }
+}
+
+fn interval_index(seconds: u32) -> i32 {
+    INTERVAL_OPTIONS
+        .iter()
+        .position(|option| *option == seconds)
+        .unwrap_or(0) as i32
+}
+
+/// The interval selected on the page, in seconds.
+pub fn selected_interval(ui: &GadgetMainWindow) -> u32 {
+    usize::try_from(ui.global::<EnergyLog>().get_interval_index())
+        .ok()
+        .and_then(|index| INTERVAL_OPTIONS.get(index))
+        .copied()
+        .unwrap_or(DEFAULT_INTERVAL)
+}
+
+/// Select the interval and hand it to the task, which saves it.
+pub fn apply_interval(ui: &GadgetMainWindow, seconds: u32) {
+    let energy_log = ui.global::<EnergyLog>();
+    let index = interval_index(seconds);
+    energy_log.set_interval_index(index);
+    energy_log.invoke_interval_changed(index);
+}
+
+pub struct EnergyLogPage {
+    ui: Weak<GadgetMainWindow>,
+    battery_history: Rc<VecModel<f32>>,
+    /// The latest history from the task, for export.
+    records: Rc<RefCell<Vec<EnergyRecord>>>,
+}
+
+impl EnergyLogPage {
+    /// Show the whole history, as sent by the task.
+    pub fn show(&self, records: Vec<EnergyRecord>) {
+        self.battery_history.set_vec(
+            records
+                .iter()
+                .map(|record| record.battery.percent as f32 / 100.0)
+                .collect::<Vec<f32>>(),
+        );
+        let summary = match records.last() {
+            Some(record) => format!(
+                "{}% {:.2}V {} ({}条)",
+                record.battery.percent,
+                record.battery.millivolts as f32 / 1000.0,
+                charge_state_label(record.battery.charge_state),
+                records.len(),
+            ),
+            None => "暂无记录".into(),
+        };
+        self.ui
+            .unwrap()
+            .global::<EnergyLog>()
+            .set_summary(summary.into());
+        *self.records.borrow_mut() = records;
+    }
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    interval: u32,
+    control: &SyncSender<EnergyLogControl>,
+    sd_card: &Rc<RefCell<SdCard>>,
+    haptics: &SyncSender<HapticPattern>,
+) -> EnergyLogPage {
+    let energy_log = ui.global::<EnergyLog>();
+    let battery_history = Rc::new(VecModel::<f32>::default());
+    energy_log.set_battery_history(ModelRc::from(battery_history.clone()));
+    energy_log.set_history_capacity(HISTORY_CAPACITY as i32);
+    let interval_labels: Vec<SharedString> = INTERVAL_OPTIONS
+        .iter()
+        .map(|seconds| format!("{}分钟", seconds / 60).into())
+        .collect();
+    energy_log.set_interval_options(ModelRc::new(VecModel::from(interval_labels)));
+    energy_log.set_interval_index(interval_index(interval));
+    let interval_control = control.clone();
+    energy_log.on_interval_changed(move |index| {
+        if let Some(seconds) = usize::try_from(index)
+            .ok()
+            .and_then(|index| INTERVAL_OPTIONS.get(index))
+        {
+            let _ = interval_control.send(EnergyLogControl::SetInterval(*seconds));
+        }
+    });
+    let records: Rc<RefCell<Vec<EnergyRecord>>> = Rc::new(RefCell::new(Vec::new()));
+    let app_ui_weak = ui.as_weak();
+    let export_records = records.clone();
+    let sd_card = sd_card.clone();
+    let haptics = haptics.clone();
+    energy_log.on_export(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let energy_log = app_ui.global::<EnergyLog>();
+        let records = export_records.borrow();
+        if !sd_card.borrow().is_mounted() {
+            // the serial console is the only way out without a card
+            log::info!("Energy history:");
+            log::info!("{}", energylog::CSV_HEADER);
+            records
+                .iter()
+                .for_each(|record| log::info!("{}", record.to_csv()));
+            energy_log.set_status("未挂载存储卡, 已输出到串口".into());
+            return;
+        }
+        let path = Path::new(storage::MOUNT_POINT).join(energylog::EXPORT_FILE);
+        match File::create(&path)
+            .and_then(|file| energylog::write_csv(BufWriter::new(file), &records))
+        {
+            Ok(_) => {
+                energy_log.set_status(
+                    format!("已导出 {} 条到 {}", records.len(), energylog::EXPORT_FILE).into(),
+                );
+                let _ = haptics.try_send(HapticPattern::Double);
+            }
+            Err(e) => {
+                log::error!("Failed to export energy history: {:?}", e);
+                energy_log.set_status(format!("导出失败: {}", e.kind()).into());
+                let _ = haptics.try_send(HapticPattern::Error);
+            }
+        }
+    });
+    let clear_control = control.clone();
+    energy_log.on_clear(move || {
+        let _ = clear_control.send(EnergyLogControl::Clear);
+    });
+    EnergyLogPage {
+        ui: ui.as_weak(),
+        battery_history,
+        records,
+    }
+}
@@ -0,0 +1,505 @@
+//! File browser page, with the viewers of CSV tables, images and text.
+
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::SyncSender,
+    time::Duration,
+};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::{ComponentHandle, ModelRc, StandardListViewItem, TableColumn, VecModel, Weak};
+
+use crate::{
+    files::{self, CsvTable, FileBrowser, Viewer},
+    haptics::HapticPattern,
+    images::{DecodedImage, ImageControl, ImageList},
+    inputevent::InputEvent,
+    platform::duration_since_start,
+    storage::{self, SdCard},
+    textview::{self, ConversionEvent, GlyphFont, ReadingPositions, TextDocument},
+    FileEntry, FileViewer, Files, GadgetMainWindow, MotionLogger, TextPage,
+};
+
+fn show_csv(files_ui: &Files, name: &str, table: CsvTable) {
+    let columns: Vec<TableColumn> = table
+        .header
+        .iter()
+        .map(|title| {
+            let mut column = TableColumn::default();
+            column.title = title.as_str().into();
+            column
+        })
+        .collect();
+    let rows: Vec<ModelRc<StandardListViewItem>> = table
+        .rows
+        .iter()
+        .map(|row| {
+            ModelRc::new(VecModel::from(
+                row.iter()
+                    .map(|cell| StandardListViewItem::from(cell.as_str()))
+                    .collect::<Vec<_>>(),
+            ))
+        })
+        .collect();
+    let title = if table.truncated {
+        format!("{} (前 {} 行)", name, files::CSV_MAX_ROWS)
+    } else {
+        name.to_string()
+    };
+    files_ui.set_viewer_title(title.into());
+    files_ui.set_csv_columns(ModelRc::new(VecModel::from(columns)));
+    files_ui.set_csv_rows(ModelRc::new(VecModel::from(rows)));
+    files_ui.set_viewer(FileViewer::Csv);
+}
+
+/// The page is drawn by the viewer, through render-text.
+fn redraw_text(files_ui: &Files) {
+    files_ui.set_text_revision(files_ui.get_text_revision().wrapping_add(1));
+}
+
+pub struct FilesPage {
+    ui: Weak<GadgetMainWindow>,
+    browser: RefCell<FileBrowser>,
+    sd_card: Rc<RefCell<SdCard>>,
+    images: RefCell<ImageList>,
+    image_decoder: SyncSender<ImageControl>,
+    /// The font is loaded on first use, the very first time it's converted in the background,
+    /// and the file waits for it.
+    glyph_font: RefCell<Option<GlyphFont>>,
+    pending_text: RefCell<Option<(PathBuf, String)>>,
+    font_directory: PathBuf,
+    font_events: SyncSender<InputEvent>,
+    /// The text file open in the viewer, where reading stopped is saved when the viewer is
+    /// closed or left, and before power off.
+    text_document: RefCell<Option<TextDocument>>,
+    reading_positions: RefCell<ReadingPositions>,
+    nvs_partition: EspDefaultNvsPartition,
+    haptics: SyncSender<HapticPattern>,
+}
+
+impl FilesPage {
+    /// Read the folder again and show it, `status` is shown unless something went wrong.
+    fn show_files(&self, files_ui: &Files, browser: &mut FileBrowser, status: &str) {
+        let result = if self.sd_card.borrow().is_mounted() {
+            browser
+                .refresh()
+                .map_err(|e| format!("无法读取文件夹: {}", e))
+        } else {
+            Err("请先挂载存储卡".to_string())
+        };
+        let entries: Vec<FileEntry> = match result {
+            Ok(()) => browser
+                .entries()
+                .iter()
+                .map(|entry| FileEntry {
+                    name: entry.name.as_str().into(),
+                    detail: entry.detail().into(),
+                    is_dir: entry.is_dir,
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let status = match result {
+            Err(e) => e,
+            Ok(()) if status.is_empty() && entries.is_empty() => "空文件夹".into(),
+            Ok(()) => status.into(),
+        };
+        files_ui.set_path(browser.display_path().into());
+        files_ui.set_entries(ModelRc::new(VecModel::from(entries)));
+        files_ui.set_selected(-1);
+        files_ui.set_status(status.into());
+    }
+
+    /// The image is shown once decoded, see [`FilesPage::show_decoded`].
+    fn show_image(&self, files_ui: &Files, images: &ImageList) {
+        let Some(path) = images.current() else {
+            return;
+        };
+        files_ui.set_viewer_title(
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .as_ref()
+                .into(),
+        );
+        files_ui.set_slideshow(images.is_slideshow());
+        // free the previous one first, PSRAM is tight
+        files_ui.set_image(slint::Image::default());
+        files_ui.set_image_info(format!("{} 解码中", images.position()).into());
+        let _ = self
+            .image_decoder
+            .send(ImageControl::Decode(path.to_path_buf()));
+    }
+
+    fn load_font(&self) -> Result<(), String> {
+        let mut font = self.glyph_font.borrow_mut();
+        if font.is_some() {
+            return Ok(());
+        }
+        GlyphFont::open(&self.font_directory)
+            .map(|value| *font = Some(value))
+            .map_err(|e| {
+                log::warn!("Failed to open font: {:?}", e);
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    format!(
+                        "未找到字体, 请将 unifont.hex 放入存储卡的 {} 文件夹",
+                        textview::FONT_DIRECTORY
+                    )
+                } else {
+                    format!("字体读取失败: {}", e)
+                }
+            })
+    }
+
+    fn open_text(&self, files_ui: &Files, path: &Path, name: &str) -> Result<(), String> {
+        let mut document = TextDocument::load(path).map_err(|e| format!("无法打开文件: {}", e))?;
+        if let Some(offset) = self.reading_positions.borrow().get(path) {
+            document.seek(offset);
+        }
+        *self.text_document.borrow_mut() = Some(document);
+        files_ui.set_viewer_title(name.into());
+        files_ui.set_viewer(FileViewer::Text);
+        redraw_text(files_ui);
+        Ok(())
+    }
+
+    fn save_reading_position(&self, document: &TextDocument) {
+        let mut positions = self.reading_positions.borrow_mut();
+        if !positions.set(document.path(), document.offset()) {
+            return;
+        }
+        if let Err(e) = EspNvs::new(self.nvs_partition.clone(), textview::NVS_NAMESPACE, true)
+            .and_then(|mut nvs| positions.store(&mut nvs))
+        {
+            log::error!("Failed to save reading positions: {:?}", e);
+        }
+    }
+
+    fn open(&self, files_ui: &Files, index: usize) {
+        let mut browser = self.browser.borrow_mut();
+        if browser.enter(index) {
+            self.show_files(files_ui, &mut browser, "");
+            return;
+        }
+        let (Some(entry), Some(path)) = (browser.entry(index), browser.path_of(index)) else {
+            return;
+        };
+        let result = match files::viewer_for(&path) {
+            Some(Viewer::Csv) => files::read_csv(&path)
+                .map(|table| show_csv(files_ui, &entry.name, table))
+                .map_err(|e| format!("无法打开文件: {}", e)),
+            Some(Viewer::Image) => {
+                let paths = browser.paths_for(Viewer::Image);
+                let position = paths.iter().position(|other| *other == path).unwrap_or(0);
+                let mut images = self.images.borrow_mut();
+                *images = ImageList::new(paths, position);
+                files_ui.set_viewer(FileViewer::Image);
+                self.show_image(files_ui, &images);
+                Ok(())
+            }
+            Some(Viewer::Text) if textview::needs_conversion(&self.font_directory) => {
+                // the file is opened once the font is ready
+                let pending = Some((path, entry.name.to_string()));
+                if self.pending_text.replace(pending).is_none() {
+                    textview::spawn_font_conversion(
+                        self.font_directory.clone(),
+                        self.font_events.clone(),
+                    );
+                }
+                files_ui.set_status("正在转换字体, 仅首次需要".into());
+                Ok(())
+            }
+            Some(Viewer::Text) => self
+                .load_font()
+                .and_then(|_| self.open_text(files_ui, &path, &entry.name)),
+            None => Err("没有可打开此类文件的查看器".to_string()),
+        };
+        if let Err(e) = result {
+            files_ui.set_status(e.into());
+            let _ = self.haptics.try_send(HapticPattern::Error);
+        }
+    }
+
+    /// Delete or rename the file at `index`, `change` returns the status to show.
+    fn change(&self, index: i32, change: impl FnOnce(&Path) -> String) {
+        let app_ui = self.ui.unwrap();
+        let files_ui = app_ui.global::<Files>();
+        // the log being written may be the one
+        if app_ui.global::<MotionLogger>().get_running() {
+            files_ui.set_status("请先停止数据记录".into());
+            let _ = self.haptics.try_send(HapticPattern::Error);
+            return;
+        }
+        let mut browser = self.browser.borrow_mut();
+        let Some(path) = usize::try_from(index)
+            .ok()
+            .and_then(|index| browser.path_of(index))
+        else {
+            return;
+        };
+        let status = change(&path);
+        self.show_files(&files_ui, &mut browser, &status);
+    }
+
+    /// Show an image from the decoder, unless flipped on or closed in the meantime.
+    pub fn show_decoded(&self, decoded: DecodedImage) {
+        let images = self.images.borrow();
+        if images.current() != Some(decoded.path.as_path()) {
+            return;
+        }
+        let app_ui = self.ui.unwrap();
+        let files_ui = app_ui.global::<Files>();
+        match decoded.result {
+            Ok(buffer) => {
+                files_ui.set_image(slint::Image::from_rgb8(buffer));
+                files_ui.set_image_info(images.position().into());
+            }
+            Err(e) => {
+                log::warn!("Failed to decode {}: {:?}", decoded.path.display(), e);
+                files_ui.set_image_info(format!("{} {}", images.position(), e).into());
+            }
+        }
+    }
+
+    /// Follow the font conversion, the waiting file is opened once it's done.
+    pub fn show_conversion(&self, event: ConversionEvent) {
+        let app_ui = self.ui.unwrap();
+        let files_ui = app_ui.global::<Files>();
+        match event {
+            ConversionEvent::Progress(percent) => {
+                files_ui.set_status(format!("正在转换字体 {}%", percent).into());
+            }
+            ConversionEvent::Finished(result) => {
+                let Some((path, name)) = self.pending_text.take() else {
+                    return;
+                };
+                let opened = result
+                    .map_err(|kind| format!("字体转换失败: {}", kind))
+                    .and_then(|_| self.load_font())
+                    .and_then(|_| self.open_text(&files_ui, &path, &name));
+                match opened {
+                    Ok(()) => files_ui.set_status("字体转换完成".into()),
+                    Err(e) => {
+                        files_ui.set_status(e.into());
+                        let _ = self.haptics.try_send(HapticPattern::Error);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The slideshow stops once the page is left, the reading position is saved as if the
+    /// viewer was closed.
+    pub fn leave(&self, now: Duration) {
+        self.images.borrow_mut().set_slideshow(false, now);
+        self.ui.unwrap().global::<Files>().set_slideshow(false);
+        self.save_pending();
+    }
+
+    /// Flip to the next image of the slideshow when it's due.
+    pub fn update(&self, now: Duration) {
+        let mut images = self.images.borrow_mut();
+        if images.take_due(now) {
+            images.next();
+            self.show_image(&self.ui.unwrap().global::<Files>(), &images);
+        }
+    }
+
+    /// Save where reading stopped, before power off.
+    pub fn save_pending(&self) {
+        if let Some(document) = self.text_document.borrow().as_ref() {
+            self.save_reading_position(document);
+        }
+    }
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    sd_card: &Rc<RefCell<SdCard>>,
+    image_decoder: SyncSender<ImageControl>,
+    font_events: SyncSender<InputEvent>,
+    nvs_partition: &EspDefaultNvsPartition,
+    haptics: &SyncSender<HapticPattern>,
+) -> Rc<FilesPage> {
+    let page = Rc::new(FilesPage {
+        ui: ui.as_weak(),
+        browser: RefCell::new(FileBrowser::new(storage::MOUNT_POINT)),
+        sd_card: sd_card.clone(),
+        images: RefCell::new(ImageList::default()),
+        image_decoder,
+        glyph_font: RefCell::new(None),
+        pending_text: RefCell::new(None),
+        font_directory: Path::new(storage::MOUNT_POINT).join(textview::FONT_DIRECTORY),
+        font_events,
+        text_document: RefCell::new(None),
+        reading_positions: RefCell::new(
+            EspNvs::new(nvs_partition.clone(), textview::NVS_NAMESPACE, false)
+                .map(|nvs| ReadingPositions::load(&nvs))
+                .unwrap_or_default(),
+        ),
+        nvs_partition: nvs_partition.clone(),
+        haptics: haptics.clone(),
+    });
+    let files_ui = ui.global::<Files>();
+    files_ui.set_text_line_height(textview::LINE_HEIGHT as f32);
+    let app_ui_weak = ui.as_weak();
+    let refresh_page = page.clone();
+    files_ui.on_refresh(move || {
+        let app_ui = app_ui_weak.unwrap();
+        refresh_page.show_files(
+            &app_ui.global::<Files>(),
+            &mut refresh_page.browser.borrow_mut(),
+            "",
+        );
+    });
+    let app_ui_weak = ui.as_weak();
+    let up_page = page.clone();
+    files_ui.on_up(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let mut browser = up_page.browser.borrow_mut();
+        if browser.up() {
+            up_page.show_files(&app_ui.global::<Files>(), &mut browser, "");
+        }
+    });
+    let app_ui_weak = ui.as_weak();
+    let open_page = page.clone();
+    files_ui.on_open(move |index| {
+        let app_ui = app_ui_weak.unwrap();
+        if let Ok(index) = usize::try_from(index) {
+            open_page.open(&app_ui.global::<Files>(), index);
+        }
+    });
+    let delete_page = page.clone();
+    files_ui.on_delete(move |index| {
+        let haptics = &delete_page.haptics;
+        delete_page.change(index, |path| match files::delete(path) {
+            Ok(()) => {
+                let _ = haptics.try_send(HapticPattern::Double);
+                format!(
+                    "已删除 {}",
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                )
+            }
+            Err(e) => {
+                log::error!("Failed to delete {}: {:?}", path.display(), e);
+                let _ = haptics.try_send(HapticPattern::Error);
+                format!("删除失败: {}", e)
+            }
+        });
+    });
+    let rename_page = page.clone();
+    files_ui.on_rename(move |index, name| {
+        let haptics = &rename_page.haptics;
+        rename_page.change(index, |path| match files::rename(path, &name) {
+            Ok(_) => {
+                let _ = haptics.try_send(HapticPattern::Double);
+                format!("已重命名为 {}", name)
+            }
+            Err(e) => {
+                log::error!("Failed to rename {}: {:?}", path.display(), e);
+                let _ = haptics.try_send(HapticPattern::Error);
+                match e.kind() {
+                    std::io::ErrorKind::InvalidInput => "名称无效".to_string(),
+                    std::io::ErrorKind::AlreadyExists => "已存在同名文件".to_string(),
+                    _ => format!("重命名失败: {}", e),
+                }
+            }
+        });
+    });
+    let app_ui_weak = ui.as_weak();
+    let close_page = page.clone();
+    files_ui.on_close_viewer(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let files_ui = app_ui.global::<Files>();
+        files_ui.set_viewer(FileViewer::None);
+        files_ui.set_csv_columns(ModelRc::default());
+        files_ui.set_csv_rows(ModelRc::default());
+        files_ui.set_image(slint::Image::default());
+        *close_page.images.borrow_mut() = ImageList::default();
+        // remember where reading stopped
+        if let Some(document) = close_page.text_document.borrow_mut().take() {
+            close_page.save_reading_position(&document);
+        }
+    });
+    let app_ui_weak = ui.as_weak();
+    let previous_page = page.clone();
+    files_ui.on_previous_image(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let mut images = previous_page.images.borrow_mut();
+        images.previous();
+        previous_page.show_image(&app_ui.global::<Files>(), &images);
+    });
+    let app_ui_weak = ui.as_weak();
+    let next_page = page.clone();
+    files_ui.on_next_image(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let mut images = next_page.images.borrow_mut();
+        images.next();
+        next_page.show_image(&app_ui.global::<Files>(), &images);
+    });
+    let app_ui_weak = ui.as_weak();
+    let slideshow_page = page.clone();
+    files_ui.on_toggle_slideshow(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let mut images = slideshow_page.images.borrow_mut();
+        let enabled = !images.is_slideshow();
+        images.set_slideshow(enabled, duration_since_start());
+        app_ui.global::<Files>().set_slideshow(enabled);
+    });
+    let app_ui_weak = ui.as_weak();
+    let scroll_page = page.clone();
+    files_ui.on_scroll_text(move |lines| {
+        let app_ui = app_ui_weak.unwrap();
+        if let Some(document) = scroll_page.text_document.borrow_mut().as_mut() {
+            document.scroll_by(lines);
+            redraw_text(&app_ui.global::<Files>());
+        }
+    });
+    let app_ui_weak = ui.as_weak();
+    let paging_page = page.clone();
+    files_ui.on_page_text(move |pages| {
+        let app_ui = app_ui_weak.unwrap();
+        if let Some(document) = paging_page.text_document.borrow_mut().as_mut() {
+            document.scroll_by(pages * textview::PAGE_LINES as i32);
+            redraw_text(&app_ui.global::<Files>());
+        }
+    });
+    let app_ui_weak = ui.as_weak();
+    let jump_page = page.clone();
+    files_ui.on_jump_text(move || {
+        let app_ui = app_ui_weak.unwrap();
+        if let Some(document) = jump_page.text_document.borrow_mut().as_mut() {
+            document.jump();
+            redraw_text(&app_ui.global::<Files>());
+        }
+    });
+    let render_page = page.clone();
+    files_ui.on_render_text(move |width, _revision| {
+        let mut document = render_page.text_document.borrow_mut();
+        let mut font = render_page.glyph_font.borrow_mut();
+        let (Some(document), Some(font)) = (document.as_mut(), font.as_mut()) else {
+            return TextPage::default();
+        };
+        document.set_width(width as u32, font);
+        let mut info = document.position();
+        if document.is_truncated() {
+            info.push_str(" 已截断");
+        }
+        let image = match document.render(font) {
+            Ok(buffer) => slint::Image::from_rgb8(buffer),
+            Err(e) => {
+                log::warn!("Failed to render {}: {:?}", document.path().display(), e);
+                info = format!("{} 字体读取失败", info);
+                slint::Image::default()
+            }
+        };
+        TextPage {
+            image,
+            info: info.into(),
+            at_end: document.is_at_end(),
+        }
+    });
+    page
+}
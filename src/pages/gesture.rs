@@ -0,0 +1,70 @@
+//! Gesture page, the thresholds of the detectors, handed to the motion task and saved when the
+//! apply button is pressed.
+
+use std::sync::{Arc, Mutex};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::ComponentHandle;
+
+use crate::{
+    motion::gesture::{self, Gesture, GestureConfig},
+    GadgetMainWindow, GestureSettings,
+};
+
+/// Show the thresholds of `config`, they're applied by the apply button.
+pub fn show_config(gesture_ui: &GestureSettings, config: &GestureConfig) {
+    gesture_ui.set_shake(config.shake as i32);
+    gesture_ui.set_tap(config.tap as i32);
+    gesture_ui.set_flip(config.flip as i32);
+}
+
+pub fn show_gesture(ui: &GadgetMainWindow, gesture: Gesture) {
+    ui.global::<GestureSettings>().set_last_gesture(
+        match gesture {
+            Gesture::Shake => "摇晃",
+            Gesture::DoubleTap => "双击",
+            Gesture::FlipDown => "扣下",
+            Gesture::FlipUp => "翻起",
+        }
+        .into(),
+    );
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    config: &Arc<Mutex<GestureConfig>>,
+    nvs_partition: &EspDefaultNvsPartition,
+) {
+    let gesture_ui = ui.global::<GestureSettings>();
+    gesture_ui.set_shake_minimum(gesture::SHAKE_RANGE.0 as i32);
+    gesture_ui.set_shake_maximum(gesture::SHAKE_RANGE.1 as i32);
+    gesture_ui.set_tap_minimum(gesture::TAP_RANGE.0 as i32);
+    gesture_ui.set_tap_maximum(gesture::TAP_RANGE.1 as i32);
+    gesture_ui.set_flip_minimum(gesture::FLIP_RANGE.0 as i32);
+    gesture_ui.set_flip_maximum(gesture::FLIP_RANGE.1 as i32);
+    show_config(&gesture_ui, &config.lock().unwrap());
+    let app_ui_weak = ui.as_weak();
+    let gesture_state = config.clone();
+    let nvs_partition = nvs_partition.clone();
+    gesture_ui.on_apply(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let gesture_ui = app_ui.global::<GestureSettings>();
+        let to_threshold = |value: i32| value.clamp(0, u8::MAX as i32) as u8;
+        let config = GestureConfig {
+            shake: to_threshold(gesture_ui.get_shake()),
+            tap: to_threshold(gesture_ui.get_tap()),
+            flip: to_threshold(gesture_ui.get_flip()),
+        }
+        .sanitized();
+        *gesture_state.lock().unwrap() = config;
+        match EspNvs::new(nvs_partition.clone(), gesture::NVS_NAMESPACE, true) {
+            Ok(mut nvs) => {
+                if let Err(e) = gesture::store_config(&mut nvs, &config) {
+                    log::error!("Failed to save gesture thresholds: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to open gesture storage: {:?}", e),
+        }
+        show_config(&gesture_ui, &config);
+    });
+}
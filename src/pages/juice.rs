@@ -0,0 +1,198 @@
+//! Main page, the backlight, the transmit power and the jammer.
+//!
+//! The settings are saved by the super loop a while after they change, see
+//! [`settings::SAVE_DELAY`].
+
+use std::{cell::Cell, rc::Rc, sync::mpsc::SyncSender, time::Instant};
+
+use embedded_hal_bus::i2c::MutexDevice as SharedI2cBus;
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::{ComponentHandle, Weak};
+
+use crate::{
+    applejuice::JuicyTaskControl,
+    pmu::rails::{Rail, RailManager},
+    settings::{self, Settings},
+    GadgetMainWindow,
+};
+
+use super::I2cBus;
+
+const BACKLIGHT_MINIMUM_VOLTAGE: u16 = 2500;
+/// Maximum backlight voltage in docked mode, the clock should not light up the room.
+const DOCKED_BACKLIGHT_VOLTAGE: u16 = 2600;
+
+/// Backlight voltage after applying thermal throttling and docked mode to user's choice.
+fn backlight_target(voltage: u16, dimmed: bool, docked: bool) -> u16 {
+    if dimmed {
+        BACKLIGHT_MINIMUM_VOLTAGE
+    } else if docked {
+        voltage.min(DOCKED_BACKLIGHT_VOLTAGE)
+    } else {
+        voltage
+    }
+}
+
+pub struct MainPage {
+    ui: Weak<GadgetMainWindow>,
+    settings: Cell<Settings>,
+    changed: Cell<Option<Instant>>,
+    /// The backlight voltage chosen by user, and whether it's overridden by thermal throttling
+    /// or docked mode.
+    backlight_voltage: Cell<u16>,
+    backlight_dimmed: Cell<bool>,
+    backlight_docked: Cell<bool>,
+    i2c_bus: &'static I2cBus,
+    juicy: SyncSender<JuicyTaskControl>,
+    nvs_partition: EspDefaultNvsPartition,
+}
+
+impl MainPage {
+    fn show_sliders(&self, value: Settings) {
+        let app_ui = self.ui.unwrap();
+        app_ui.set_brightness(value.brightness as f32);
+        app_ui.set_transmission_power(value.transmission_power as f32);
+    }
+
+    fn update_backlight(&self) {
+        let voltage = backlight_target(
+            self.backlight_voltage.get(),
+            self.backlight_dimmed.get(),
+            self.backlight_docked.get(),
+        );
+        RailManager::new(SharedI2cBus::new(self.i2c_bus))
+            .set_voltage(Rail::Bldo1, voltage)
+            .unwrap();
+    }
+
+    /// Apply settings to the backlight and the BLE task, the sliders are left to the caller.
+    fn apply(&self, value: Settings) {
+        let previous = self.settings.replace(value);
+        if value.brightness != previous.brightness {
+            self.backlight_voltage.set(value.backlight_voltage());
+            self.update_backlight();
+        }
+        if value.transmission_power != previous.transmission_power {
+            self.juicy
+                .send(JuicyTaskControl::SetPower(value.transmission_power))
+                .unwrap();
+        }
+    }
+
+    fn save(&self, value: &Settings) {
+        match EspNvs::new(self.nvs_partition.clone(), settings::NVS_NAMESPACE, true) {
+            Ok(mut nvs) => {
+                if let Err(e) = settings::store(&mut nvs, value) {
+                    log::error!("Failed to save settings: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to open settings storage: {:?}", e),
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings.get()
+    }
+
+    /// Apply and show imported settings, saved right away, it's a single change.
+    pub fn import(&self, value: Settings) {
+        self.apply(value);
+        self.show_sliders(value);
+        self.changed.set(None);
+        self.save(&value);
+    }
+
+    /// Settings are saved once they stop changing.
+    pub fn save_if_due(&self) {
+        if self
+            .changed
+            .get()
+            .is_some_and(|changed| changed.elapsed() >= settings::SAVE_DELAY)
+        {
+            self.changed.set(None);
+            self.save(&self.settings.get());
+        }
+    }
+
+    /// Save a change not saved yet, before power off.
+    pub fn save_pending(&self) {
+        if self.changed.take().is_some() {
+            self.save(&self.settings.get());
+        }
+    }
+
+    /// Forget a change not saved yet, nothing may be written back over a factory reset.
+    pub fn discard_pending(&self) {
+        self.changed.set(None);
+    }
+
+    /// Dim the backlight for thermal throttling, or bring it back.
+    pub fn set_dimmed(&self, dimmed: bool) {
+        if dimmed != self.backlight_dimmed.get() {
+            self.backlight_dimmed.set(dimmed);
+            self.update_backlight();
+        }
+    }
+
+    pub fn is_docked(&self) -> bool {
+        self.backlight_docked.get()
+    }
+
+    /// Limit the backlight in docked mode, or bring it back.
+    pub fn set_docked(&self, docked: bool) {
+        self.backlight_docked.set(docked);
+        self.update_backlight();
+    }
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    settings: Settings,
+    i2c_bus: &'static I2cBus,
+    juicy: &SyncSender<JuicyTaskControl>,
+    nvs_partition: &EspDefaultNvsPartition,
+) -> Rc<MainPage> {
+    let page = Rc::new(MainPage {
+        ui: ui.as_weak(),
+        settings: Cell::new(settings),
+        changed: Cell::new(None),
+        backlight_voltage: Cell::new(settings.backlight_voltage()),
+        backlight_dimmed: Cell::new(false),
+        backlight_docked: Cell::new(false),
+        i2c_bus,
+        juicy: juicy.clone(),
+        nvs_partition: nvs_partition.clone(),
+    });
+    page.show_sliders(settings);
+    let brightness_page = page.clone();
+    ui.on_update_brightness(move |brightness| {
+        brightness_page.apply(
+            Settings {
+                brightness: brightness as u8,
+                ..brightness_page.settings.get()
+            }
+            .sanitized(),
+        );
+        brightness_page.changed.set(Some(Instant::now()));
+    });
+    let juicy_enable = juicy.clone();
+    let juicy_disable = juicy.clone();
+    ui.on_enable_jammer(move || {
+        juicy_enable.send(JuicyTaskControl::Start).unwrap();
+    });
+    ui.on_disable_jammer(move || {
+        juicy_disable.send(JuicyTaskControl::Stop).unwrap();
+    });
+    let power_page = page.clone();
+    ui.on_update_transmission_power(move |value| {
+        power_page.apply(
+            Settings {
+                transmission_power: value as u8,
+                ..power_page.settings.get()
+            }
+            .sanitized(),
+        );
+        power_page.changed.set(Some(Instant::now()));
+    });
+    page
+}
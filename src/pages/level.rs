@@ -0,0 +1,150 @@
+//! Level page, the bubble and the inclinometer, the touch buttons are handled by the UI.
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::mpsc::SyncSender,
+};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::{ComponentHandle, Weak};
+
+use crate::{
+    haptics::HapticPattern,
+    level::{self, Inclinometer},
+    motion::Orientation,
+    GadgetMainWindow, Level,
+};
+
+fn show(level_ui: &Level, inclinometer: &Inclinometer, current: Orientation) {
+    let reading = inclinometer.reading(current);
+    let (x, y) = level::bubble_position(reading);
+    level_ui.set_bubble_x(x);
+    level_ui.set_bubble_y(y);
+    level_ui.set_pitch(format!("{:.1}°", reading.pitch).into());
+    level_ui.set_roll(format!("{:.1}°", reading.roll).into());
+    level_ui.set_level(level::is_level(reading));
+    level_ui.set_held(inclinometer.is_held());
+    level_ui.set_zeroed(inclinometer.is_zeroed());
+}
+
+fn save_zero(nvs_partition: &EspDefaultNvsPartition, zero: Orientation) {
+    match EspNvs::new(nvs_partition.clone(), level::NVS_NAMESPACE, true) {
+        Ok(mut nvs) => {
+            if let Err(e) = level::store_zero(&mut nvs, zero) {
+                log::error!("Failed to save level zero: {:?}", e);
+            }
+        }
+        Err(e) => log::error!("Failed to open level storage: {:?}", e),
+    }
+}
+
+pub struct LevelPage {
+    ui: Weak<GadgetMainWindow>,
+    inclinometer: Rc<RefCell<Inclinometer>>,
+    /// The latest filtered orientation from the motion service.
+    orientation: Rc<Cell<Orientation>>,
+    nvs_partition: EspDefaultNvsPartition,
+}
+
+impl LevelPage {
+    /// Show a new orientation from the motion service.
+    pub fn update(&self, orientation: Orientation) {
+        self.orientation.set(orientation);
+        show(
+            &self.ui.unwrap().global::<Level>(),
+            &self.inclinometer.borrow(),
+            orientation,
+        );
+    }
+
+    pub fn zero(&self) -> Orientation {
+        self.inclinometer.borrow().zero()
+    }
+
+    /// Start over from the zero point `zero`, it's saved right away.
+    pub fn set_zero(&self, zero: Orientation) {
+        let mut inclinometer = self.inclinometer.borrow_mut();
+        *inclinometer = Inclinometer::new(zero);
+        save_zero(&self.nvs_partition, zero);
+        show(
+            &self.ui.unwrap().global::<Level>(),
+            &inclinometer,
+            self.orientation.get(),
+        );
+    }
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    nvs_partition: &EspDefaultNvsPartition,
+    haptics: &SyncSender<HapticPattern>,
+) -> LevelPage {
+    let inclinometer = Rc::new(RefCell::new(Inclinometer::new(
+        EspNvs::new(nvs_partition.clone(), level::NVS_NAMESPACE, false)
+            .map(|nvs| level::load_zero(&nvs))
+            .unwrap_or_default(),
+    )));
+    let current_orientation = Rc::new(Cell::new(Orientation::default()));
+    let level_ui = ui.global::<Level>();
+    show(&level_ui, &inclinometer.borrow(), current_orientation.get());
+    let app_ui_weak = ui.as_weak();
+    let level_state = inclinometer.clone();
+    let level_orientation = current_orientation.clone();
+    let level_nvs_partition = nvs_partition.clone();
+    let haptics = haptics.clone();
+    level_ui.on_zero(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let level_ui = app_ui.global::<Level>();
+        let mut inclinometer = level_state.borrow_mut();
+        if inclinometer.set_zero(level_orientation.get()) {
+            save_zero(&level_nvs_partition, inclinometer.zero());
+            level_ui.set_status("已置零".into());
+            let _ = haptics.try_send(HapticPattern::Double);
+        } else {
+            level_ui.set_status("倾斜过大, 无法置零".into());
+            let _ = haptics.try_send(HapticPattern::Error);
+        }
+        show(&level_ui, &inclinometer, level_orientation.get());
+    });
+    let app_ui_weak = ui.as_weak();
+    let level_state = inclinometer.clone();
+    let level_orientation = current_orientation.clone();
+    level_ui.on_hold(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let level_ui = app_ui.global::<Level>();
+        let mut inclinometer = level_state.borrow_mut();
+        inclinometer.toggle_hold(level_orientation.get());
+        level_ui.set_status(
+            if inclinometer.is_held() {
+                "已保持"
+            } else {
+                ""
+            }
+            .into(),
+        );
+        show(&level_ui, &inclinometer, level_orientation.get());
+    });
+    let app_ui_weak = ui.as_weak();
+    let level_state = inclinometer.clone();
+    let level_orientation = current_orientation.clone();
+    let level_nvs_partition = nvs_partition.clone();
+    level_ui.on_clear_zero(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let level_ui = app_ui.global::<Level>();
+        let mut inclinometer = level_state.borrow_mut();
+        if !inclinometer.is_zeroed() {
+            return;
+        }
+        inclinometer.clear_zero();
+        save_zero(&level_nvs_partition, inclinometer.zero());
+        level_ui.set_status("已清除零点".into());
+        show(&level_ui, &inclinometer, level_orientation.get());
+    });
+    LevelPage {
+        ui: ui.as_weak(),
+        inclinometer,
+        orientation: current_orientation,
+        nvs_partition: nvs_partition.clone(),
+    }
+}
@@ -0,0 +1,151 @@
+//! IMU logger page, recording the raw samples to the SD card. The choices are kept when a log is
+//! started.
+
+use std::{cell::RefCell, path::Path, rc::Rc, sync::mpsc::SyncSender};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel};
+
+use crate::{
+    haptics::HapticPattern,
+    motion::{
+        self,
+        logger::{self, ImuLogger, LogFormat, LoggerEvent, LOG_RATES},
+        AccelRange, GyroRange, MotionConfig, MotionControl,
+    },
+    storage::{self, SdCard},
+    utils, GadgetMainWindow, MotionLogger,
+};
+
+/// Where the IMU logger writes to, under the SD card.
+const LOG_DIRECTORY: &str = "imu";
+
+pub fn show_config(motion_logger_ui: &MotionLogger, config: &MotionConfig) {
+    motion_logger_ui.set_rate_index(
+        LOG_RATES
+            .iter()
+            .position(|rate| *rate == config.sample_rate)
+            .unwrap_or(0) as i32,
+    );
+    motion_logger_ui.set_accel_range_index(config.accel_range as i32);
+    motion_logger_ui.set_gyro_range_index(config.gyro_range as i32);
+}
+
+pub fn selected_config(motion_logger_ui: &MotionLogger) -> MotionConfig {
+    let selected = |index: i32| usize::try_from(index).ok();
+    MotionConfig {
+        accel_range: selected(motion_logger_ui.get_accel_range_index())
+            .and_then(|index| AccelRange::ALL.get(index).copied())
+            .unwrap_or_default(),
+        gyro_range: selected(motion_logger_ui.get_gyro_range_index())
+            .and_then(|index| GyroRange::ALL.get(index).copied())
+            .unwrap_or_default(),
+        sample_rate: selected(motion_logger_ui.get_rate_index())
+            .and_then(|index| LOG_RATES.get(index).copied())
+            .unwrap_or(LOG_RATES[0]),
+        fifo: true,
+    }
+}
+
+pub fn save_config(nvs_partition: &EspDefaultNvsPartition, config: &MotionConfig) {
+    match EspNvs::new(nvs_partition.clone(), motion::NVS_NAMESPACE, true) {
+        Ok(mut nvs) => {
+            if let Err(e) = logger::store_config(&mut nvs, config) {
+                log::error!("Failed to save logger config: {:?}", e);
+            }
+        }
+        Err(e) => log::error!("Failed to open motion storage: {:?}", e),
+    }
+}
+
+pub fn show_event(ui: &GadgetMainWindow, event: LoggerEvent) {
+    let motion_logger_ui = ui.global::<MotionLogger>();
+    match event {
+        LoggerEvent::Progress { samples, dropped } => {
+            motion_logger_ui
+                .set_status(format!("记录中: {} 个样本, 溢出 {} 次", samples, dropped).into());
+        }
+        LoggerEvent::Finished(result) => {
+            motion_logger_ui.set_running(false);
+            let status = match result {
+                Ok(summary) => format!(
+                    "已保存 {}\n{} 个样本, 溢出 {} 次",
+                    summary.path.display(),
+                    summary.samples,
+                    summary.dropped
+                ),
+                Err(kind) => format!("记录失败: {}", kind),
+            };
+            motion_logger_ui.set_status(status.into());
+        }
+    }
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    control: &SyncSender<MotionControl>,
+    sd_card: &Rc<RefCell<SdCard>>,
+    nvs_partition: &EspDefaultNvsPartition,
+    haptics: &SyncSender<HapticPattern>,
+) {
+    let motion_logger_ui = ui.global::<MotionLogger>();
+    motion_logger_ui.set_rate_options(ModelRc::new(VecModel::from(
+        LOG_RATES
+            .iter()
+            .map(|rate| SharedString::from(format!("{}Hz", rate)))
+            .collect::<Vec<_>>(),
+    )));
+    show_config(
+        &motion_logger_ui,
+        &EspNvs::new(nvs_partition.clone(), motion::NVS_NAMESPACE, false)
+            .map(|nvs| logger::load_config(&nvs))
+            .unwrap_or_else(|_| logger::default_config()),
+    );
+    let app_ui_weak = ui.as_weak();
+    let start_control = control.clone();
+    let sd_card = sd_card.clone();
+    let nvs_partition = nvs_partition.clone();
+    let haptics = haptics.clone();
+    motion_logger_ui.on_start(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let motion_logger_ui = app_ui.global::<MotionLogger>();
+        if !sd_card.borrow().is_mounted() {
+            motion_logger_ui.set_status("请先挂载存储卡".into());
+            let _ = haptics.try_send(HapticPattern::Error);
+            return;
+        }
+        let config = selected_config(&motion_logger_ui);
+        let format = if motion_logger_ui.get_format_index() == 1 {
+            LogFormat::Binary
+        } else {
+            LogFormat::Csv
+        };
+        let logger = match ImuLogger::create(
+            &Path::new(storage::MOUNT_POINT).join(LOG_DIRECTORY),
+            format,
+            config,
+            utils::now_timestamp() as u64,
+        ) {
+            Ok(logger) => logger,
+            Err(e) => {
+                log::error!("Failed to create IMU log: {:?}", e);
+                motion_logger_ui.set_status(format!("无法创建文件: {}", e).into());
+                let _ = haptics.try_send(HapticPattern::Error);
+                return;
+            }
+        };
+        if start_control
+            .send(MotionControl::StartLogging(logger))
+            .is_ok()
+        {
+            save_config(&nvs_partition, &config);
+            motion_logger_ui.set_running(true);
+            motion_logger_ui.set_status("记录中...".into());
+            let _ = haptics.try_send(HapticPattern::Double);
+        }
+    });
+    let stop_control = control.clone();
+    motion_logger_ui.on_stop(move || {
+        let _ = stop_control.send(MotionControl::StopLogging);
+    });
+}
@@ -0,0 +1,58 @@
+//! The wiring of every page of the UI, the callbacks and what's shown before the first event.
+//!
+//! Each page has a `bind` function, called once before the super loop. A page with state the
+//! super loop or another page needs returns it, the rest is owned by the callbacks.
+
+use std::sync::{mpsc::SyncSender, Mutex};
+
+use embedded_hal_bus::i2c::MutexDevice;
+use esp_idf_svc::hal::i2c::I2cDriver;
+use slint::ComponentHandle;
+
+use crate::{haptics::HapticPattern, rtc::Pcf8563Registers};
+use crate::{GadgetMainWindow, HapticFeedback, Haptics, TextEditing};
+
+pub mod activity;
+pub mod alarms;
+pub mod charger;
+pub mod clock;
+pub mod diagnostics;
+pub mod energy_log;
+pub mod files;
+pub mod gesture;
+pub mod juice;
+pub mod level;
+pub mod logger;
+pub mod motion;
+pub mod power_monitor;
+pub mod settings;
+pub mod stopwatch;
+pub mod storage;
+pub mod thermal;
+
+/// The bus of the built-in I2C devices.
+pub type I2cBus = Mutex<I2cDriver<'static>>;
+/// A device on [`I2cBus`].
+pub type I2cDevice = MutexDevice<'static, I2cDriver<'static>>;
+/// The PMU task clears the RTC interrupt flags while the main thread arms them, each
+/// read-modify-write of the control register is done under this lock.
+pub type SharedRtc = Mutex<Pcf8563Registers<I2cDevice>>;
+
+/// The globals every page uses, haptic feedback and the on-screen keyboard.
+pub fn bind(ui: &GadgetMainWindow, haptics: &SyncSender<HapticPattern>) {
+    let haptics = haptics.clone();
+    ui.global::<Haptics>().on_play(move |feedback| {
+        let pattern = match feedback {
+            HapticFeedback::Tap => HapticPattern::Tap,
+            HapticFeedback::Double => HapticPattern::Double,
+            HapticFeedback::Error => HapticPattern::Error,
+            HapticFeedback::Long => HapticPattern::Long,
+        };
+        let _ = haptics.try_send(pattern);
+    });
+    ui.global::<TextEditing>().on_backspace(|text| {
+        let mut text = text.to_string();
+        text.pop();
+        text.into()
+    });
+}
@@ -0,0 +1,107 @@
+//! Motion page, the latest readings, calibration and the orientation lock of the screen.
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        mpsc::SyncSender,
+        Arc,
+    },
+};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::ComponentHandle;
+
+use crate::{
+    haptics::HapticPattern,
+    motion::{
+        screen::{self, ScreenOrientation},
+        Bias, CalibrationError, MotionControl, MotionData,
+    },
+    GadgetMainWindow, Motion,
+};
+
+fn save_lock(nvs_partition: &EspDefaultNvsPartition, lock: Option<ScreenOrientation>) {
+    match EspNvs::new(nvs_partition.clone(), screen::NVS_NAMESPACE, true) {
+        Ok(mut nvs) => {
+            if let Err(e) = screen::store_lock(&mut nvs, lock) {
+                log::error!("Failed to save orientation lock: {:?}", e);
+            }
+        }
+        Err(e) => log::error!("Failed to open display storage: {:?}", e),
+    }
+}
+
+/// Lock the screen at `lock`, or let it turn again, and save it.
+pub fn apply_lock(
+    ui: &GadgetMainWindow,
+    screen_lock: &Cell<Option<ScreenOrientation>>,
+    nvs_partition: &EspDefaultNvsPartition,
+    lock: Option<ScreenOrientation>,
+) {
+    screen_lock.set(lock);
+    ui.global::<Motion>().set_rotation_locked(lock.is_some());
+    save_lock(nvs_partition, lock);
+}
+
+pub fn show_data(ui: &GadgetMainWindow, data: &MotionData) {
+    let motion_ui = ui.global::<Motion>();
+    let [ax, ay, az] = data.sample.accel;
+    let [gx, gy, gz] = data.sample.gyro;
+    motion_ui.set_pitch(format!("{:.1}°", data.orientation.pitch).into());
+    motion_ui.set_roll(format!("{:.1}°", data.orientation.roll).into());
+    motion_ui.set_accel(format!("{:.2} {:.2} {:.2} g", ax, ay, az).into());
+    motion_ui.set_gyro(format!("{:.1} {:.1} {:.1} °/s", gx, gy, gz).into());
+}
+
+pub fn show_calibration(
+    ui: &GadgetMainWindow,
+    result: &Result<Bias, CalibrationError>,
+    haptics: &SyncSender<HapticPattern>,
+) {
+    let status = match result {
+        Ok(_) => "校准完成",
+        Err(CalibrationError::Moving) => "校准失败: 设备在移动",
+        Err(CalibrationError::NotLevel) => "校准失败: 请屏幕朝上平放",
+        Err(CalibrationError::Sensor) => "校准失败: 传感器错误",
+    };
+    ui.global::<Motion>().set_status(status.into());
+    let _ = haptics.try_send(if result.is_ok() {
+        HapticPattern::Double
+    } else {
+        HapticPattern::Error
+    });
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    control: &SyncSender<MotionControl>,
+    screen_lock: &Rc<Cell<Option<ScreenOrientation>>>,
+    screen_orientation: &Arc<AtomicU8>,
+    nvs_partition: &EspDefaultNvsPartition,
+) {
+    let motion_ui = ui.global::<Motion>();
+    let control = control.clone();
+    let app_ui_weak = ui.as_weak();
+    motion_ui.on_calibrate(move || {
+        if control.send(MotionControl::Calibrate).is_ok() {
+            app_ui_weak
+                .unwrap()
+                .global::<Motion>()
+                .set_status("校准中, 请勿移动...".into());
+        }
+    });
+
+    motion_ui.set_rotation_locked(screen_lock.get().is_some());
+    let nvs_partition = nvs_partition.clone();
+    let screen_lock = screen_lock.clone();
+    let screen_orientation = screen_orientation.clone();
+    motion_ui.on_lock_rotation(move |locked| {
+        // lock at whatever is shown now
+        let lock =
+            locked.then(|| ScreenOrientation::from(screen_orientation.load(Ordering::Relaxed)));
+        screen_lock.set(lock);
+        save_lock(&nvs_partition, lock);
+    });
+}
@@ -0,0 +1,74 @@
+//! Power monitor page, the channels of the latest sample and a graph of the total power.
+
+use std::{collections::VecDeque, rc::Rc};
+
+use slint::{ComponentHandle, ModelRc, VecModel, Weak};
+
+use crate::{
+    powermon::{PowerSample, CHANNEL_NAMES},
+    ChannelView, GadgetMainWindow, PowerMonitor,
+};
+
+const POWER_HISTORY_SIZE: usize = 64;
+
+/// Format the readings of a power sample for the UI.
+fn channel_views(sample: &PowerSample) -> Vec<ChannelView> {
+    sample
+        .channels
+        .iter()
+        .zip(CHANNEL_NAMES)
+        .map(|(reading, name)| ChannelView {
+            name: name.into(),
+            voltage: format!("{:.2}V", reading.bus_millivolts / 1000.0).into(),
+            current: format!("{:.1}mA", reading.current_milliamps).into(),
+            power: format!("{:.0}mW", reading.power_milliwatts()).into(),
+        })
+        .collect()
+}
+
+pub struct PowerMonitorPage {
+    ui: Weak<GadgetMainWindow>,
+    channels: Rc<VecModel<ChannelView>>,
+    history: Rc<VecModel<f32>>,
+    /// Total power in mW, the oldest first.
+    power_history: VecDeque<f32>,
+}
+
+impl PowerMonitorPage {
+    /// Show a new sample, the graph scrolls by one.
+    pub fn show(&mut self, sample: &PowerSample) {
+        let total_power = sample.total_power_milliwatts();
+        if self.power_history.len() == POWER_HISTORY_SIZE {
+            self.power_history.pop_front();
+        }
+        self.power_history.push_back(total_power);
+        // auto scaling, but at least 100mW full scale
+        let full_scale = self.power_history.iter().copied().fold(100.0, f32::max);
+        self.channels.set_vec(channel_views(sample));
+        self.history.set_vec(
+            self.power_history
+                .iter()
+                .map(|value| value / full_scale)
+                .collect::<Vec<f32>>(),
+        );
+        let app_ui = self.ui.unwrap();
+        let power_monitor = app_ui.global::<PowerMonitor>();
+        power_monitor.set_total_power(format!("{:.0}mW", total_power).into());
+        power_monitor.set_history_scale(format!("满量程 {:.0}mW", full_scale).into());
+    }
+}
+
+pub fn bind(ui: &GadgetMainWindow) -> PowerMonitorPage {
+    let power_monitor = ui.global::<PowerMonitor>();
+    let channels = Rc::new(VecModel::from(channel_views(&PowerSample::default())));
+    let history = Rc::new(VecModel::<f32>::default());
+    power_monitor.set_channels(ModelRc::from(channels.clone()));
+    power_monitor.set_history(ModelRc::from(history.clone()));
+    power_monitor.set_history_capacity(POWER_HISTORY_SIZE as i32);
+    PowerMonitorPage {
+        ui: ui.as_weak(),
+        channels,
+        history,
+        power_history: VecDeque::with_capacity(POWER_HISTORY_SIZE),
+    }
+}
@@ -0,0 +1,235 @@
+//! Settings page, factory reset, and the export and import of the configs of every page.
+
+use std::{
+    cell::{Cell, RefCell},
+    path::Path,
+    rc::Rc,
+    sync::{mpsc::SyncSender, Arc, Mutex},
+};
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel};
+
+use crate::{
+    activity, alarms, energylog,
+    haptics::HapticPattern,
+    level,
+    motion::{
+        self,
+        gesture::{self, GestureConfig},
+        screen::{self, ScreenOrientation},
+    },
+    pmu::charger::{self, ChargerConfig},
+    settings::{self, DeviceConfig},
+    storage::{self, SdCard},
+    textview,
+    thermal::{self, ThermalConfig},
+    ChargerSettings, GadgetMainWindow, GestureSettings, MotionLogger, SettingsStore, Thermal,
+};
+
+use super::{alarms::AlarmsPage, juice::MainPage, level::LevelPage};
+
+/// Everything a factory reset clears, the boot records are kept for diagnosis.
+pub const FACTORY_RESET_NAMESPACES: [&str; 11] = [
+    settings::NVS_NAMESPACE,
+    charger::NVS_NAMESPACE,
+    thermal::NVS_NAMESPACE,
+    gesture::NVS_NAMESPACE,
+    screen::NVS_NAMESPACE,
+    motion::NVS_NAMESPACE,
+    level::NVS_NAMESPACE,
+    alarms::NVS_NAMESPACE,
+    energylog::NVS_NAMESPACE,
+    activity::NVS_NAMESPACE,
+    textview::NVS_NAMESPACE,
+];
+
+/// The state of the pages that isn't kept by the UI, to export and import their configs.
+pub struct Configs {
+    pub main: Rc<MainPage>,
+    pub gesture: Arc<Mutex<GestureConfig>>,
+    pub screen_lock: Rc<Cell<Option<ScreenOrientation>>>,
+    pub level: Rc<LevelPage>,
+    pub alarms: Rc<AlarmsPage>,
+}
+
+impl Configs {
+    /// The configs of every page, as saved, for export.
+    fn current(
+        &self,
+        ui: &GadgetMainWindow,
+        nvs_partition: &EspDefaultNvsPartition,
+    ) -> DeviceConfig {
+        DeviceConfig {
+            settings: self.main.settings(),
+            charger: EspNvs::new(nvs_partition.clone(), charger::NVS_NAMESPACE, false)
+                .map(|nvs| ChargerConfig::load(&nvs))
+                .unwrap_or_default(),
+            thermal: EspNvs::new(nvs_partition.clone(), thermal::NVS_NAMESPACE, false)
+                .map(|nvs| ThermalConfig::load(&nvs))
+                .unwrap_or_default(),
+            gesture: *self.gesture.lock().unwrap(),
+            screen_lock: self.screen_lock.get(),
+            logger: super::logger::selected_config(&ui.global::<MotionLogger>()),
+            level_zero: self.level.zero(),
+            alarms: self.alarms.alarms(),
+            energylog_interval: super::energy_log::selected_interval(ui),
+        }
+    }
+
+    fn apply(
+        &self,
+        ui: &GadgetMainWindow,
+        config: DeviceConfig,
+        nvs_partition: &EspDefaultNvsPartition,
+    ) {
+        self.main.import(config.settings);
+        // the pages with an apply button check, apply and save their part themselves
+        let charger_settings = ui.global::<ChargerSettings>();
+        super::charger::show_config(&charger_settings, &config.charger);
+        charger_settings.invoke_apply();
+        let thermal_ui = ui.global::<Thermal>();
+        super::thermal::show_config(&thermal_ui, &config.thermal);
+        thermal_ui.invoke_apply();
+        let gesture_ui = ui.global::<GestureSettings>();
+        super::gesture::show_config(&gesture_ui, &config.gesture);
+        gesture_ui.invoke_apply();
+        super::energy_log::apply_interval(ui, config.energylog_interval);
+        // the rest is saved here
+        super::motion::apply_lock(ui, &self.screen_lock, nvs_partition, config.screen_lock);
+        super::logger::show_config(&ui.global::<MotionLogger>(), &config.logger);
+        super::logger::save_config(nvs_partition, &config.logger);
+        self.level.set_zero(config.level_zero);
+        self.alarms.replace(config.alarms);
+    }
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    factory_reset: bool,
+    configs: Configs,
+    sd_card: &Rc<RefCell<SdCard>>,
+    nvs_partition: &EspDefaultNvsPartition,
+    haptics: &SyncSender<HapticPattern>,
+) {
+    let settings_ui = ui.global::<SettingsStore>();
+    settings_ui.set_status(
+        if factory_reset {
+            "已在开机时恢复出厂设置".to_string()
+        } else {
+            format!("设置版本 {}", settings::SCHEMA_VERSION)
+        }
+        .into(),
+    );
+    let configs = Rc::new(configs);
+    let app_ui_weak = ui.as_weak();
+    let reset_configs = configs.clone();
+    let reset_haptics = haptics.clone();
+    settings_ui.on_factory_reset(move || {
+        let app_ui = app_ui_weak.unwrap();
+        // nothing pending may be written back over the wiped namespaces
+        reset_configs.main.discard_pending();
+        match settings::wipe(&FACTORY_RESET_NAMESPACES) {
+            Ok(_) => {
+                // every task and page comes back with its defaults
+                log::warn!("Factory reset, restarting");
+                esp_idf_svc::hal::reset::restart();
+            }
+            Err(e) => {
+                log::error!("Failed to wipe stored settings: {:?}", e);
+                let _ = reset_haptics.try_send(HapticPattern::Error);
+                app_ui
+                    .global::<SettingsStore>()
+                    .set_status(format!("清除失败: {:?}", e).into());
+            }
+        }
+    });
+    let settings_file = Path::new(storage::MOUNT_POINT).join(settings::EXPORT_FILE);
+    let app_ui_weak = ui.as_weak();
+    let export_configs = configs.clone();
+    let export_sd_card = sd_card.clone();
+    let export_settings_file = settings_file.clone();
+    let export_nvs_partition = nvs_partition.clone();
+    let export_haptics = haptics.clone();
+    settings_ui.on_export(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let settings_ui = app_ui.global::<SettingsStore>();
+        let config = export_configs.current(&app_ui, &export_nvs_partition);
+        // a copy on the serial console, also without a card
+        log::info!("Settings exported:\n{}", config.export());
+        if !export_sd_card.borrow().is_mounted() {
+            settings_ui.set_status("请先挂载存储卡".into());
+            let _ = export_haptics.try_send(HapticPattern::Error);
+            return;
+        }
+        match config.write_file(&export_settings_file) {
+            Ok(_) => {
+                settings_ui.set_status(format!("已导出到 {}", settings::EXPORT_FILE).into());
+                let _ = export_haptics.try_send(HapticPattern::Double);
+            }
+            Err(e) => {
+                log::error!("Failed to export settings: {:?}", e);
+                settings_ui.set_status(format!("导出失败: {}", e.kind()).into());
+                let _ = export_haptics.try_send(HapticPattern::Error);
+            }
+        }
+    });
+    // read and validated, applied only after the changes are confirmed
+    let pending_import: Rc<RefCell<Option<DeviceConfig>>> = Rc::new(RefCell::new(None));
+    let app_ui_weak = ui.as_weak();
+    let import_configs = configs.clone();
+    let import_sd_card = sd_card.clone();
+    let import_pending = pending_import.clone();
+    let import_nvs_partition = nvs_partition.clone();
+    let import_haptics = haptics.clone();
+    settings_ui.on_import(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let settings_ui = app_ui.global::<SettingsStore>();
+        if !import_sd_card.borrow().is_mounted() {
+            settings_ui.set_status("请先挂载存储卡".into());
+            let _ = import_haptics.try_send(HapticPattern::Error);
+            return;
+        }
+        let current = import_configs.current(&app_ui, &import_nvs_partition);
+        match current.read_file(&settings_file) {
+            Ok(imported) => {
+                let changes: Vec<SharedString> = current
+                    .diff(&imported)
+                    .into_iter()
+                    .map(SharedString::from)
+                    .collect();
+                *import_pending.borrow_mut() = Some(imported);
+                settings_ui.set_import_changes(ModelRc::new(VecModel::from(changes)));
+                settings_ui.set_import_pending(true);
+            }
+            Err(e) => {
+                log::warn!("Failed to import settings: {:?}", e);
+                settings_ui.set_status(e.to_string().into());
+                let _ = import_haptics.try_send(HapticPattern::Error);
+            }
+        }
+    });
+    let app_ui_weak = ui.as_weak();
+    let import_pending = pending_import.clone();
+    let import_nvs_partition = nvs_partition.clone();
+    let import_haptics = haptics.clone();
+    settings_ui.on_apply_import(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let settings_ui = app_ui.global::<SettingsStore>();
+        settings_ui.set_import_pending(false);
+        let Some(config) = import_pending.borrow_mut().take() else {
+            return;
+        };
+        configs.apply(&app_ui, config, &import_nvs_partition);
+        settings_ui.set_status("已导入设置".into());
+        let _ = import_haptics.try_send(HapticPattern::Double);
+    });
+    let app_ui_weak = ui.as_weak();
+    settings_ui.on_cancel_import(move || {
+        pending_import.borrow_mut().take();
+        app_ui_weak
+            .unwrap()
+            .global::<SettingsStore>()
+            .set_import_pending(false);
+    });
+}
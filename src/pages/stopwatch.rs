@@ -0,0 +1,119 @@
+//! Stopwatch page, the touch buttons are handled by the UI.
+
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use slint::{ComponentHandle, SharedString, VecModel, Weak};
+
+use crate::{
+    platform::duration_since_start,
+    stopwatch::{self, format_duration, Countdown},
+    GadgetMainWindow, Stopwatch,
+};
+
+fn countdown_duration(stopwatch_ui: &Stopwatch) -> Duration {
+    Duration::from_secs(
+        stopwatch_ui.get_countdown_minutes().max(0) as u64 * 60
+            + stopwatch_ui.get_countdown_seconds().max(0) as u64,
+    )
+}
+
+pub struct StopwatchPage {
+    ui: Weak<GadgetMainWindow>,
+    stopwatch: Rc<RefCell<stopwatch::Stopwatch>>,
+    countdown: Rc<RefCell<Countdown>>,
+}
+
+impl StopwatchPage {
+    /// Show the times at `now`, true if the countdown has just run out. Both keep running when
+    /// locked.
+    pub fn update(&self, now: Duration) -> bool {
+        let app_ui = self.ui.unwrap();
+        let stopwatch_ui = app_ui.global::<Stopwatch>();
+        stopwatch_ui.set_elapsed(format_duration(self.stopwatch.borrow().elapsed(now)).into());
+        let mut countdown = self.countdown.borrow_mut();
+        let expired = countdown.take_expired(now);
+        if expired {
+            stopwatch_ui.set_countdown_running(false);
+        }
+        stopwatch_ui.set_remaining(format_duration(countdown.remaining(now)).into());
+        expired
+    }
+}
+
+pub fn bind(ui: &GadgetMainWindow) -> StopwatchPage {
+    let stopwatch_state = Rc::new(RefCell::new(stopwatch::Stopwatch::default()));
+    let countdown_state = Rc::new(RefCell::new(Countdown::default()));
+    let stopwatch_ui = ui.global::<Stopwatch>();
+    countdown_state
+        .borrow_mut()
+        .set(countdown_duration(&stopwatch_ui));
+    let laps_model = Rc::new(VecModel::<SharedString>::default());
+    stopwatch_ui.set_laps(laps_model.clone().into());
+    let app_ui_weak = ui.as_weak();
+    let countdown = countdown_state.clone();
+    stopwatch_ui.on_countdown_changed(move || {
+        let app_ui = app_ui_weak.unwrap();
+        countdown
+            .borrow_mut()
+            .set(countdown_duration(&app_ui.global::<Stopwatch>()));
+    });
+    let app_ui_weak = ui.as_weak();
+    let stopwatch = stopwatch_state.clone();
+    let countdown = countdown_state.clone();
+    stopwatch_ui.on_start_stop(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let stopwatch_ui = app_ui.global::<Stopwatch>();
+        let now = duration_since_start();
+        if stopwatch_ui.get_countdown_mode() {
+            let mut countdown = countdown.borrow_mut();
+            countdown.toggle(now);
+            stopwatch_ui.set_countdown_running(countdown.is_running());
+        } else {
+            let mut stopwatch = stopwatch.borrow_mut();
+            stopwatch.toggle(now);
+            stopwatch_ui.set_running(stopwatch.is_running());
+        }
+    });
+    let app_ui_weak = ui.as_weak();
+    let stopwatch = stopwatch_state.clone();
+    let countdown = countdown_state.clone();
+    stopwatch_ui.on_lap_reset(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let stopwatch_ui = app_ui.global::<Stopwatch>();
+        let now = duration_since_start();
+        if stopwatch_ui.get_countdown_mode() {
+            countdown
+                .borrow_mut()
+                .reset(countdown_duration(&stopwatch_ui));
+            stopwatch_ui.set_countdown_running(false);
+            return;
+        }
+        let mut stopwatch = stopwatch.borrow_mut();
+        if stopwatch.is_running() {
+            stopwatch.lap(now);
+        } else {
+            stopwatch.reset();
+        }
+        // newest first
+        let mut laps: Vec<SharedString> = stopwatch
+            .laps()
+            .enumerate()
+            .map(|(index, (lap, total))| {
+                format!(
+                    "#{:<3} {}  {}",
+                    index + 1,
+                    format_duration(lap),
+                    format_duration(total)
+                )
+                .into()
+            })
+            .collect();
+        laps.reverse();
+        laps_model.set_vec(laps);
+    });
+    StopwatchPage {
+        ui: ui.as_weak(),
+        stopwatch: stopwatch_state,
+        countdown: countdown_state,
+    }
+}
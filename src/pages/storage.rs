@@ -0,0 +1,76 @@
+//! Storage page, mounting the SD card and how full it is.
+
+use std::{cell::RefCell, rc::Rc, sync::mpsc::SyncSender};
+
+use slint::ComponentHandle;
+
+use crate::{
+    haptics::HapticPattern,
+    storage::{self, format_size, SdCard, StorageError},
+    GadgetMainWindow, MotionLogger, Storage,
+};
+
+fn show(storage_ui: &Storage, sd_card: &SdCard, error: Option<StorageError>) {
+    storage_ui.set_mounted(sd_card.is_mounted());
+    let status = match (error, sd_card.usage()) {
+        (Some(e), _) => e.to_string(),
+        (None, Ok(usage)) => {
+            let used = usage.total.saturating_sub(usage.free);
+            storage_ui.set_usage(used as f32 / usage.total.max(1) as f32);
+            storage_ui.set_capacity(
+                format!(
+                    "已用 {} / 共 {}",
+                    format_size(used),
+                    format_size(usage.total)
+                )
+                .into(),
+            );
+            format!("已挂载于 {}", storage::MOUNT_POINT)
+        }
+        (None, Err(_)) if !sd_card.is_mounted() => "未挂载".into(),
+        (None, Err(e)) => e.to_string(),
+    };
+    storage_ui.set_status(status.into());
+}
+
+/// `error` is why the card couldn't be mounted at boot, if so.
+pub fn bind(
+    ui: &GadgetMainWindow,
+    sd_card: &Rc<RefCell<SdCard>>,
+    error: Option<StorageError>,
+    haptics: &SyncSender<HapticPattern>,
+) {
+    let storage_ui = ui.global::<Storage>();
+    show(&storage_ui, &sd_card.borrow(), error);
+    let app_ui_weak = ui.as_weak();
+    let mount_sd_card = sd_card.clone();
+    let mount_haptics = haptics.clone();
+    storage_ui.on_mount(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let mut sd_card = mount_sd_card.borrow_mut();
+        let error = sd_card.mount().err();
+        let _ = mount_haptics.try_send(if error.is_none() {
+            HapticPattern::Double
+        } else {
+            HapticPattern::Error
+        });
+        show(&app_ui.global::<Storage>(), &sd_card, error);
+    });
+    let app_ui_weak = ui.as_weak();
+    let unmount_sd_card = sd_card.clone();
+    let unmount_haptics = haptics.clone();
+    storage_ui.on_unmount(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let storage_ui = app_ui.global::<Storage>();
+        // files open in other tasks would be cut off
+        if app_ui.global::<MotionLogger>().get_running() {
+            storage_ui.set_status("请先停止数据记录".into());
+            let _ = unmount_haptics.try_send(HapticPattern::Error);
+            return;
+        }
+        let mut sd_card = unmount_sd_card.borrow_mut();
+        sd_card.unmount();
+        let _ = unmount_haptics.try_send(HapticPattern::Double);
+        show(&storage_ui, &sd_card, None);
+    });
+}
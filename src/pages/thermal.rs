@@ -0,0 +1,75 @@
+//! Thermal page, the readings and the thresholds, applied to the task and saved when the apply
+//! button is pressed.
+
+use std::sync::mpsc::SyncSender;
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs};
+use slint::ComponentHandle;
+
+use crate::{
+    thermal::{self, ThermalConfig, ThermalControl, ThermalLevel, ThermalReading, THRESHOLD_RANGE},
+    GadgetMainWindow, Thermal,
+};
+
+/// Show the thresholds of `config`, they're applied by the apply button.
+pub fn show_config(thermal_ui: &Thermal, config: &ThermalConfig) {
+    thermal_ui.set_warm(config.warm as i32);
+    thermal_ui.set_hot(config.hot as i32);
+    thermal_ui.set_critical(config.critical as i32);
+}
+
+pub fn show_reading(ui: &GadgetMainWindow, reading: &ThermalReading) {
+    let thermal_ui = ui.global::<Thermal>();
+    thermal_ui.set_pmu_temperature(format!("{:.1}°C", reading.pmu_celsius).into());
+    thermal_ui.set_esp_temperature(
+        reading
+            .esp_celsius
+            .map(|celsius| format!("{:.1}°C", celsius))
+            .unwrap_or_else(|| "--".into())
+            .into(),
+    );
+    thermal_ui.set_level(
+        match reading.level {
+            ThermalLevel::Normal => "正常",
+            ThermalLevel::Warm => "降低功率",
+            ThermalLevel::Hot => "调暗屏幕",
+            ThermalLevel::Critical => "停止射频",
+        }
+        .into(),
+    );
+}
+
+pub fn bind(
+    ui: &GadgetMainWindow,
+    config: &ThermalConfig,
+    control: SyncSender<ThermalControl>,
+    nvs_partition: &EspDefaultNvsPartition,
+) {
+    let thermal_ui = ui.global::<Thermal>();
+    thermal_ui.set_threshold_minimum(*THRESHOLD_RANGE.start() as i32);
+    thermal_ui.set_threshold_maximum(*THRESHOLD_RANGE.end() as i32);
+    show_config(&thermal_ui, config);
+    let app_ui_weak = ui.as_weak();
+    let nvs_partition = nvs_partition.clone();
+    thermal_ui.on_apply(move || {
+        let app_ui = app_ui_weak.unwrap();
+        let thermal_ui = app_ui.global::<Thermal>();
+        let to_celsius = |value: i32| value.clamp(0, u8::MAX as i32) as u8;
+        let config = ThermalConfig {
+            warm: to_celsius(thermal_ui.get_warm()),
+            hot: to_celsius(thermal_ui.get_hot()),
+            critical: to_celsius(thermal_ui.get_critical()),
+        }
+        .sanitized();
+        let _ = control.send(ThermalControl::SetConfig(config));
+        match EspNvs::new(nvs_partition.clone(), thermal::NVS_NAMESPACE, true) {
+            Ok(mut nvs) => {
+                if let Err(e) = config.store(&mut nvs) {
+                    log::error!("Failed to save thermal thresholds: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to open thermal storage: {:?}", e),
+        }
+        show_config(&thermal_ui, &config);
+    });
+}
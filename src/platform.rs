@@ -29,12 +29,17 @@ impl Platform for M5Core2V11GadgetPlatform {
     }
 
     fn duration_since_start(&self) -> core::time::Duration {
-        // the implementation is copied from original std implementation XD
-        let the_beginning = *INITIAL_INSTANT.get_or_init(time::Instant::now);
-        time::Instant::now() - the_beginning
+        duration_since_start()
     }
 }
 
+/// The monotonic clock shared by slint and the apps.
+pub fn duration_since_start() -> core::time::Duration {
+    // the implementation is copied from original std implementation XD
+    let the_beginning = *INITIAL_INSTANT.get_or_init(time::Instant::now);
+    time::Instant::now() - the_beginning
+}
+
 // simple display wrapper from the official example
 pub struct DisplayWrapper<'a, T> {
     display: &'a mut T,
@@ -0,0 +1,102 @@
+//! Battery and charging status.
+
+use embedded_hal::i2c::I2c;
+
+use super::Axp2101Registers;
+
+const REG_STATUS_1: u8 = 0x00;
+const REG_STATUS_2: u8 = 0x01;
+const REG_ADC_ENABLE: u8 = 0x30;
+const REG_IRQ_ENABLE_1: u8 = 0x41;
+const REG_VBAT_HIGH: u8 = 0x34;
+const REG_VBAT_LOW: u8 = 0x35;
+const REG_BATTERY_PERCENT: u8 = 0xA4;
+
+/// VBAT, VBUS, VSYS and die temperature.
+const ADC_CHANNELS: u8 = 0b11101;
+/// VBUS insert and remove.
+const VBUS_IRQS: u8 = 0b11000000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeState {
+    Trickle,
+    PreCharge,
+    ConstantCurrent,
+    ConstantVoltage,
+    Done,
+    #[default]
+    NotCharging,
+}
+
+impl ChargeState {
+    pub fn is_charging(self) -> bool {
+        matches!(
+            self,
+            Self::Trickle | Self::PreCharge | Self::ConstantCurrent | Self::ConstantVoltage
+        )
+    }
+}
+
+impl From<u8> for ChargeState {
+    fn from(value: u8) -> Self {
+        match value & 0b111 {
+            0 => Self::Trickle,
+            1 => Self::PreCharge,
+            2 => Self::ConstantCurrent,
+            3 => Self::ConstantVoltage,
+            4 => Self::Done,
+            _ => Self::NotCharging,
+        }
+    }
+}
+
+impl From<ChargeState> for u8 {
+    fn from(value: ChargeState) -> Self {
+        match value {
+            ChargeState::Trickle => 0,
+            ChargeState::PreCharge => 1,
+            ChargeState::ConstantCurrent => 2,
+            ChargeState::ConstantVoltage => 3,
+            ChargeState::Done => 4,
+            ChargeState::NotCharging => 5,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BatteryStatus {
+    pub present: bool,
+    pub percent: u8,
+    pub millivolts: u16,
+    pub charge_state: ChargeState,
+    pub vbus_present: bool,
+}
+
+/// Enable the ADC channels used by [`read_battery_status`] and the thermal monitor.
+pub fn enable_adc<I2C: I2c>(registers: &mut Axp2101Registers<I2C>) -> Result<(), I2C::Error> {
+    registers.update(REG_ADC_ENABLE, ADC_CHANNELS, ADC_CHANNELS)
+}
+
+/// Report USB power plug/unplug through the PMU interrupt.
+pub fn enable_vbus_interrupts<I2C: I2c>(
+    registers: &mut Axp2101Registers<I2C>,
+) -> Result<(), I2C::Error> {
+    registers.update(REG_IRQ_ENABLE_1, VBUS_IRQS, VBUS_IRQS)
+}
+
+pub fn read_battery_status<I2C: I2c>(
+    registers: &mut Axp2101Registers<I2C>,
+) -> Result<BatteryStatus, I2C::Error> {
+    let status_1 = registers.read(REG_STATUS_1)?;
+    let status_2 = registers.read(REG_STATUS_2)?;
+    // 14-bit, 1mV/LSB
+    let millivolts = ((registers.read(REG_VBAT_HIGH)? & 0b111111) as u16) << 8
+        | registers.read(REG_VBAT_LOW)? as u16;
+    Ok(BatteryStatus {
+        present: status_1 & (1 << 3) != 0,
+        percent: registers.read(REG_BATTERY_PERCENT)?.min(100),
+        millivolts,
+        charge_state: ChargeState::from(status_2),
+        vbus_present: status_1 & (1 << 5) != 0,
+    })
+}
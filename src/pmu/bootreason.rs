@@ -0,0 +1,57 @@
+//! Power-on and power-off sources of AXP2101.
+//!
+//! Both registers are latched by the PMU, so they describe the current boot and the last
+//! shutdown respectively.
+
+use embedded_hal::i2c::I2c;
+
+use super::Axp2101Registers;
+
+const REG_POWER_ON_SOURCE: u8 = 0x20;
+const REG_POWER_OFF_SOURCE: u8 = 0x21;
+
+const POWER_ON_SOURCES: [&str; 6] = [
+    "power key",
+    "IRQ pin",
+    "VBUS inserted",
+    "battery charged",
+    "battery inserted",
+    "EN mode",
+];
+
+const POWER_OFF_SOURCES: [&str; 8] = [
+    "power key",
+    "software",
+    "power key held low",
+    "VSYS under voltage",
+    "VBUS over voltage",
+    "DCDC under voltage",
+    "DCDC over voltage",
+    "over temperature",
+];
+
+/// (power-on source, power-off source) raw register values.
+pub fn read_power_sources<I2C: I2c>(
+    registers: &mut Axp2101Registers<I2C>,
+) -> Result<(u8, u8), I2C::Error> {
+    Ok((
+        registers.read(REG_POWER_ON_SOURCE)?,
+        registers.read(REG_POWER_OFF_SOURCE)?,
+    ))
+}
+
+fn decode(raw: u8, names: &'static [&'static str]) -> impl Iterator<Item = &'static str> {
+    names
+        .iter()
+        .enumerate()
+        .filter(move |(bit, _)| raw & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+}
+
+pub fn power_on_reasons(raw: u8) -> impl Iterator<Item = &'static str> {
+    decode(raw, &POWER_ON_SOURCES)
+}
+
+pub fn power_off_reasons(raw: u8) -> impl Iterator<Item = &'static str> {
+    decode(raw, &POWER_OFF_SOURCES)
+}
@@ -0,0 +1,190 @@
+//! Battery charger configuration.
+//!
+//! The battery on M5Stack Core2 V1.1 is a tiny 390mAh LiPo cell, so only a safe subset of
+//! what AXP2101 supports is exposed here.
+
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::EspError,
+};
+
+use super::Axp2101Registers;
+
+const REG_INPUT_CURRENT_LIMIT: u8 = 0x16;
+const REG_PRECHARGE_CURRENT: u8 = 0x61;
+const REG_CONSTANT_CURRENT: u8 = 0x62;
+const REG_TERMINATION_VOLTAGE: u8 = 0x64;
+
+/// NVS namespace used to persist the charger configuration.
+pub const NVS_NAMESPACE: &str = "charger";
+
+/// Selectable constant charge current(mA), with the raw register value.
+///
+/// The upper bound is approx. 1C for the built-in battery.
+pub const CONSTANT_CURRENT_OPTIONS: [(u16, u8); 7] = [
+    (100, 4),
+    (125, 5),
+    (150, 6),
+    (175, 7),
+    (200, 8),
+    (300, 9),
+    (400, 10),
+];
+
+/// Selectable charge termination voltage(mV), with the raw register value.
+///
+/// 4.35V and 4.4V are only for high voltage cells, and are not exposed.
+pub const TERMINATION_VOLTAGE_OPTIONS: [(u16, u8); 3] = [(4000, 1), (4100, 2), (4200, 3)];
+
+/// Selectable precharge current(mA), with the raw register value.
+pub const PRECHARGE_CURRENT_OPTIONS: [(u16, u8); 4] = [(25, 1), (50, 2), (75, 3), (100, 4)];
+
+/// Selectable input current limit(mA), with the raw register value.
+pub const INPUT_CURRENT_LIMIT_OPTIONS: [(u16, u8); 6] = [
+    (100, 0),
+    (500, 1),
+    (900, 2),
+    (1000, 3),
+    (1500, 4),
+    (2000, 5),
+];
+
+/// Charger settings, all currents in mA and voltages in mV.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChargerConfig {
+    pub constant_current: u16,
+    pub termination_voltage: u16,
+    pub precharge_current: u16,
+    pub input_current_limit: u16,
+}
+
+impl Default for ChargerConfig {
+    fn default() -> Self {
+        Self {
+            constant_current: 200,
+            termination_voltage: 4200,
+            precharge_current: 50,
+            input_current_limit: 1000,
+        }
+    }
+}
+
+/// Find the largest option not exceeding `value`, or the smallest option.
+fn floor_option(options: &[(u16, u8)], value: u16) -> (u16, u8) {
+    options
+        .iter()
+        .rev()
+        .find(|(option, _)| *option <= value)
+        .copied()
+        .unwrap_or(options[0])
+}
+
+/// Find the index of `value` in `options`, rounding down like [`floor_option`].
+pub fn option_index(options: &[(u16, u8)], value: u16) -> usize {
+    options
+        .iter()
+        .rposition(|(option, _)| *option <= value)
+        .unwrap_or(0)
+}
+
+impl ChargerConfig {
+    /// Snap every field to a supported value within the safe bounds.
+    pub fn sanitized(self) -> Self {
+        Self {
+            constant_current: floor_option(&CONSTANT_CURRENT_OPTIONS, self.constant_current).0,
+            termination_voltage: floor_option(
+                &TERMINATION_VOLTAGE_OPTIONS,
+                self.termination_voltage,
+            )
+            .0,
+            precharge_current: floor_option(&PRECHARGE_CURRENT_OPTIONS, self.precharge_current).0,
+            input_current_limit: floor_option(
+                &INPUT_CURRENT_LIMIT_OPTIONS,
+                self.input_current_limit,
+            )
+            .0,
+        }
+    }
+
+    /// Write the configuration to the PMU, it's sanitized first.
+    pub fn apply<I2C: I2c>(&self, registers: &mut Axp2101Registers<I2C>) -> Result<(), I2C::Error> {
+        let config = self.sanitized();
+        registers.update(
+            REG_INPUT_CURRENT_LIMIT,
+            0b111,
+            floor_option(&INPUT_CURRENT_LIMIT_OPTIONS, config.input_current_limit).1,
+        )?;
+        registers.update(
+            REG_PRECHARGE_CURRENT,
+            0b1111,
+            floor_option(&PRECHARGE_CURRENT_OPTIONS, config.precharge_current).1,
+        )?;
+        registers.update(
+            REG_CONSTANT_CURRENT,
+            0b11111,
+            floor_option(&CONSTANT_CURRENT_OPTIONS, config.constant_current).1,
+        )?;
+        registers.update(
+            REG_TERMINATION_VOLTAGE,
+            0b111,
+            floor_option(&TERMINATION_VOLTAGE_OPTIONS, config.termination_voltage).1,
+        )?;
+        Ok(())
+    }
+
+    /// Read the actual configuration from the PMU.
+    ///
+    /// The result is not sanitized, so it reveals values set by others(e.g. the bootloader).
+    pub fn read<I2C: I2c>(registers: &mut Axp2101Registers<I2C>) -> Result<Self, I2C::Error> {
+        let input_current_limit = match registers.read(REG_INPUT_CURRENT_LIMIT)? & 0b111 {
+            0 => 100,
+            1 => 500,
+            2 => 900,
+            3 => 1000,
+            4 => 1500,
+            _ => 2000,
+        };
+        let precharge_current =
+            (registers.read(REG_PRECHARGE_CURRENT)? & 0b1111).min(8) as u16 * 25;
+        let constant_current = match registers.read(REG_CONSTANT_CURRENT)? & 0b11111 {
+            raw @ 0..=8 => raw as u16 * 25,
+            raw => 200 + (raw.min(16) as u16 - 8) * 100,
+        };
+        let termination_voltage = match registers.read(REG_TERMINATION_VOLTAGE)? & 0b111 {
+            1 => 4000,
+            2 => 4100,
+            3 => 4200,
+            4 => 4350,
+            _ => 4400,
+        };
+        Ok(Self {
+            constant_current,
+            termination_voltage,
+            precharge_current,
+            input_current_limit,
+        })
+    }
+
+    /// Load the stored configuration, missing values fall back to defaults.
+    pub fn load(nvs: &EspNvs<NvsDefault>) -> Self {
+        let default = Self::default();
+        let get = |key: &str, fallback: u16| nvs.get_u16(key).ok().flatten().unwrap_or(fallback);
+        Self {
+            constant_current: get("cc", default.constant_current),
+            termination_voltage: get("cv", default.termination_voltage),
+            precharge_current: get("pre", default.precharge_current),
+            input_current_limit: get("ilim", default.input_current_limit),
+        }
+        .sanitized()
+    }
+
+    /// Persist the configuration.
+    pub fn store(&self, nvs: &mut EspNvs<NvsDefault>) -> Result<(), EspError> {
+        nvs.set_u16("cc", self.constant_current)?;
+        nvs.set_u16("cv", self.termination_voltage)?;
+        nvs.set_u16("pre", self.precharge_current)?;
+        nvs.set_u16("ilim", self.input_current_limit)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+//! Charge LED as a system status indicator.
+//!
+//! Several states can be active at the same time, the one with the highest priority decides
+//! the pattern. AXP2101 only offers 4 outputs in manual mode, errors are told apart by blinking
+//! in bursts, which the controller times itself.
+
+use std::time::{Duration, Instant};
+
+use embedded_hal::i2c::I2c;
+
+use super::Axp2101Registers;
+
+const REG_CHGLED: u8 = 0x69;
+const CHGLED_OUTPUT_MASK: u8 = 0b110000;
+/// Length of a burst and of the pause after it.
+const BURST_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedPattern {
+    Off,
+    Blink1Hz,
+    Blink4Hz,
+    On,
+    /// 4Hz blinking for a [`BURST_PERIOD`], then off for as long.
+    Burst4Hz,
+}
+
+impl LedPattern {
+    /// The output for the PMU, `in_burst` is only used by bursts.
+    fn raw(self, in_burst: bool) -> u8 {
+        match self {
+            LedPattern::Off => 0b00 << 4,
+            LedPattern::Blink1Hz => 0b01 << 4,
+            LedPattern::Blink4Hz => 0b10 << 4,
+            LedPattern::On => 0b11 << 4,
+            LedPattern::Burst4Hz if in_burst => LedPattern::Blink4Hz.raw(false),
+            LedPattern::Burst4Hz => LedPattern::Off.raw(false),
+        }
+    }
+}
+
+/// System states shown by the LED, in priority order, highest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedState {
+    Error,
+    LowBattery,
+    Charging,
+    Full,
+    RadioActive,
+    Sleeping,
+}
+
+impl LedState {
+    const ALL: [LedState; 6] = [
+        LedState::Error,
+        LedState::LowBattery,
+        LedState::Charging,
+        LedState::Full,
+        LedState::RadioActive,
+        LedState::Sleeping,
+    ];
+
+    pub fn pattern(self) -> LedPattern {
+        match self {
+            LedState::Error => LedPattern::Burst4Hz,
+            LedState::LowBattery => LedPattern::Blink1Hz,
+            LedState::Charging => LedPattern::On,
+            LedState::Full => LedPattern::Off,
+            LedState::RadioActive => LedPattern::Blink4Hz,
+            LedState::Sleeping => LedPattern::Off,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Pattern when no state is active, the LED works as a power indicator.
+const IDLE_PATTERN: LedPattern = LedPattern::On;
+
+pub struct LedController<I2C> {
+    registers: Axp2101Registers<I2C>,
+    active: u8,
+    /// The raw output written to the PMU.
+    shown: Option<u8>,
+    /// Bursts are timed from here.
+    created: Instant,
+}
+
+impl<I2C> LedController<I2C>
+where
+    I2C: I2c,
+{
+    /// The LED must already be in manual mode.
+    pub fn new(registers: Axp2101Registers<I2C>) -> Self {
+        Self {
+            registers,
+            active: 0,
+            shown: None,
+            created: Instant::now(),
+        }
+    }
+
+    pub fn set(&mut self, state: LedState, active: bool) {
+        if active {
+            self.active |= state.bit();
+        } else {
+            self.active &= !state.bit();
+        }
+    }
+
+    pub fn is_active(&self, state: LedState) -> bool {
+        self.active & state.bit() != 0
+    }
+
+    /// The state currently shown, if any.
+    pub fn top_state(&self) -> Option<LedState> {
+        LedState::ALL
+            .into_iter()
+            .find(|state| self.is_active(*state))
+    }
+
+    pub fn pattern(&self) -> LedPattern {
+        self.top_state()
+            .map(LedState::pattern)
+            .unwrap_or(IDLE_PATTERN)
+    }
+
+    /// Write the pattern to the PMU, only if it's changed. Call it often for bursts.
+    pub fn update(&mut self) -> Result<(), I2C::Error> {
+        let pattern = self.pattern();
+        let periods = self.created.elapsed().as_millis() / BURST_PERIOD.as_millis();
+        let raw = pattern.raw(periods % 2 == 0);
+        if self.shown != Some(raw) {
+            self.registers.update(REG_CHGLED, CHGLED_OUTPUT_MASK, raw)?;
+            log::debug!("Charge LED: {:?} by {:?}", pattern, self.top_state());
+            self.shown = Some(raw);
+        }
+        Ok(())
+    }
+}
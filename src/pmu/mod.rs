@@ -0,0 +1,47 @@
+//! Extra AXP2101 features which are not covered by the `axp2101` crate.
+//!
+//! Register addresses and layouts are taken from the AXP2101 datasheet (V1.0).
+
+use embedded_hal::i2c::I2c;
+
+pub mod battery;
+pub mod bootreason;
+pub mod charger;
+pub mod led;
+pub mod rails;
+
+/// The 7-bit I2C address of AXP2101.
+pub const AXP2101_ADDRESS: u8 = 0x34;
+
+/// Raw register access to AXP2101.
+///
+/// It only does plain reads/writes, the meaning of the registers is up to the caller.
+pub struct Axp2101Registers<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C> Axp2101Registers<I2C>
+where
+    I2C: I2c,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self { i2c }
+    }
+
+    pub fn read(&mut self, register: u8) -> Result<u8, I2C::Error> {
+        let mut buffer = [0u8; 1];
+        self.i2c
+            .write_read(AXP2101_ADDRESS, &[register], &mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) -> Result<(), I2C::Error> {
+        self.i2c.write(AXP2101_ADDRESS, &[register, value])
+    }
+
+    /// Read-modify-write, only bits in `mask` are changed.
+    pub fn update(&mut self, register: u8, mask: u8, value: u8) -> Result<(), I2C::Error> {
+        let old = self.read(register)?;
+        self.write(register, (old & !mask) | (value & mask))
+    }
+}
@@ -0,0 +1,358 @@
+//! Power rails of M5Stack Core2 V1.1.
+//!
+//! Every regulator output of AXP2101 used on the board is described in [`BOARD_RAILS`], and
+//! all subsystems should go through [`RailManager`] so the voltage limits are always enforced.
+//! The manager only keeps how many users have requested each rail, so it can be created wherever
+//! needed. Voltages and switches are read from the PMU registers.
+
+use core::{
+    fmt,
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU8, Ordering},
+};
+use embedded_hal::{digital, i2c::I2c};
+
+use super::Axp2101Registers;
+
+const REG_DCDC_ENABLE: u8 = 0x80;
+const REG_LDO_ENABLE: u8 = 0x90;
+
+/// Regulator outputs of AXP2101 which are wired on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rail {
+    Dcdc1,
+    Dcdc3,
+    Aldo1,
+    Aldo2,
+    Aldo3,
+    Aldo4,
+    Bldo1,
+    Bldo2,
+    Dldo1,
+}
+
+/// Users of each rail through [`RailManager::request`], in the order of [`BOARD_RAILS`].
+static REQUESTS: [AtomicU8; BOARD_RAILS.len()] = [NO_REQUESTS; BOARD_RAILS.len()];
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_REQUESTS: AtomicU8 = AtomicU8::new(0);
+
+/// Static description of a rail.
+#[derive(Debug)]
+pub struct RailInfo {
+    pub rail: Rail,
+    /// The net name on the schematic.
+    pub name: &'static str,
+    /// Allowed output voltage(mV) on this board, narrower than what the regulator supports.
+    pub voltages: RangeInclusive<u16>,
+    pub consumers: &'static [&'static str],
+    /// Rails which must never be turned off at runtime.
+    pub always_on: bool,
+}
+
+/// The rail map of M5Stack Core2 V1.1.
+pub const BOARD_RAILS: [RailInfo; 9] = [
+    RailInfo {
+        rail: Rail::Dcdc1,
+        name: "MCU_VDD",
+        voltages: 3300..=3300,
+        consumers: &["ESP32"],
+        always_on: true,
+    },
+    RailInfo {
+        rail: Rail::Dcdc3,
+        name: "VDD_3V3",
+        voltages: 3300..=3300,
+        consumers: &["ESP32", "PSRAM", "peripherals"],
+        always_on: true,
+    },
+    RailInfo {
+        rail: Rail::Aldo1,
+        name: "ALDO1",
+        voltages: 1800..=3300,
+        consumers: &[],
+        always_on: false,
+    },
+    RailInfo {
+        rail: Rail::Aldo2,
+        name: "LCD_RST",
+        voltages: 3300..=3300,
+        consumers: &["LCD reset", "touch panel reset"],
+        always_on: false,
+    },
+    RailInfo {
+        rail: Rail::Aldo3,
+        name: "SPK_EN",
+        voltages: 3300..=3300,
+        consumers: &["speaker amplifier"],
+        always_on: false,
+    },
+    RailInfo {
+        rail: Rail::Aldo4,
+        name: "TF_VDD",
+        voltages: 3300..=3300,
+        consumers: &["TF card"],
+        always_on: false,
+    },
+    RailInfo {
+        rail: Rail::Bldo1,
+        name: "LCD_BL",
+        voltages: 2500..=3300,
+        consumers: &["LCD backlight"],
+        always_on: false,
+    },
+    RailInfo {
+        rail: Rail::Bldo2,
+        name: "BOOST_EN",
+        voltages: 3300..=3300,
+        consumers: &["5V bus output"],
+        always_on: false,
+    },
+    RailInfo {
+        rail: Rail::Dldo1,
+        name: "VIB_MOTOR",
+        voltages: 500..=3300,
+        consumers: &["vibration motor"],
+        always_on: false,
+    },
+];
+
+impl Rail {
+    pub fn info(self) -> &'static RailInfo {
+        &BOARD_RAILS[self.index()]
+    }
+
+    fn index(self) -> usize {
+        BOARD_RAILS
+            .iter()
+            .position(|info| info.rail == self)
+            .expect("every rail is described")
+    }
+
+    /// (register, bit) of the enable switch.
+    fn enable_bit(self) -> (u8, u8) {
+        match self {
+            Rail::Dcdc1 => (REG_DCDC_ENABLE, 0),
+            Rail::Dcdc3 => (REG_DCDC_ENABLE, 2),
+            Rail::Aldo1 => (REG_LDO_ENABLE, 0),
+            Rail::Aldo2 => (REG_LDO_ENABLE, 1),
+            Rail::Aldo3 => (REG_LDO_ENABLE, 2),
+            Rail::Aldo4 => (REG_LDO_ENABLE, 3),
+            Rail::Bldo1 => (REG_LDO_ENABLE, 4),
+            Rail::Bldo2 => (REG_LDO_ENABLE, 5),
+            Rail::Dldo1 => (REG_LDO_ENABLE, 7),
+        }
+    }
+
+    fn voltage_register(self) -> u8 {
+        match self {
+            Rail::Dcdc1 => 0x82,
+            Rail::Dcdc3 => 0x84,
+            Rail::Aldo1 => 0x92,
+            Rail::Aldo2 => 0x93,
+            Rail::Aldo3 => 0x94,
+            Rail::Aldo4 => 0x95,
+            Rail::Bldo1 => 0x96,
+            Rail::Bldo2 => 0x97,
+            Rail::Dldo1 => 0x99,
+        }
+    }
+
+    /// (mask, raw value) for the voltage register, the voltage must be within the board limits.
+    fn encode_voltage(self, millivolts: u16) -> (u8, u8) {
+        match self {
+            // 1.5~3.4V, 100mV/step
+            Rail::Dcdc1 => (0b11111, ((millivolts - 1500) / 100) as u8),
+            // 0.5~1.2V 10mV/step, 1.22~1.54V 20mV/step, 1.6~3.4V 100mV/step
+            Rail::Dcdc3 => {
+                let raw = match millivolts {
+                    0..=1200 => (millivolts.max(500) - 500) / 10,
+                    1201..=1540 => 71 + (millivolts - 1220) / 20,
+                    _ => 88 + (millivolts.max(1600) - 1600) / 100,
+                };
+                (0b1111111, raw as u8)
+            }
+            // 0.5~3.5V, 100mV/step
+            _ => (0b11111, ((millivolts - 500) / 100) as u8),
+        }
+    }
+
+    fn decode_voltage(self, raw: u8) -> u16 {
+        match self {
+            Rail::Dcdc1 => 1500 + (raw & 0b11111) as u16 * 100,
+            Rail::Dcdc3 => match raw & 0b1111111 {
+                raw @ 0..=70 => 500 + raw as u16 * 10,
+                raw @ 71..=87 => 1220 + (raw as u16 - 71) * 20,
+                raw => 1600 + (raw as u16 - 88) * 100,
+            },
+            _ => 500 + (raw & 0b11111) as u16 * 100,
+        }
+    }
+}
+
+impl fmt::Display for Rail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(Debug)]
+pub enum RailError<E> {
+    I2c(E),
+    VoltageOutOfRange { rail: Rail, millivolts: u16 },
+    AlwaysOn(Rail),
+}
+
+impl<E> From<E> for RailError<E> {
+    fn from(value: E) -> Self {
+        Self::I2c(value)
+    }
+}
+
+impl<E: fmt::Debug> digital::Error for RailError<E> {
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
+
+/// Current state of a rail, as reported by the PMU.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RailState {
+    pub rail: Rail,
+    pub enabled: bool,
+    pub millivolts: u16,
+}
+
+impl fmt::Display for RailState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = self.rail.info();
+        write!(
+            f,
+            "{:<5} {:<9} {} {:>4}mV {:?}",
+            self.rail,
+            info.name,
+            if self.enabled { "ON " } else { "OFF" },
+            self.millivolts,
+            info.consumers,
+        )
+    }
+}
+
+/// The only entry to change the power rails.
+pub struct RailManager<I2C> {
+    registers: Axp2101Registers<I2C>,
+}
+
+impl<I2C> RailManager<I2C>
+where
+    I2C: I2c,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            registers: Axp2101Registers::new(i2c),
+        }
+    }
+
+    pub fn set_voltage(
+        &mut self,
+        rail: Rail,
+        millivolts: u16,
+    ) -> Result<(), RailError<I2C::Error>> {
+        if !rail.info().voltages.contains(&millivolts) {
+            return Err(RailError::VoltageOutOfRange { rail, millivolts });
+        }
+        let (mask, raw) = rail.encode_voltage(millivolts);
+        self.registers.update(rail.voltage_register(), mask, raw)?;
+        Ok(())
+    }
+
+    /// Turn the rail on, for a rail with a single user, see [`Self::request`] otherwise.
+    pub fn enable(&mut self, rail: Rail) -> Result<(), RailError<I2C::Error>> {
+        let (register, bit) = rail.enable_bit();
+        self.registers.update(register, 1 << bit, 1 << bit)?;
+        Ok(())
+    }
+
+    pub fn disable(&mut self, rail: Rail) -> Result<(), RailError<I2C::Error>> {
+        if rail.info().always_on {
+            return Err(RailError::AlwaysOn(rail));
+        }
+        let (register, bit) = rail.enable_bit();
+        self.registers.update(register, 1 << bit, 0)?;
+        Ok(())
+    }
+
+    /// Set the voltage and turn the rail on, it stays on until every user has released it.
+    pub fn request(&mut self, rail: Rail, millivolts: u16) -> Result<(), RailError<I2C::Error>> {
+        self.set_voltage(rail, millivolts)?;
+        self.enable(rail)?;
+        REQUESTS[rail.index()].fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Drop a request, the rail is turned off by the last user unless the system requires it.
+    pub fn release(&mut self, rail: Rail) -> Result<(), RailError<I2C::Error>> {
+        let previous = REQUESTS[rail.index()]
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                Some(count.saturating_sub(1))
+            })
+            .unwrap_or_default();
+        if previous > 1 || rail.info().always_on {
+            return Ok(());
+        }
+        self.disable(rail)
+    }
+
+    pub fn state(&mut self, rail: Rail) -> Result<RailState, RailError<I2C::Error>> {
+        let (register, bit) = rail.enable_bit();
+        let enabled = self.registers.read(register)? & (1 << bit) != 0;
+        let millivolts = rail.decode_voltage(self.registers.read(rail.voltage_register())?);
+        Ok(RailState {
+            rail,
+            enabled,
+            millivolts,
+        })
+    }
+
+    /// Read the state of all rails on the board, for diagnostics.
+    pub fn dump(&mut self) -> Result<Vec<RailState>, RailError<I2C::Error>> {
+        BOARD_RAILS
+            .iter()
+            .map(|info| self.state(info.rail))
+            .collect()
+    }
+}
+
+/// A rail used as a logic output, like the LCD reset on ALDO2.
+pub struct RailPin<I2C> {
+    rails: RailManager<I2C>,
+    rail: Rail,
+}
+
+impl<I2C> RailPin<I2C>
+where
+    I2C: I2c,
+{
+    pub fn new(rails: RailManager<I2C>, rail: Rail) -> Self {
+        Self { rails, rail }
+    }
+}
+
+impl<I2C> digital::ErrorType for RailPin<I2C>
+where
+    I2C: I2c,
+{
+    type Error = RailError<I2C::Error>;
+}
+
+impl<I2C> digital::OutputPin for RailPin<I2C>
+where
+    I2C: I2c,
+{
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.rails.disable(self.rail)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.rails.enable(self.rail)
+    }
+}
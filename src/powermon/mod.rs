@@ -0,0 +1,95 @@
+//! Power monitoring with the INA3221 on board.
+//!
+//! Only the few registers required for sampling are used, see the INA3221 datasheet.
+
+use embedded_hal::i2c::I2c;
+
+mod task;
+
+pub use task::spawn_powermon_task;
+pub use task::PowerMonitorControl;
+
+/// The 7-bit I2C address of INA3221, A0 is tied to GND.
+pub const INA3221_ADDRESS: u8 = 0x40;
+
+/// Shunt resistors(mΩ) of the 3 channels, in series with the battery, the USB input and the 5V
+/// output on Core2 V1.1.
+pub const SHUNT_MILLIOHMS: [f32; 3] = [10.0, 10.0, 10.0];
+
+/// Net names of the 3 channels, the bus voltage is measured on the load side of each shunt.
+pub const CHANNEL_NAMES: [&str; 3] = ["VBAT", "VBUS", "5V_OUT"];
+
+const REG_CONFIG: u8 = 0x00;
+/// All channels on, 16 samples averaged, 1.1ms conversion time, continuous shunt and bus.
+const CONFIG_VALUE: u16 = 0x7527;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ChannelReading {
+    pub bus_millivolts: f32,
+    pub current_milliamps: f32,
+}
+
+impl ChannelReading {
+    pub fn power_milliwatts(&self) -> f32 {
+        self.bus_millivolts * self.current_milliamps / 1000.0
+    }
+}
+
+/// Readings of all channels taken at the same time.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PowerSample {
+    pub channels: [ChannelReading; 3],
+}
+
+impl PowerSample {
+    pub fn total_power_milliwatts(&self) -> f32 {
+        self.channels.iter().map(|c| c.power_milliwatts()).sum()
+    }
+}
+
+/// Raw register access to INA3221.
+pub struct Ina3221Sampler<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C> Ina3221Sampler<I2C>
+where
+    I2C: I2c,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self { i2c }
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u16, I2C::Error> {
+        let mut buffer = [0u8; 2];
+        self.i2c
+            .write_read(INA3221_ADDRESS, &[register], &mut buffer)?;
+        Ok(u16::from_be_bytes(buffer))
+    }
+
+    pub fn init(&mut self) -> Result<(), I2C::Error> {
+        let [high, low] = CONFIG_VALUE.to_be_bytes();
+        self.i2c.write(INA3221_ADDRESS, &[REG_CONFIG, high, low])
+    }
+
+    /// Read one channel, `channel` is 0-based.
+    pub fn read_channel(&mut self, channel: usize) -> Result<ChannelReading, I2C::Error> {
+        let shunt_register = 1 + channel as u8 * 2;
+        // both are left-aligned 13-bit values, shunt LSB is 40uV and bus LSB is 8mV
+        let shunt_raw = (self.read_register(shunt_register)? as i16) >> 3;
+        let bus_raw = (self.read_register(shunt_register + 1)? as i16) >> 3;
+        let shunt_microvolts = shunt_raw as f32 * 40.0;
+        Ok(ChannelReading {
+            bus_millivolts: bus_raw as f32 * 8.0,
+            current_milliamps: shunt_microvolts / SHUNT_MILLIOHMS[channel],
+        })
+    }
+
+    pub fn sample(&mut self) -> Result<PowerSample, I2C::Error> {
+        let mut sample = PowerSample::default();
+        for (index, channel) in sample.channels.iter_mut().enumerate() {
+            *channel = self.read_channel(index)?;
+        }
+        Ok(sample)
+    }
+}
@@ -0,0 +1,73 @@
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::hal::delay::FreeRtos as FreeRtosDelay;
+use std::{
+    sync::mpsc::{self, SyncSender},
+    thread,
+};
+
+use crate::inputevent::InputEvent;
+
+use super::Ina3221Sampler;
+
+const DEFAULT_INTERVAL_MS: u32 = 500;
+const MINIMUM_INTERVAL_MS: u32 = 50;
+
+/// Simple task control commands.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerMonitorControl {
+    Start,
+    Stop,
+    /// Sampling interval in ms.
+    SetInterval(u32),
+    Terminate,
+}
+
+/// Spawn the power monitor task and return a control handle.
+///
+/// Samples are delivered as [`InputEvent::PowerMonitor`], they are dropped if the queue is full.
+#[inline]
+pub fn spawn_powermon_task<I2C>(
+    mut sampler: Ina3221Sampler<I2C>,
+    sender: SyncSender<InputEvent>,
+) -> SyncSender<PowerMonitorControl>
+where
+    I2C: I2c + Send + 'static,
+{
+    let (control_sender, receiver) = mpsc::sync_channel::<PowerMonitorControl>(3);
+    let _ = thread::spawn(move || {
+        if let Err(e) = sampler.init() {
+            log::error!("INA3221 initialization failure! {:?}", e);
+            return;
+        }
+
+        let mut task_running = true;
+        let mut interval_ms = DEFAULT_INTERVAL_MS;
+
+        loop {
+            for event in receiver.try_iter() {
+                match event {
+                    PowerMonitorControl::Start => task_running = true,
+                    PowerMonitorControl::Stop => task_running = false,
+                    PowerMonitorControl::Terminate => return,
+                    PowerMonitorControl::SetInterval(value) => {
+                        interval_ms = value.max(MINIMUM_INTERVAL_MS);
+                    }
+                };
+            }
+
+            if task_running {
+                match sampler.sample() {
+                    Ok(sample) => {
+                        let _ = sender.try_send(InputEvent::PowerMonitor(sample));
+                    }
+                    Err(e) => log::warn!("Failed to sample INA3221: {:?}", e),
+                }
+            }
+
+            FreeRtosDelay::delay_ms(interval_ms);
+        }
+    });
+
+    control_sender
+}
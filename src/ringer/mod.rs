@@ -0,0 +1,48 @@
+//! Audible alerts through the built-in speaker, with vibration.
+//!
+//! The NS4168 amplifier is fed by I2S0, BCLK on GPIO12, LRCK on GPIO0 and DATA on GPIO2. Its
+//! power is switched by the SPK_EN rail, see [`crate::pmu::rails`].
+
+mod task;
+
+pub use task::spawn_ringer_task;
+
+pub const SAMPLE_RATE: u32 = 16000;
+
+/// Give up ringing after this, so a forgotten alarm doesn't drain the battery.
+pub const RING_TIMEOUT_SECONDS: u32 = 60;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingerControl {
+    /// Ring until stopped or timed out.
+    Ring,
+    /// A single short beep.
+    Beep,
+    Stop,
+    Terminate,
+}
+
+/// One cycle of the ring tone, (frequency in Hz, duration in ms), 0Hz is silence.
+const RING_TONE: [(u32, u32); 4] = [(880, 150), (0, 100), (880, 150), (0, 600)];
+
+/// 16-bit mono samples of a square wave, duplicated for both channels.
+fn square_wave(frequency: u32, duration_ms: u32) -> Vec<u8> {
+    let samples = SAMPLE_RATE * duration_ms / 1000;
+    let half_period = match frequency {
+        0 => u32::MAX,
+        frequency => SAMPLE_RATE / frequency / 2,
+    };
+    let mut buffer = Vec::with_capacity(samples as usize * 4);
+    for index in 0..samples {
+        let value: i16 = match frequency {
+            0 => 0,
+            _ if (index / half_period) % 2 == 0 => 8000,
+            _ => -8000,
+        };
+        let bytes = value.to_le_bytes();
+        buffer.extend_from_slice(&bytes);
+        buffer.extend_from_slice(&bytes);
+    }
+    buffer
+}
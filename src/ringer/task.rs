@@ -0,0 +1,82 @@
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::hal::{
+    delay::BLOCK,
+    i2s::{I2sDriver, I2sTx},
+};
+use std::{
+    sync::mpsc::{self, SyncSender},
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    haptics::HapticPattern,
+    pmu::rails::{Rail, RailManager},
+};
+
+use super::{square_wave, RingerControl, RING_TIMEOUT_SECONDS, RING_TONE};
+
+fn play(speaker: &mut I2sDriver<'static, I2sTx>, frequency: u32, duration_ms: u32) {
+    if let Err(e) = speaker.write_all(&square_wave(frequency, duration_ms), BLOCK) {
+        log::warn!("Failed to play tone: {:?}", e);
+    }
+}
+
+/// Spawn the ringer task, the I2S driver must be configured for [`super::SAMPLE_RATE`].
+#[inline]
+pub fn spawn_ringer_task<I2C>(
+    mut speaker: I2sDriver<'static, I2sTx>,
+    mut rails: RailManager<I2C>,
+    haptics: SyncSender<HapticPattern>,
+) -> SyncSender<RingerControl>
+where
+    I2C: I2c + Send + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel::<RingerControl>(4);
+    let _ = thread::spawn(move || {
+        let mut terminate = false;
+        for control in receiver.iter() {
+            let ring = match control {
+                RingerControl::Ring => true,
+                RingerControl::Beep => false,
+                RingerControl::Stop => continue,
+                RingerControl::Terminate => break,
+            };
+
+            if let Err(e) = rails.request(Rail::Aldo3, 3300) {
+                log::error!("Failed to power the speaker: {:?}", e);
+            }
+            if let Err(e) = speaker.tx_enable() {
+                log::error!("Failed to start I2S: {:?}", e);
+            }
+
+            if ring {
+                let started = Instant::now();
+                'ring: while started.elapsed().as_secs() < RING_TIMEOUT_SECONDS as u64 {
+                    let _ = haptics.try_send(HapticPattern::Long);
+                    for (frequency, duration) in RING_TONE {
+                        play(&mut speaker, frequency, duration);
+                    }
+                    for control in receiver.try_iter() {
+                        if control != RingerControl::Ring {
+                            terminate = control == RingerControl::Terminate;
+                            break 'ring;
+                        }
+                    }
+                }
+            } else {
+                play(&mut speaker, RING_TONE[0].0, RING_TONE[0].1);
+            }
+            // flush the DMA buffer with silence before cutting the power
+            play(&mut speaker, 0, 50);
+
+            let _ = speaker.tx_disable();
+            let _ = rails.release(Rail::Aldo3);
+            if terminate {
+                break;
+            }
+        }
+    });
+
+    sender
+}
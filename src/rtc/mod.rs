@@ -0,0 +1,237 @@
+//! PCF8563 real-time clock, and the system time kept in sync with it.
+//!
+//! The RTC keeps time in UTC. Register layouts are taken from the PCF8563 datasheet (Rev. 11).
+
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::sys;
+
+use crate::clock::DateTime;
+
+/// The 7-bit I2C address of PCF8563.
+pub const PCF8563_ADDRESS: u8 = 0x51;
+
+const REG_CONTROL_1: u8 = 0x00;
+const REG_CONTROL_2: u8 = 0x01;
+const REG_SECONDS: u8 = 0x02;
+const REG_ALARM_MINUTE: u8 = 0x09;
+const REG_TIMER_CONTROL: u8 = 0x0E;
+const REG_TIMER: u8 = 0x0F;
+
+/// VL flag in the seconds register, the clock integrity is not guaranteed when set.
+const VOLTAGE_LOW: u8 = 1 << 7;
+/// Century flag in the months register, set for 2100~2199, which is never used here.
+const CENTURY: u8 = 1 << 7;
+/// STOP bit in control register 1.
+const STOP: u8 = 1 << 5;
+/// Flags and interrupt enables in control register 2.
+const ALARM_FLAG: u8 = 1 << 3;
+const TIMER_FLAG: u8 = 1 << 2;
+const ALARM_INTERRUPT: u8 = 1 << 1;
+const TIMER_INTERRUPT: u8 = 1 << 0;
+/// AE bit of each alarm register, the field is ignored when set.
+const ALARM_DISABLED: u8 = 1 << 7;
+const TIMER_ENABLE: u8 = 1 << 7;
+const TIMER_1HZ: u8 = 0b10;
+const TIMER_1_60HZ: u8 = 0b11;
+
+/// Interrupts raised by the RTC on the shared wake-up line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcEvent {
+    Alarm,
+    Timer,
+}
+
+/// Time read from the RTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcTime {
+    pub datetime: DateTime,
+    /// The RTC lost power at some point, the time is invalid until set again.
+    pub voltage_low: bool,
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Raw register access to PCF8563.
+///
+/// The alarm, the timer and [`Self::take_events`] all read-modify-write control register 2, so a
+/// single instance should be shared behind a lock instead of one per thread.
+pub struct Pcf8563Registers<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C> Pcf8563Registers<I2C>
+where
+    I2C: I2c,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self { i2c }
+    }
+
+    pub fn read(&mut self, register: u8) -> Result<u8, I2C::Error> {
+        let mut buffer = [0u8; 1];
+        self.i2c
+            .write_read(PCF8563_ADDRESS, &[register], &mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) -> Result<(), I2C::Error> {
+        self.i2c.write(PCF8563_ADDRESS, &[register, value])
+    }
+
+    /// Read-modify-write, only bits in `mask` are changed.
+    pub fn update(&mut self, register: u8, mask: u8, value: u8) -> Result<(), I2C::Error> {
+        let old = self.read(register)?;
+        self.write(register, (old & !mask) | (value & mask))
+    }
+
+    /// Read all time registers at once, so they are consistent.
+    pub fn read_time(&mut self) -> Result<RtcTime, I2C::Error> {
+        let mut buffer = [0u8; 7];
+        self.i2c
+            .write_read(PCF8563_ADDRESS, &[REG_SECONDS], &mut buffer)?;
+        let datetime = DateTime {
+            second: from_bcd(buffer[0] & 0x7F),
+            minute: from_bcd(buffer[1] & 0x7F),
+            hour: from_bcd(buffer[2] & 0x3F),
+            day: from_bcd(buffer[3] & 0x3F),
+            weekday: buffer[4] & 0x07,
+            month: from_bcd(buffer[5] & 0x1F),
+            year: 2000 + from_bcd(buffer[6]) as u16,
+        };
+        Ok(RtcTime {
+            datetime: datetime.sanitized(),
+            voltage_low: buffer[0] & VOLTAGE_LOW != 0,
+        })
+    }
+
+    /// Set the time, this also clears the voltage-low flag.
+    pub fn set_time(&mut self, datetime: &DateTime) -> Result<(), I2C::Error> {
+        let datetime = datetime.sanitized();
+        let mut buffer = [0u8; 8];
+        buffer[0] = REG_SECONDS;
+        buffer[1] = to_bcd(datetime.second);
+        buffer[2] = to_bcd(datetime.minute);
+        buffer[3] = to_bcd(datetime.hour);
+        buffer[4] = to_bcd(datetime.day);
+        buffer[5] = datetime.weekday;
+        buffer[6] = to_bcd(datetime.month) & !CENTURY;
+        buffer[7] = to_bcd((datetime.year - 2000) as u8);
+        // hold the prescaler while writing, as suggested by the datasheet
+        self.update(REG_CONTROL_1, STOP, STOP)?;
+        self.i2c.write(PCF8563_ADDRESS, &buffer)?;
+        self.update(REG_CONTROL_1, STOP, 0)
+    }
+
+    /// Program the alarm on (day, hour, minute), or disable it.
+    pub fn set_alarm(&mut self, alarm: Option<(u8, u8, u8)>) -> Result<(), I2C::Error> {
+        let buffer = match alarm {
+            Some((day, hour, minute)) => [
+                REG_ALARM_MINUTE,
+                to_bcd(minute),
+                to_bcd(hour),
+                to_bcd(day),
+                ALARM_DISABLED,
+            ],
+            None => [
+                REG_ALARM_MINUTE,
+                ALARM_DISABLED,
+                ALARM_DISABLED,
+                ALARM_DISABLED,
+                ALARM_DISABLED,
+            ],
+        };
+        self.i2c.write(PCF8563_ADDRESS, &buffer)?;
+        let enable = if alarm.is_some() { ALARM_INTERRUPT } else { 0 };
+        // also clear a stale alarm flag, writing back 1 to the other flag has no effect
+        self.update(REG_CONTROL_2, ALARM_FLAG | ALARM_INTERRUPT, enable)
+    }
+
+    /// Start the countdown timer, or stop it.
+    ///
+    /// The timer only counts up to 255 ticks, so long countdowns go off early at a minute
+    /// resolution, and should be programmed again with the remaining time.
+    pub fn set_timer(&mut self, seconds: Option<u32>) -> Result<(), I2C::Error> {
+        match seconds {
+            Some(seconds) => {
+                let (control, value) = match seconds {
+                    0..=255 => (TIMER_ENABLE | TIMER_1HZ, seconds.max(1) as u8),
+                    _ => (TIMER_ENABLE | TIMER_1_60HZ, (seconds / 60).min(255) as u8),
+                };
+                // stop it before changing the value
+                self.write(REG_TIMER_CONTROL, 0)?;
+                self.write(REG_TIMER, value)?;
+                self.write(REG_TIMER_CONTROL, control)?;
+                self.update(REG_CONTROL_2, TIMER_FLAG | TIMER_INTERRUPT, TIMER_INTERRUPT)
+            }
+            None => {
+                self.write(REG_TIMER_CONTROL, 0)?;
+                self.update(REG_CONTROL_2, TIMER_FLAG | TIMER_INTERRUPT, 0)
+            }
+        }
+    }
+
+    /// Read and clear the pending interrupts, which releases the interrupt line.
+    pub fn take_events(&mut self) -> Result<Vec<RtcEvent>, I2C::Error> {
+        let control = self.read(REG_CONTROL_2)?;
+        let mut events = Vec::new();
+        if control & ALARM_FLAG != 0 {
+            events.push(RtcEvent::Alarm);
+        }
+        if control & TIMER_FLAG != 0 {
+            events.push(RtcEvent::Timer);
+        }
+        if !events.is_empty() {
+            self.write(REG_CONTROL_2, control & !(ALARM_FLAG | TIMER_FLAG))?;
+        }
+        Ok(events)
+    }
+}
+
+/// Set the system time, so [`std::time::SystemTime`] follows the RTC.
+pub fn set_system_time(timestamp: u64) {
+    let time = sys::timeval {
+        tv_sec: timestamp as _,
+        tv_usec: 0,
+    };
+    let result = unsafe { sys::settimeofday(&time, core::ptr::null()) };
+    if result != 0 {
+        log::error!("Failed to set system time, error {}", result);
+    }
+}
+
+/// Initialise the system time from the RTC.
+///
+/// Returns whether the time is valid.
+pub fn sync_system_time<I2C: I2c>(rtc: &mut Pcf8563Registers<I2C>) -> Result<bool, I2C::Error> {
+    let time = rtc.read_time()?;
+    set_system_time(time.datetime.to_timestamp());
+    if time.voltage_low {
+        log::warn!("RTC voltage was low, the time is invalid");
+    } else {
+        log::info!(
+            "System time set from RTC: {} {:02}:{:02}:{:02}",
+            time.datetime.date_string(),
+            time.datetime.hour,
+            time.datetime.minute,
+            time.datetime.second
+        );
+    }
+    Ok(!time.voltage_low)
+}
+
+/// Set both the RTC and the system time.
+pub fn set_time<I2C: I2c>(
+    rtc: &mut Pcf8563Registers<I2C>,
+    datetime: &DateTime,
+) -> Result<(), I2C::Error> {
+    let datetime = datetime.sanitized();
+    rtc.set_time(&datetime)?;
+    set_system_time(datetime.to_timestamp());
+    Ok(())
+}
@@ -0,0 +1,813 @@
+//! Every saved config in one TOML file on the TF card.
+//!
+//! The settings of the main page are the root table, each module has a table of its own and the
+//! alarms are the `[[alarms.list]]` array of tables. The file is read and written with `toml`,
+//! this module only checks the values and lists the changes.
+//!
+//! An import is checked as a whole, a single value out of range fails it, so the caller can show
+//! the changes before applying any of them.
+
+use std::{fs, io, ops::RangeInclusive, path::Path};
+
+use gadget_core::settings::BRIGHTNESS_MAX;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    alarms::{Alarm, MAX_ALARMS},
+    energylog::INTERVAL_OPTIONS,
+    level::MAX_ZERO_DEGREES,
+    motion::{
+        gesture::{GestureConfig, FLIP_RANGE, SHAKE_RANGE, TAP_RANGE},
+        logger::LOG_RATES,
+        screen::ScreenOrientation,
+        AccelRange, GyroRange, MotionConfig, Orientation,
+    },
+    pmu::charger::{
+        ChargerConfig, CONSTANT_CURRENT_OPTIONS, INPUT_CURRENT_LIMIT_OPTIONS,
+        PRECHARGE_CURRENT_OPTIONS, TERMINATION_VOLTAGE_OPTIONS,
+    },
+    thermal::{ThermalConfig, THRESHOLD_RANGE},
+};
+
+use super::{Settings, SCHEMA_VERSION, TRANSMISSION_POWER_MAX};
+
+/// File on the TF card for export and import.
+pub const EXPORT_FILE: &str = "settings.toml";
+/// Anything larger isn't an exported file.
+const MAX_IMPORT_BYTES: u64 = 8192;
+
+/// The configs of every module, as they're saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceConfig {
+    pub settings: Settings,
+    pub charger: ChargerConfig,
+    pub thermal: ThermalConfig,
+    pub gesture: GestureConfig,
+    pub screen_lock: Option<ScreenOrientation>,
+    /// Ranges and rate of the IMU logger.
+    pub logger: MotionConfig,
+    pub level_zero: Orientation,
+    pub alarms: Vec<Alarm>,
+    /// Seconds between the records of the energy log.
+    pub energylog_interval: u32,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::ErrorKind),
+    TooLarge,
+    /// Not TOML, or not the tables and keys of an exported file: the line number from 1 and
+    /// what's wrong.
+    Syntax(usize, String),
+    /// The label of the field, the value, and what's allowed.
+    OutOfRange(String, String, String),
+    TooManyAlarms,
+    /// The label of thresholds that must go up one after another.
+    Unordered(&'static str),
+    /// Exported by a firmware with a newer layout.
+    NewerVersion(String),
+}
+
+impl core::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ImportError::Io(io::ErrorKind::NotFound) => write!(f, "找不到 {}", EXPORT_FILE),
+            ImportError::Io(kind) => write!(f, "读取失败: {}", kind),
+            ImportError::TooLarge => write!(f, "文件太大"),
+            ImportError::Syntax(line, message) => write!(f, "第 {} 行格式错误: {}", line, message),
+            ImportError::OutOfRange(label, value, allowed) => {
+                write!(f, "{}超出范围: {}, 应为 {}", label, value, allowed)
+            }
+            ImportError::TooManyAlarms => write!(f, "闹钟最多 {} 个", MAX_ALARMS),
+            ImportError::Unordered(label) => write!(f, "{}应从低到高", label),
+            ImportError::NewerVersion(version) => {
+                write!(f, "设置版本 {} 太新, 请更新固件", version)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        ImportError::Io(e.kind())
+    }
+}
+
+impl ImportError {
+    fn syntax(text: &str, e: toml::de::Error) -> Self {
+        let line = e
+            .span()
+            .map_or(0, |span| text[..span.start].matches('\n').count())
+            + 1;
+        ImportError::Syntax(line, e.message().to_string())
+    }
+}
+
+/// The file, a value missing from it is `None` and keeps the current one.
+///
+/// Integers are read as `i64` so a value too large for the field is out of range rather than
+/// malformed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    version: Option<i64>,
+    brightness: Option<i64>,
+    transmission_power: Option<i64>,
+    charger: Option<ChargerTable>,
+    thermal: Option<ThermalTable>,
+    gesture: Option<GestureTable>,
+    display: Option<DisplayTable>,
+    logger: Option<LoggerTable>,
+    level: Option<LevelTable>,
+    energylog: Option<EnergyLogTable>,
+    alarms: Option<AlarmsTable>,
+}
+
+/// Currents in mA, the voltage in mV.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChargerTable {
+    constant_current: Option<i64>,
+    termination_voltage: Option<i64>,
+    precharge_current: Option<i64>,
+    input_current_limit: Option<i64>,
+}
+
+/// Thresholds in °C.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThermalTable {
+    warm: Option<i64>,
+    hot: Option<i64>,
+    critical: Option<i64>,
+}
+
+/// Thresholds in 0.1g.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GestureTable {
+    shake: Option<i64>,
+    tap: Option<i64>,
+    flip: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DisplayTable {
+    /// 0 follows the device, 1 landscape, 2 portrait, 3 and 4 flipped.
+    lock: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LoggerTable {
+    /// In Hz.
+    sample_rate: Option<i64>,
+    /// Full scale in g.
+    accel_range: Option<i64>,
+    /// Full scale in °/s.
+    gyro_range: Option<i64>,
+}
+
+/// Degrees, written with 2 decimals.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LevelTable {
+    pitch: Option<f64>,
+    roll: Option<f64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnergyLogTable {
+    /// In seconds.
+    interval: Option<i64>,
+}
+
+/// The list in the file replaces all the alarms, even if it's empty.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AlarmsTable {
+    #[serde(default)]
+    list: Vec<AlarmTable>,
+}
+
+/// The missing keys of an alarm are the defaults.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AlarmTable {
+    hour: Option<i64>,
+    minute: Option<i64>,
+    /// Bit 0 is Sunday, 0 goes off once.
+    weekdays: Option<i64>,
+    enabled: Option<bool>,
+}
+
+/// Read before the rest, so a newer file isn't refused for a key added since.
+#[derive(Deserialize)]
+struct Version {
+    version: Option<i64>,
+}
+
+/// The labels of the values as on their pages, by table and key, `""` is the root table.
+const LABELS: [(&str, &str, &str); 22] = [
+    ("", "brightness", "亮度"),
+    ("", "transmission_power", "功率"),
+    ("charger", "constant_current", "充电设置 充电电流"),
+    ("charger", "termination_voltage", "充电设置 截止电压"),
+    ("charger", "precharge_current", "充电设置 预充电流"),
+    ("charger", "input_current_limit", "充电设置 输入限流"),
+    ("thermal", "warm", "温度 降低功率"),
+    ("thermal", "hot", "温度 调暗屏幕"),
+    ("thermal", "critical", "温度 停止射频"),
+    ("gesture", "shake", "手势 摇晃"),
+    ("gesture", "tap", "手势 双击"),
+    ("gesture", "flip", "手势 翻转"),
+    ("display", "lock", "姿态 锁定屏幕方向"),
+    ("logger", "sample_rate", "数据记录 采样率"),
+    ("logger", "accel_range", "数据记录 加速度"),
+    ("logger", "gyro_range", "数据记录 角速度"),
+    ("level", "pitch", "水平仪 俯仰零点"),
+    ("level", "roll", "水平仪 横滚零点"),
+    ("energylog", "interval", "电池历史 记录间隔"),
+    ("alarms.list", "hour", "闹钟 时"),
+    ("alarms.list", "minute", "闹钟 分"),
+    ("alarms.list", "weekdays", "闹钟 重复"),
+];
+
+fn label(table: &str, key: &'static str) -> &'static str {
+    LABELS
+        .iter()
+        .find(|(t, k, _)| *t == table && *k == key)
+        .map_or(key, |(_, _, label)| label)
+}
+
+/// The value if it's within `range`, both ends included.
+fn in_range(
+    table: &str,
+    key: &'static str,
+    value: i64,
+    range: RangeInclusive<i64>,
+) -> Result<i64, ImportError> {
+    if range.contains(&value) {
+        Ok(value)
+    } else {
+        Err(ImportError::OutOfRange(
+            label(table, key).to_string(),
+            value.to_string(),
+            format!("{}..{}", range.start(), range.end()),
+        ))
+    }
+}
+
+/// The value if it's one of `options`.
+fn one_of(
+    table: &str,
+    key: &'static str,
+    value: i64,
+    options: impl IntoIterator<Item = i64>,
+) -> Result<i64, ImportError> {
+    let options: Vec<i64> = options.into_iter().collect();
+    if options.contains(&value) {
+        Ok(value)
+    } else {
+        let options: Vec<String> = options.iter().map(i64::to_string).collect();
+        Err(ImportError::OutOfRange(
+            label(table, key).to_string(),
+            value.to_string(),
+            options.join("/"),
+        ))
+    }
+}
+
+/// The value if it's up to `max` from 0.
+fn within(table: &str, key: &'static str, value: f64, max: f32) -> Result<f32, ImportError> {
+    if value.abs() <= max as f64 {
+        Ok(value as f32)
+    } else {
+        Err(ImportError::OutOfRange(
+            label(table, key).to_string(),
+            value.to_string(),
+            format!("-{}..{}", max, max),
+        ))
+    }
+}
+
+fn options(options: &[(u16, u8)]) -> impl Iterator<Item = i64> + '_ {
+    options.iter().map(|(value, _)| *value as i64)
+}
+
+/// Degrees with 2 decimals, as they're written.
+fn rounded(degrees: f32) -> f64 {
+    (degrees as f64 * 100.0).round() / 100.0
+}
+
+/// A decimal read back unchanged keeps the precision it's saved with.
+fn degrees(current: f32, value: f32) -> f32 {
+    if rounded(value) == rounded(current) {
+        current
+    } else {
+        value
+    }
+}
+
+fn alarm_list(alarms: &[Alarm]) -> String {
+    if alarms.is_empty() {
+        return "无".to_string();
+    }
+    alarms
+        .iter()
+        .map(|alarm| {
+            if alarm.enabled {
+                alarm.label()
+            } else {
+                format!("{}(关)", alarm.label())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl From<&DeviceConfig> for ConfigFile {
+    fn from(config: &DeviceConfig) -> Self {
+        ConfigFile {
+            version: Some(SCHEMA_VERSION as i64),
+            brightness: Some(config.settings.brightness as i64),
+            transmission_power: Some(config.settings.transmission_power as i64),
+            charger: Some(ChargerTable {
+                constant_current: Some(config.charger.constant_current as i64),
+                termination_voltage: Some(config.charger.termination_voltage as i64),
+                precharge_current: Some(config.charger.precharge_current as i64),
+                input_current_limit: Some(config.charger.input_current_limit as i64),
+            }),
+            thermal: Some(ThermalTable {
+                warm: Some(config.thermal.warm as i64),
+                hot: Some(config.thermal.hot as i64),
+                critical: Some(config.thermal.critical as i64),
+            }),
+            gesture: Some(GestureTable {
+                shake: Some(config.gesture.shake as i64),
+                tap: Some(config.gesture.tap as i64),
+                flip: Some(config.gesture.flip as i64),
+            }),
+            display: Some(DisplayTable {
+                lock: Some(
+                    config
+                        .screen_lock
+                        .map_or(0, |orientation| orientation as i64 + 1),
+                ),
+            }),
+            logger: Some(LoggerTable {
+                sample_rate: Some(config.logger.sample_rate as i64),
+                accel_range: Some(config.logger.accel_range.full_scale() as i64),
+                gyro_range: Some(config.logger.gyro_range.full_scale() as i64),
+            }),
+            level: Some(LevelTable {
+                pitch: Some(rounded(config.level_zero.pitch)),
+                roll: Some(rounded(config.level_zero.roll)),
+            }),
+            energylog: Some(EnergyLogTable {
+                interval: Some(config.energylog_interval as i64),
+            }),
+            alarms: Some(AlarmsTable {
+                list: config
+                    .alarms
+                    .iter()
+                    .map(|alarm| AlarmTable {
+                        hour: Some(alarm.hour as i64),
+                        minute: Some(alarm.minute as i64),
+                        weekdays: Some(alarm.weekdays as i64),
+                        enabled: Some(alarm.enabled),
+                    })
+                    .collect(),
+            }),
+        }
+    }
+}
+
+impl ConfigFile {
+    /// The values of the file over `config`, each checked against what's allowed.
+    fn apply(self, config: &mut DeviceConfig) -> Result<(), ImportError> {
+        if let Some(value) = self.brightness {
+            let value = in_range("", "brightness", value, 0..=BRIGHTNESS_MAX as i64)?;
+            config.settings.brightness = value as u8;
+        }
+        if let Some(value) = self.transmission_power {
+            let value = in_range(
+                "",
+                "transmission_power",
+                value,
+                0..=TRANSMISSION_POWER_MAX as i64,
+            )?;
+            config.settings.transmission_power = value as u8;
+        }
+        if let Some(table) = self.charger {
+            let charger = &mut config.charger;
+            for (key, value, allowed, field) in [
+                (
+                    "constant_current",
+                    table.constant_current,
+                    &CONSTANT_CURRENT_OPTIONS[..],
+                    &mut charger.constant_current,
+                ),
+                (
+                    "termination_voltage",
+                    table.termination_voltage,
+                    &TERMINATION_VOLTAGE_OPTIONS[..],
+                    &mut charger.termination_voltage,
+                ),
+                (
+                    "precharge_current",
+                    table.precharge_current,
+                    &PRECHARGE_CURRENT_OPTIONS[..],
+                    &mut charger.precharge_current,
+                ),
+                (
+                    "input_current_limit",
+                    table.input_current_limit,
+                    &INPUT_CURRENT_LIMIT_OPTIONS[..],
+                    &mut charger.input_current_limit,
+                ),
+            ] {
+                if let Some(value) = value {
+                    *field = one_of("charger", key, value, options(allowed))? as u16;
+                }
+            }
+        }
+        if let Some(table) = self.thermal {
+            let range = *THRESHOLD_RANGE.start() as i64..=*THRESHOLD_RANGE.end() as i64;
+            let thermal = &mut config.thermal;
+            for (key, value, field) in [
+                ("warm", table.warm, &mut thermal.warm),
+                ("hot", table.hot, &mut thermal.hot),
+                ("critical", table.critical, &mut thermal.critical),
+            ] {
+                if let Some(value) = value {
+                    *field = in_range("thermal", key, value, range.clone())? as u8;
+                }
+            }
+        }
+        if let Some(table) = self.gesture {
+            let gesture = &mut config.gesture;
+            for (key, value, (min, max), field) in [
+                ("shake", table.shake, SHAKE_RANGE, &mut gesture.shake),
+                ("tap", table.tap, TAP_RANGE, &mut gesture.tap),
+                ("flip", table.flip, FLIP_RANGE, &mut gesture.flip),
+            ] {
+                if let Some(value) = value {
+                    *field = in_range("gesture", key, value, min as i64..=max as i64)? as u8;
+                }
+            }
+        }
+        if let Some(lock) = self.display.and_then(|table| table.lock) {
+            config.screen_lock = match in_range("display", "lock", lock, 0..=4)? {
+                0 => None,
+                lock => Some(ScreenOrientation::from(lock as u8 - 1)),
+            };
+        }
+        if let Some(table) = self.logger {
+            if let Some(value) = table.sample_rate {
+                let rates = LOG_RATES.iter().map(|rate| *rate as i64);
+                config.logger.sample_rate = one_of("logger", "sample_rate", value, rates)? as u16;
+            }
+            if let Some(value) = table.accel_range {
+                let scales = AccelRange::ALL.map(|range| range.full_scale() as i64);
+                let value = one_of("logger", "accel_range", value, scales)?;
+                config.logger.accel_range = AccelRange::ALL
+                    .into_iter()
+                    .find(|range| range.full_scale() as i64 == value)
+                    .unwrap_or_default();
+            }
+            if let Some(value) = table.gyro_range {
+                let scales = GyroRange::ALL.map(|range| range.full_scale() as i64);
+                let value = one_of("logger", "gyro_range", value, scales)?;
+                config.logger.gyro_range = GyroRange::ALL
+                    .into_iter()
+                    .find(|range| range.full_scale() as i64 == value)
+                    .unwrap_or_default();
+            }
+        }
+        if let Some(table) = self.level {
+            let zero = &mut config.level_zero;
+            for (key, value, field) in [
+                ("pitch", table.pitch, &mut zero.pitch),
+                ("roll", table.roll, &mut zero.roll),
+            ] {
+                if let Some(value) = value {
+                    *field = degrees(*field, within("level", key, value, MAX_ZERO_DEGREES)?);
+                }
+            }
+        }
+        if let Some(interval) = self.energylog.and_then(|table| table.interval) {
+            let intervals = INTERVAL_OPTIONS.iter().map(|seconds| *seconds as i64);
+            config.energylog_interval =
+                one_of("energylog", "interval", interval, intervals)? as u32;
+        }
+        if let Some(table) = self.alarms {
+            if table.list.len() > MAX_ALARMS {
+                return Err(ImportError::TooManyAlarms);
+            }
+            let mut alarms = Vec::new();
+            for entry in table.list {
+                let mut alarm = Alarm::default();
+                if let Some(hour) = entry.hour {
+                    alarm.hour = in_range("alarms.list", "hour", hour, 0..=23)? as u8;
+                }
+                if let Some(minute) = entry.minute {
+                    alarm.minute = in_range("alarms.list", "minute", minute, 0..=59)? as u8;
+                }
+                if let Some(weekdays) = entry.weekdays {
+                    alarm.weekdays =
+                        in_range("alarms.list", "weekdays", weekdays, 0..=0b1111111)? as u8;
+                }
+                alarm.enabled = entry.enabled.unwrap_or(alarm.enabled);
+                alarms.push(alarm);
+            }
+            config.alarms = alarms;
+        }
+        Ok(())
+    }
+}
+
+/// Every value but the alarms, by their labels.
+fn values(config: &DeviceConfig) -> Vec<(&'static str, String)> {
+    let file = toml::Table::try_from(ConfigFile::from(config)).unwrap_or_default();
+    LABELS
+        .iter()
+        .filter(|(table, _, _)| *table != "alarms.list")
+        .map(|(table, key, label)| {
+            let table = match *table {
+                "" => Some(&file),
+                table => file.get(table).and_then(toml::Value::as_table),
+            };
+            let value = table.and_then(|table| table.get(*key));
+            (*label, value.map(ToString::to_string).unwrap_or_default())
+        })
+        .collect()
+}
+
+impl DeviceConfig {
+    /// The root table with the settings of the main page, then a table for each module.
+    pub fn export(&self) -> String {
+        let file = toml::to_string(&ConfigFile::from(self)).unwrap_or_default();
+        format!("# settings of the M5Stack Core2 gadget\n{}", file)
+    }
+
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.export())
+    }
+
+    /// Reads an exported file, whatever is missing from it keeps the value of `self`.
+    ///
+    /// Nothing is clamped here, a value out of range fails the whole import. The alarms are
+    /// replaced as a list, only if the file has the `[alarms]` table, the missing keys of an
+    /// alarm are the defaults.
+    pub fn import(&self, text: &str) -> Result<Self, ImportError> {
+        if let Ok(Version {
+            version: Some(version),
+        }) = toml::from_str(text)
+        {
+            if version > SCHEMA_VERSION as i64 {
+                return Err(ImportError::NewerVersion(version.to_string()));
+            }
+        }
+        let file: ConfigFile = toml::from_str(text).map_err(|e| ImportError::syntax(text, e))?;
+        let mut config = self.clone();
+        file.apply(&mut config)?;
+        if config.thermal.sanitized() != config.thermal {
+            return Err(ImportError::Unordered("温度阈值"));
+        }
+        Ok(config)
+    }
+
+    pub fn read_file(&self, path: &Path) -> Result<Self, ImportError> {
+        if fs::metadata(path)?.len() > MAX_IMPORT_BYTES {
+            return Err(ImportError::TooLarge);
+        }
+        // invalid UTF-8 ends up in a syntax error
+        let text = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+        self.import(&text)
+    }
+
+    /// What would change, like "亮度: 2 -> 4" or "充电设置 充电电流: 200 -> 300".
+    pub fn diff(&self, other: &Self) -> Vec<String> {
+        let mut changes: Vec<String> = values(self)
+            .into_iter()
+            .zip(values(other))
+            .filter(|((_, from), (_, to))| from != to)
+            .map(|((label, from), (_, to))| format!("{}: {} -> {}", label, from, to))
+            .collect();
+        if self.alarms != other.alarms {
+            changes.push(format!(
+                "闹钟: {} -> {}",
+                alarm_list(&self.alarms),
+                alarm_list(&other.alarms)
+            ));
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn current() -> DeviceConfig {
+        DeviceConfig {
+            settings: Settings::default(),
+            charger: ChargerConfig::default(),
+            thermal: ThermalConfig::default(),
+            gesture: GestureConfig::default(),
+            screen_lock: None,
+            logger: MotionConfig {
+                sample_rate: LOG_RATES[1],
+                fifo: true,
+                ..Default::default()
+            },
+            level_zero: Orientation::default(),
+            alarms: vec![Alarm {
+                hour: 7,
+                minute: 30,
+                weekdays: 0b0111110,
+                enabled: true,
+            }],
+            energylog_interval: 300,
+        }
+    }
+
+    /// Every field differs from [`current`].
+    fn changed() -> DeviceConfig {
+        DeviceConfig {
+            settings: Settings {
+                brightness: 4,
+                transmission_power: 3,
+            },
+            charger: ChargerConfig {
+                constant_current: 300,
+                termination_voltage: 4100,
+                precharge_current: 25,
+                input_current_limit: 500,
+            },
+            thermal: ThermalConfig {
+                warm: 50,
+                hot: 60,
+                critical: 70,
+            },
+            gesture: GestureConfig {
+                shake: 30,
+                tap: 10,
+                flip: 9,
+            },
+            screen_lock: Some(ScreenOrientation::Portrait),
+            logger: MotionConfig {
+                accel_range: AccelRange::G8,
+                gyro_range: GyroRange::Dps1000,
+                sample_rate: 500,
+                fifo: true,
+            },
+            level_zero: Orientation {
+                pitch: 1.25,
+                roll: -2.5,
+            },
+            alarms: vec![
+                Alarm {
+                    hour: 6,
+                    minute: 0,
+                    weekdays: 0,
+                    enabled: false,
+                },
+                Alarm {
+                    hour: 22,
+                    minute: 45,
+                    weekdays: 0b1111111,
+                    enabled: true,
+                },
+            ],
+            energylog_interval: 900,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let changed = changed();
+        assert_eq!(current().import(&changed.export()).unwrap(), changed);
+        assert_eq!(current().import(&current().export()).unwrap(), current());
+    }
+
+    #[test]
+    fn every_change_is_listed() {
+        let fields = LABELS
+            .iter()
+            .filter(|(table, _, _)| *table != "alarms.list")
+            .count();
+        let changes = current().diff(&changed());
+        assert_eq!(changes.len(), fields + 1);
+        assert!(changes.contains(&"充电设置 充电电流: 200 -> 300".to_string()));
+        assert_eq!(current().diff(&current()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn out_of_range_fails_the_whole_import() {
+        assert!(matches!(
+            current().import("brightness = 1\n[charger]\nconstant_current = 500\n"),
+            Err(ImportError::OutOfRange(..))
+        ));
+        assert!(matches!(
+            current().import("[level]\npitch = 15.5\n"),
+            Err(ImportError::OutOfRange(..))
+        ));
+        assert!(matches!(
+            current().import("[[alarms.list]]\nhour = 24\n"),
+            Err(ImportError::OutOfRange(..))
+        ));
+        assert!(matches!(
+            current().import("[level]\nroll = nan\n"),
+            Err(ImportError::OutOfRange(..))
+        ));
+    }
+
+    #[test]
+    fn malformed() {
+        for (text, line) in [
+            ("brightness", 1),
+            ("brightness = x", 1),
+            ("\n[charger\n", 2),
+            ("[[alarms.list]]\nenabled = 1\n", 2),
+        ] {
+            assert!(
+                matches!(current().import(text), Err(ImportError::Syntax(number, _)) if number == line),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_and_duplicate() {
+        for (text, line) in [
+            ("[wifi]\nssid = 1\n", 1),
+            ("[gesture]\nspin = 10\n", 2),
+            ("[gesture]\ntap = 10\n[gesture]\n", 3),
+            ("[gesture]\ntap = 10\ntap = 11\n", 3),
+        ] {
+            assert!(
+                matches!(current().import(text), Err(ImportError::Syntax(number, _)) if number == line),
+                "{}",
+                text
+            );
+        }
+        // the same key in another table is fine
+        assert!(current()
+            .import("[[alarms.list]]\nhour = 1\n[[alarms.list]]\nhour = 2\n")
+            .is_ok());
+    }
+
+    #[test]
+    fn alarms_are_replaced_as_a_list() {
+        assert_eq!(current().import("[alarms]\n").unwrap().alarms, []);
+        assert_eq!(
+            current()
+                .import("[[alarms.list]]\nhour = 9\n")
+                .unwrap()
+                .alarms,
+            [Alarm {
+                hour: 9,
+                ..Default::default()
+            }]
+        );
+        let too_many = "[[alarms.list]]\n".repeat(MAX_ALARMS + 1);
+        assert!(matches!(
+            current().import(&too_many),
+            Err(ImportError::TooManyAlarms)
+        ));
+    }
+
+    #[test]
+    fn thresholds_in_order() {
+        assert!(matches!(
+            current().import("[thermal]\nwarm = 70\n"),
+            Err(ImportError::Unordered(_))
+        ));
+    }
+
+    #[test]
+    fn newer_version() {
+        let text = format!("version = {}\n", SCHEMA_VERSION + 1);
+        assert!(matches!(
+            current().import(&text),
+            Err(ImportError::NewerVersion(_))
+        ));
+    }
+
+    #[test]
+    fn unchanged_decimal_keeps_its_precision() {
+        let config = DeviceConfig {
+            level_zero: Orientation {
+                pitch: 1.234,
+                roll: 0.0,
+            },
+            ..current()
+        };
+        assert_eq!(config.import(&config.export()).unwrap(), config);
+    }
+}
@@ -0,0 +1,29 @@
+//! Settings of the main page, kept across reboots.
+//!
+//! Sliders change them many times in a row, so they're saved a while after the last change
+//! instead of every time, to limit flash wear.
+//!
+//! The stored layout and its migrations are in [`gadget_core::settings`], with the tests, this
+//! module reads and writes them in NVS.
+//!
+//! They're exported to the TF card along with the configs of the other modules, see
+//! [`DeviceConfig`].
+
+use std::time::Duration;
+
+mod export;
+mod nvs;
+
+pub use export::{DeviceConfig, EXPORT_FILE};
+pub use gadget_core::settings::{Settings, SCHEMA_VERSION, TRANSMISSION_POWER_MAX};
+pub use nvs::{load, store, wipe};
+
+/// NVS namespace of the settings.
+pub const NVS_NAMESPACE: &str = "settings";
+const NVS_KEY_VALUES: &str = "values";
+
+/// Entries of version 1.
+const V1_KEYS: [&str; 2] = ["brightness", "power"];
+
+/// Time to wait after the last change before saving.
+pub const SAVE_DELAY: Duration = Duration::from_secs(5);
@@ -0,0 +1,112 @@
+//! Settings kept in NVS.
+
+use std::ffi::CString;
+
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::{self, EspError},
+};
+
+use super::{Settings, NVS_KEY_VALUES, SCHEMA_VERSION, V1_KEYS};
+
+/// Loads the stored settings, or the defaults. An older layout is written back in the current
+/// one, a newer one is left alone for the firmware that wrote it.
+pub fn load(nvs: &mut EspNvs<NvsDefault>) -> Settings {
+    let Some((version, values)) = read_stored(nvs) else {
+        return Settings::default();
+    };
+    let Some(settings) = Settings::decode(version, values) else {
+        log::warn!("Unknown settings version {}, using the defaults", version);
+        return Settings::default();
+    };
+    if version > SCHEMA_VERSION {
+        log::warn!(
+            "Settings version {} is newer than {}, using the known fields",
+            version,
+            SCHEMA_VERSION
+        );
+    } else if version < SCHEMA_VERSION {
+        log::info!(
+            "Migrating settings from version {} to {}",
+            version,
+            SCHEMA_VERSION
+        );
+        if let Err(e) = store(nvs, &settings).and_then(|_| remove_v1(nvs)) {
+            log::error!("Failed to save migrated settings: {:?}", e);
+        }
+    }
+    settings
+}
+
+/// Stores the settings, the fields a newer layout has beyond them are kept.
+pub fn store(nvs: &mut EspNvs<NvsDefault>, settings: &Settings) -> Result<(), EspError> {
+    let stored = read_stored(nvs);
+    let data = settings.encode(
+        stored
+            .as_ref()
+            .map(|(version, values)| (*version, values.as_slice())),
+    );
+    nvs.set_blob(NVS_KEY_VALUES, &data)
+}
+
+/// The version and the fields of the stored settings, `None` if there are none.
+fn read_stored(nvs: &EspNvs<NvsDefault>) -> Option<(u8, Vec<u8>)> {
+    // a newer layout may be longer than the current one
+    let mut buffer = match nvs.blob_len(NVS_KEY_VALUES) {
+        Ok(len) => vec![0u8; len.unwrap_or_default()],
+        Err(e) => {
+            log::warn!("Failed to load settings: {:?}", e);
+            return None;
+        }
+    };
+    match nvs.get_blob(NVS_KEY_VALUES, &mut buffer) {
+        Ok(Some([version, values @ ..])) => return Some((*version, values.to_vec())),
+        Ok(_) => {}
+        Err(e) => {
+            log::warn!("Failed to load settings: {:?}", e);
+            return None;
+        }
+    }
+    let values: Vec<Option<u8>> = V1_KEYS
+        .iter()
+        .map(|key| nvs.get_u8(key).ok().flatten())
+        .collect();
+    if values.iter().all(Option::is_none) {
+        return None;
+    }
+    let default = Settings::default().fields();
+    let values = values
+        .into_iter()
+        .zip(default)
+        .map(|(value, default)| value.unwrap_or(default))
+        .collect();
+    Some((1, values))
+}
+
+fn remove_v1(nvs: &mut EspNvs<NvsDefault>) -> Result<(), EspError> {
+    for key in V1_KEYS {
+        nvs.remove(key)?;
+    }
+    Ok(())
+}
+
+/// Erases everything stored in each namespace of the default partition, the owners find their
+/// defaults the next time they load.
+pub fn wipe(namespaces: &[&str]) -> Result<(), EspError> {
+    for namespace in namespaces {
+        let name = CString::new(*namespace).unwrap();
+        let mut handle: sys::nvs_handle_t = 0;
+        sys::esp!(unsafe {
+            sys::nvs_open(
+                name.as_ptr(),
+                sys::nvs_open_mode_t_NVS_READWRITE,
+                &mut handle,
+            )
+        })?;
+        let result = sys::esp!(unsafe { sys::nvs_erase_all(handle) })
+            .and_then(|_| sys::esp!(unsafe { sys::nvs_commit(handle) }));
+        unsafe { sys::nvs_close(handle) };
+        result?;
+    }
+    Ok(())
+}
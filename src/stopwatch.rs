@@ -0,0 +1,161 @@
+//! Stopwatch and countdown timer.
+//!
+//! Both run on [`crate::platform::duration_since_start`], so they are independent of the
+//! system time, and keep counting while the screen is locked.
+
+use core::time::Duration;
+
+/// Laps kept, the oldest ones are dropped.
+pub const MAX_LAPS: usize = 99;
+
+#[derive(Debug, Default)]
+pub struct Stopwatch {
+    /// When it was started last time, if running.
+    started: Option<Duration>,
+    /// Time counted before the last start.
+    accumulated: Duration,
+    /// Total elapsed time at each lap.
+    laps: Vec<Duration>,
+}
+
+impl Stopwatch {
+    pub fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    pub fn elapsed(&self, now: Duration) -> Duration {
+        self.accumulated
+            + self
+                .started
+                .map(|started| now - started)
+                .unwrap_or_default()
+    }
+
+    pub fn start(&mut self, now: Duration) {
+        if self.started.is_none() {
+            self.started = Some(now);
+        }
+    }
+
+    pub fn stop(&mut self, now: Duration) {
+        self.accumulated = self.elapsed(now);
+        self.started = None;
+    }
+
+    pub fn toggle(&mut self, now: Duration) {
+        if self.is_running() {
+            self.stop(now);
+        } else {
+            self.start(now);
+        }
+    }
+
+    /// Only counted while running.
+    pub fn lap(&mut self, now: Duration) {
+        if self.is_running() {
+            if self.laps.len() == MAX_LAPS {
+                self.laps.remove(0);
+            }
+            self.laps.push(self.elapsed(now));
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// (lap time, total time) of each lap.
+    pub fn laps(&self) -> impl Iterator<Item = (Duration, Duration)> + '_ {
+        let mut previous = Duration::ZERO;
+        self.laps.iter().map(move |total| {
+            let lap = *total - previous;
+            previous = *total;
+            (lap, *total)
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Countdown {
+    /// When it ends, if running.
+    deadline: Option<Duration>,
+    /// Time left when paused.
+    remaining: Duration,
+}
+
+impl Countdown {
+    pub fn is_running(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    pub fn remaining(&self, now: Duration) -> Duration {
+        match self.deadline {
+            Some(deadline) => deadline.saturating_sub(now),
+            None => self.remaining,
+        }
+    }
+
+    /// Set the time, only when not running.
+    pub fn set(&mut self, duration: Duration) {
+        if !self.is_running() {
+            self.remaining = duration;
+        }
+    }
+
+    /// Stop and set the time again.
+    pub fn reset(&mut self, duration: Duration) {
+        self.deadline = None;
+        self.remaining = duration;
+    }
+
+    pub fn start(&mut self, now: Duration) {
+        if self.deadline.is_none() && !self.remaining.is_zero() {
+            self.deadline = Some(now + self.remaining);
+        }
+    }
+
+    pub fn pause(&mut self, now: Duration) {
+        self.remaining = self.remaining(now);
+        self.deadline = None;
+    }
+
+    pub fn toggle(&mut self, now: Duration) {
+        if self.is_running() {
+            self.pause(now);
+        } else {
+            self.start(now);
+        }
+    }
+
+    /// Returns true once when the countdown ends.
+    pub fn take_expired(&mut self, now: Duration) -> bool {
+        if self.deadline.is_some_and(|deadline| deadline <= now) {
+            self.deadline = None;
+            self.remaining = Duration::ZERO;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// MM:SS.cc, or H:MM:SS.cc beyond an hour.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let centiseconds = duration.subsec_millis() / 10;
+    match seconds / 3600 {
+        0 => format!(
+            "{:02}:{:02}.{:02}",
+            seconds / 60,
+            seconds % 60,
+            centiseconds
+        ),
+        hours => format!(
+            "{}:{:02}:{:02}.{:02}",
+            hours,
+            seconds / 60 % 60,
+            seconds % 60,
+            centiseconds
+        ),
+    }
+}
@@ -0,0 +1,147 @@
+//! TF card on the SPI bus shared with the LCD.
+//!
+//! The card is driven by esp-idf's SD-SPI host as another device on the bus, the SPI master
+//! driver serializes the transactions of both. Once mounted, the FAT filesystem is reachable
+//! through `std::fs` under [`MOUNT_POINT`].
+
+use std::ffi::CString;
+
+use esp_idf_svc::{
+    fs::fatfs::Fatfs,
+    hal::{
+        gpio::{AnyIOPin, AnyOutputPin},
+        peripheral::Peripheral,
+        sd::{spi::SdSpiHostDriver, SdCardConfiguration, SdCardDriver},
+        spi::SpiDriver,
+    },
+    io::vfs::MountedFatfs,
+    sys::{self, EspError},
+};
+
+/// Where the card shows up in the VFS.
+pub const MOUNT_POINT: &str = "/sdcard";
+/// Files open at the same time.
+const MAX_OPEN_FILES: usize = 4;
+/// FATFS drive number of the card.
+const DRIVE: u8 = 0;
+
+type CardDriver = SdCardDriver<SdSpiHostDriver<'static, &'static SpiDriver<'static>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageError {
+    /// Nothing answered on the bus.
+    NoCard,
+    /// The card is there, but no FAT filesystem on it.
+    Unformatted,
+    Driver(EspError),
+}
+
+impl core::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StorageError::NoCard => write!(f, "未插入存储卡"),
+            StorageError::Unformatted => write!(f, "存储卡未格式化(需要 FAT32)"),
+            StorageError::Driver(e) => write!(f, "存储卡错误: {}", e),
+        }
+    }
+}
+
+/// Capacity in bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub total: u64,
+    pub free: u64,
+}
+
+pub struct SdCard {
+    spi_bus: &'static SpiDriver<'static>,
+    cs: AnyOutputPin,
+    mounted: Option<MountedFatfs<Fatfs<CardDriver>>>,
+}
+
+impl SdCard {
+    pub fn new(spi_bus: &'static SpiDriver<'static>, cs: AnyOutputPin) -> Self {
+        Self {
+            spi_bus,
+            cs,
+            mounted: None,
+        }
+    }
+
+    pub fn is_mounted(&self) -> bool {
+        self.mounted.is_some()
+    }
+
+    /// Probe the card and mount it, the card may have been swapped since last time.
+    pub fn mount(&mut self) -> Result<(), StorageError> {
+        if self.is_mounted() {
+            return Ok(());
+        }
+        // SAFETY: the pin is only used by the host driver, which is dropped on unmount or
+        // failure before it's taken again.
+        let cs = unsafe { self.cs.clone_unchecked() };
+        let host = SdSpiHostDriver::new(
+            self.spi_bus,
+            Some(cs),
+            AnyIOPin::none(),
+            AnyIOPin::none(),
+            AnyIOPin::none(),
+            None,
+        )
+        .map_err(StorageError::Driver)?;
+        let card = SdCardDriver::new_spi(host, &SdCardConfiguration::new()).map_err(|e| {
+            log::warn!("Failed to initialize SD card: {:?}", e);
+            match e.code() {
+                sys::ESP_ERR_TIMEOUT | sys::ESP_ERR_INVALID_RESPONSE | sys::ESP_ERR_NOT_FOUND => {
+                    StorageError::NoCard
+                }
+                _ => StorageError::Driver(e),
+            }
+        })?;
+        let fatfs = Fatfs::new_sdcard(DRIVE, card).map_err(StorageError::Driver)?;
+        // the card answered, so a failure here is about the filesystem
+        let mounted = MountedFatfs::mount(fatfs, MOUNT_POINT, MAX_OPEN_FILES).map_err(|e| {
+            log::warn!("Failed to mount SD card: {:?}", e);
+            StorageError::Unformatted
+        })?;
+        self.mounted = Some(mounted);
+        log::info!("SD card mounted at {}", MOUNT_POINT);
+        Ok(())
+    }
+
+    /// Files still open become invalid, close them first.
+    pub fn unmount(&mut self) {
+        if self.mounted.take().is_some() {
+            log::info!("SD card unmounted");
+        }
+    }
+
+    pub fn usage(&self) -> Result<Usage, StorageError> {
+        if !self.is_mounted() {
+            return Err(StorageError::NoCard);
+        }
+        let path = CString::new(MOUNT_POINT).unwrap();
+        let mut usage = Usage::default();
+        sys::esp!(unsafe {
+            sys::esp_vfs_fat_info(path.as_ptr(), &mut usage.total, &mut usage.free)
+        })
+        .map_err(StorageError::Driver)?;
+        Ok(usage)
+    }
+}
+
+/// Human readable size, "1.5 MB".
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f32;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,501 @@
+//! Paged text viewer.
+//!
+//! The software renderer only has the glyphs of the characters in the UI, so text files are
+//! laid out and drawn here instead, with [GNU Unifont](https://unifoundry.com/unifont/), which
+//! covers CJK. The font is read from `unifont.hex` in [`FONT_DIRECTORY`] on the card, and
+//! converted once to `unifont.bin` there for random access, which takes a few minutes and is done
+//! by [`spawn_font_conversion`]:
+//!
+//! | offset | size          | content                                    |
+//! |--------|---------------|--------------------------------------------|
+//! | 0      | 65536         | width of each BMP character, 0 if missing  |
+//! | 65536  | 65536 * 32    | 16 rows of u16 big endian, MSB on the left |
+//!
+//! Pages are drawn into a pixel buffer for `slint::Image`, as wide as the viewer, and the top line
+//! of each file is remembered in NVS.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::SyncSender,
+    thread,
+};
+
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::EspError,
+};
+use slint::{Rgb8Pixel, SharedPixelBuffer};
+
+use crate::inputevent::InputEvent;
+
+/// NVS namespace of the reading positions.
+pub const NVS_NAMESPACE: &str = "textview";
+const NVS_KEY_POSITIONS: &str = "positions";
+
+/// Where the font is, under the SD card.
+pub const FONT_DIRECTORY: &str = "fonts";
+const FONT_HEX: &str = "unifont.hex";
+const FONT_BIN: &str = "unifont.bin";
+const FONT_CHARACTERS: usize = 0x10000;
+const GLYPH_SIZE: usize = 32;
+const GLYPH_HEIGHT: u32 = 16;
+
+/// The default pthread stack is too small for the FAT driver.
+const CONVERSION_STACK_SIZE: usize = 8 * 1024;
+
+/// Files are read up to this, the rest is left out.
+pub const MAX_TEXT_BYTES: usize = 1024 * 1024;
+/// Narrower pages are drawn at this width and cut.
+const MIN_PAGE_WIDTH: u32 = 64;
+pub const LINE_HEIGHT: u32 = 18;
+pub const PAGE_LINES: usize = 8;
+const MARGIN: u32 = 4;
+const TAB_WIDTH: u32 = 4 * 8;
+/// Files with a remembered position.
+const MAX_POSITIONS: usize = 16;
+const POSITION_SIZE: usize = 8;
+
+const BACKGROUND: Rgb8Pixel = Rgb8Pixel::new(0xff, 0xff, 0xff);
+const FOREGROUND: Rgb8Pixel = Rgb8Pixel::new(0x20, 0x20, 0x20);
+
+/// Unifont glyphs of the BMP, the widths are kept in memory, the bitmaps read when needed.
+pub struct GlyphFont {
+    path: PathBuf,
+    widths: Vec<u8>,
+    cache: HashMap<char, [u16; GLYPH_HEIGHT as usize]>,
+}
+
+impl GlyphFont {
+    /// Open the converted font in `directory`, see [`needs_conversion`].
+    pub fn open(directory: &Path) -> io::Result<Self> {
+        let path = directory.join(FONT_BIN);
+        let mut widths = vec![0u8; FONT_CHARACTERS];
+        File::open(&path)?.read_exact(&mut widths)?;
+        Ok(Self {
+            path,
+            widths,
+            cache: HashMap::new(),
+        })
+    }
+
+    /// Width in pixels, missing characters take the width of a box.
+    fn width(&self, c: char) -> u32 {
+        match c {
+            '\t' => TAB_WIDTH,
+            c if c.is_control() => 0,
+            c => match self.widths.get(c as usize) {
+                Some(&width) if width > 0 => width as u32,
+                _ => 8,
+            },
+        }
+    }
+
+    /// Read the bitmaps of `text` not read yet.
+    fn load(&mut self, text: &str) -> io::Result<()> {
+        let mut file: Option<BufReader<File>> = None;
+        for c in text.chars() {
+            if self.cache.contains_key(&c) {
+                continue;
+            }
+            let mut rows = [0u16; GLYPH_HEIGHT as usize];
+            if self.widths.get(c as usize).is_some_and(|&width| width > 0) {
+                let reader = match file.as_mut() {
+                    Some(reader) => reader,
+                    None => file.insert(BufReader::new(File::open(&self.path)?)),
+                };
+                let mut bytes = [0u8; GLYPH_SIZE];
+                reader.seek(SeekFrom::Start(
+                    (FONT_CHARACTERS + c as usize * GLYPH_SIZE) as u64,
+                ))?;
+                reader.read_exact(&mut bytes)?;
+                for (row, pair) in rows.iter_mut().zip(bytes.chunks_exact(2)) {
+                    *row = u16::from_be_bytes([pair[0], pair[1]]);
+                }
+            } else if !c.is_control() {
+                // a box
+                rows = [0x8100; GLYPH_HEIGHT as usize];
+                rows[1] = 0xff00;
+                rows[GLYPH_HEIGHT as usize - 2] = 0xff00;
+                rows[0] = 0;
+                rows[GLYPH_HEIGHT as usize - 1] = 0;
+            }
+            self.cache.insert(c, rows);
+        }
+        Ok(())
+    }
+}
+
+/// Reported to the super loop as [`InputEvent::FontConversion`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionEvent {
+    /// Percent done.
+    Progress(u8),
+    Finished(Result<(), io::ErrorKind>),
+}
+
+/// The font in `directory` is only there as `unifont.hex`.
+pub fn needs_conversion(directory: &Path) -> bool {
+    !directory.join(FONT_BIN).exists() && directory.join(FONT_HEX).exists()
+}
+
+/// Convert the font in `directory` in a thread of its own, the progress and the result are sent
+/// as [`ConversionEvent`].
+pub fn spawn_font_conversion(directory: PathBuf, sender: SyncSender<InputEvent>) {
+    let _ = thread::Builder::new()
+        .stack_size(CONVERSION_STACK_SIZE)
+        .spawn(move || {
+            let result = convert_hex(
+                &directory.join(FONT_HEX),
+                &directory.join(FONT_BIN),
+                |percent| {
+                    let _ = sender.send(InputEvent::FontConversion(ConversionEvent::Progress(
+                        percent,
+                    )));
+                },
+            );
+            if let Err(e) = &result {
+                log::error!("Failed to convert the font: {:?}", e);
+            }
+            let _ = sender.send(InputEvent::FontConversion(ConversionEvent::Finished(
+                result.map_err(|e| e.kind()),
+            )));
+        });
+}
+
+/// Turn a Unifont `.hex` file, "4E00:<32 or 64 hex digits>" per line, into the binary form.
+///
+/// `progress` is called with the percentage read, each time it goes up.
+fn convert_hex(hex: &Path, bin: &Path, mut progress: impl FnMut(u8)) -> io::Result<()> {
+    log::info!("Converting {} ...", hex.display());
+    let file = File::open(hex)?;
+    let total = file.metadata()?.len().max(1);
+    let reader = BufReader::new(file);
+    // written under another name first, an interrupted conversion is started over
+    let partial = bin.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    let mut widths = vec![0u8; FONT_CHARACTERS];
+    writer.write_all(&widths)?;
+    let mut next = 0usize;
+    let mut read = 0u64;
+    let mut reported = 0u8;
+    for line in reader.lines() {
+        let line = line?;
+        read += line.len() as u64 + 1;
+        let percent = (read * 100 / total).min(100) as u8;
+        if percent > reported {
+            reported = percent;
+            progress(percent);
+        }
+        let Some((code, bitmap)) = line.trim().split_once(':') else {
+            continue;
+        };
+        let Ok(code) = usize::from_str_radix(code, 16) else {
+            continue;
+        };
+        let width = match bitmap.len() {
+            32 => 8,
+            64 => 16,
+            _ => continue,
+        };
+        // sorted in the upstream files, anything else is skipped
+        if code < next || code >= FONT_CHARACTERS {
+            continue;
+        }
+        // by bytes, a multibyte character in a broken file is not a char boundary
+        let Some(bytes) = bitmap
+            .as_bytes()
+            .chunks_exact(2)
+            .map(|pair| {
+                std::str::from_utf8(pair)
+                    .ok()
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+        else {
+            continue;
+        };
+        writer.write_all(&vec![0u8; (code - next) * GLYPH_SIZE])?;
+        let mut glyph = [0u8; GLYPH_SIZE];
+        if width == 8 {
+            for (row, byte) in bytes.iter().enumerate() {
+                glyph[row * 2] = *byte;
+            }
+        } else {
+            glyph.copy_from_slice(&bytes);
+        }
+        writer.write_all(&glyph)?;
+        widths[code] = width;
+        next = code + 1;
+    }
+    writer.write_all(&vec![0u8; (FONT_CHARACTERS - next) * GLYPH_SIZE])?;
+    writer.seek(SeekFrom::Start(0))?;
+    writer.write_all(&widths)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&partial, bin)?;
+    log::info!("Font converted to {}", bin.display());
+    Ok(())
+}
+
+/// Byte offsets where the lines start, wrapped to fit `width`.
+///
+/// Lines break after spaces, and around wide characters, long words are cut.
+fn wrap(text: &str, font: &GlyphFont, width: u32) -> Vec<u32> {
+    let mut lines = Vec::new();
+    let mut start = 0usize;
+    for paragraph in text.split_inclusive('\n') {
+        lines.push(start as u32);
+        let mut line_start = start;
+        let mut x = 0;
+        // where the line may break, and the width up to there
+        let mut opportunity: Option<(usize, u32)> = None;
+        for (index, c) in paragraph.char_indices() {
+            let index = start + index;
+            let advance = font.width(c);
+            if advance > 8 {
+                opportunity = Some((index, x));
+            }
+            while x + advance > width && x > 0 {
+                let (at, used) = match opportunity {
+                    Some((at, used)) if at > line_start => (at, used),
+                    _ => (index, x),
+                };
+                lines.push(at as u32);
+                line_start = at;
+                x -= used;
+                opportunity = None;
+            }
+            x += advance;
+            if c == ' ' || advance > 8 {
+                opportunity = Some((index + c.len_utf8(), x));
+            }
+        }
+        start += paragraph.len();
+    }
+    if lines.is_empty() {
+        lines.push(0);
+    }
+    lines
+}
+
+pub struct TextDocument {
+    path: PathBuf,
+    text: String,
+    /// Where the lines start, wrapped to fit `width`.
+    lines: Vec<u32>,
+    /// Width of the page in pixels, 0 until it's laid out.
+    width: u32,
+    /// Byte offset in the line shown first, kept when the width changes.
+    offset: u32,
+    /// The file is longer than [`MAX_TEXT_BYTES`].
+    truncated: bool,
+}
+
+impl TextDocument {
+    /// Read a text file, invalid UTF-8 is replaced. It's laid out by [`Self::set_width`].
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let truncated = file.metadata()?.len() > MAX_TEXT_BYTES as u64;
+        let mut bytes = Vec::new();
+        file.take(MAX_TEXT_BYTES as u64).read_to_end(&mut bytes)?;
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        Ok(Self {
+            path: path.to_path_buf(),
+            text,
+            lines: vec![0],
+            width: 0,
+            offset: 0,
+            truncated,
+        })
+    }
+
+    /// Wrap the lines again if the page is `width` pixels wide now.
+    pub fn set_width(&mut self, width: u32, font: &GlyphFont) {
+        let width = width.max(MIN_PAGE_WIDTH);
+        if width != self.width {
+            self.lines = wrap(&self.text, font, width - MARGIN * 2);
+            self.width = width;
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    fn last_top(&self) -> usize {
+        self.lines.len().saturating_sub(PAGE_LINES)
+    }
+
+    /// The first line shown.
+    fn top(&self) -> usize {
+        let line = self.lines.partition_point(|&start| start <= self.offset);
+        line.saturating_sub(1).min(self.last_top())
+    }
+
+    fn set_top(&mut self, top: usize) {
+        self.offset = self.lines.get(top).copied().unwrap_or(0);
+    }
+
+    /// Positive is down.
+    pub fn scroll_by(&mut self, lines: i32) {
+        let top = self
+            .top()
+            .saturating_add_signed(lines as isize)
+            .min(self.last_top());
+        self.set_top(top);
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.top() >= self.last_top()
+    }
+
+    /// Go to the end, or back to the start from there.
+    pub fn jump(&mut self) {
+        let top = if self.is_at_end() { 0 } else { self.last_top() };
+        self.set_top(top);
+    }
+
+    /// "12-21/340".
+    pub fn position(&self) -> String {
+        let top = self.top();
+        let bottom = (top + PAGE_LINES).min(self.lines.len());
+        format!("{}-{}/{}", top + 1, bottom, self.lines.len())
+    }
+
+    /// Byte offset of the top line, stays valid when the layout changes.
+    pub fn offset(&self) -> u32 {
+        self.lines.get(self.top()).copied().unwrap_or(0)
+    }
+
+    /// Show the line containing `offset` at the top.
+    pub fn seek(&mut self, offset: u32) {
+        self.offset = offset;
+    }
+
+    fn line(&self, index: usize) -> Option<&str> {
+        let start = *self.lines.get(index)? as usize;
+        let end = self
+            .lines
+            .get(index + 1)
+            .map_or(self.text.len(), |&end| end as usize);
+        Some(self.text[start..end].trim_end_matches(['\n', '\r']))
+    }
+
+    /// Draw the lines shown, at the width of the last [`Self::set_width`].
+    pub fn render(&self, font: &mut GlyphFont) -> io::Result<SharedPixelBuffer<Rgb8Pixel>> {
+        let width = self.width.max(MIN_PAGE_WIDTH);
+        let height = PAGE_LINES as u32 * LINE_HEIGHT;
+        let mut buffer = SharedPixelBuffer::<Rgb8Pixel>::new(width, height);
+        let pixels = buffer.make_mut_slice();
+        pixels.fill(BACKGROUND);
+        let top = self.top();
+        for row in 0..PAGE_LINES {
+            let Some(line) = self.line(top + row) else {
+                break;
+            };
+            font.load(line)?;
+            let y = row as u32 * LINE_HEIGHT + (LINE_HEIGHT - GLYPH_HEIGHT) / 2;
+            let mut x = MARGIN;
+            for c in line.chars() {
+                let advance = font.width(c);
+                if x + advance > width {
+                    break;
+                }
+                if let Some(rows) = font.cache.get(&c) {
+                    for (dy, bits) in rows.iter().enumerate() {
+                        let start = ((y + dy as u32) * width + x) as usize;
+                        for dx in 0..advance.min(16) as usize {
+                            if bits & (0x8000 >> dx) != 0 {
+                                pixels[start + dx] = FOREGROUND;
+                            }
+                        }
+                    }
+                }
+                x += advance;
+            }
+        }
+        Ok(buffer)
+    }
+}
+
+/// FNV-1a, to tell files apart in little space.
+fn path_hash(path: &Path) -> u32 {
+    path.to_string_lossy()
+        .bytes()
+        .fold(0x811c_9dc5, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        })
+}
+
+/// Where reading stopped in the last few files, most recent first.
+#[derive(Debug, Default)]
+pub struct ReadingPositions {
+    /// (path hash, byte offset).
+    entries: VecDeque<(u32, u32)>,
+}
+
+impl ReadingPositions {
+    pub fn load(nvs: &EspNvs<NvsDefault>) -> Self {
+        let mut buffer = [0u8; MAX_POSITIONS * POSITION_SIZE];
+        let entries = match nvs.get_blob(NVS_KEY_POSITIONS, &mut buffer) {
+            Ok(Some(data)) => data
+                .chunks_exact(POSITION_SIZE)
+                .map(|entry| {
+                    (
+                        u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+                        u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+                    )
+                })
+                .collect(),
+            Ok(None) => VecDeque::new(),
+            Err(e) => {
+                log::warn!("Failed to load reading positions: {:?}", e);
+                VecDeque::new()
+            }
+        };
+        Self { entries }
+    }
+
+    pub fn store(&self, nvs: &mut EspNvs<NvsDefault>) -> Result<(), EspError> {
+        let data: Vec<u8> = self
+            .entries
+            .iter()
+            .flat_map(|(hash, offset)| {
+                let mut bytes = [0u8; POSITION_SIZE];
+                bytes[0..4].copy_from_slice(&hash.to_le_bytes());
+                bytes[4..8].copy_from_slice(&offset.to_le_bytes());
+                bytes
+            })
+            .collect();
+        nvs.set_blob(NVS_KEY_POSITIONS, &data)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<u32> {
+        let hash = path_hash(path);
+        self.entries
+            .iter()
+            .find(|(other, _)| *other == hash)
+            .map(|(_, offset)| *offset)
+    }
+
+    /// Returns false if nothing changed.
+    pub fn set(&mut self, path: &Path, offset: u32) -> bool {
+        let hash = path_hash(path);
+        if self.entries.front() == Some(&(hash, offset)) {
+            return false;
+        }
+        self.entries.retain(|(other, _)| *other != hash);
+        self.entries.push_front((hash, offset));
+        self.entries.truncate(MAX_POSITIONS);
+        true
+    }
+}
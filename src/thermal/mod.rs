@@ -0,0 +1,149 @@
+//! Thermal monitoring and protective throttling.
+//!
+//! AXP2101's die temperature decides the [`ThermalLevel`], and the super loop throttles the radio
+//! and the backlight accordingly. ESP32's internal sensor is not calibrated and reads 50-60°C
+//! when idle, so it is only shown for information.
+
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::EspError,
+};
+
+use crate::pmu::Axp2101Registers;
+
+mod task;
+
+pub use task::spawn_thermal_task;
+pub use task::ThermalControl;
+
+/// NVS namespace used to persist the thresholds.
+pub const NVS_NAMESPACE: &str = "thermal";
+
+/// Allowed range of the thresholds in °C.
+pub const THRESHOLD_RANGE: core::ops::RangeInclusive<u8> = 40..=90;
+
+/// A level is left only when cooled down by this amount(°C).
+const HYSTERESIS: f32 = 3.0;
+
+const REG_TDIE_HIGH: u8 = 0x3C;
+const REG_TDIE_LOW: u8 = 0x3D;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThermalLevel {
+    #[default]
+    Normal,
+    /// Reduce the transmit power.
+    Warm,
+    /// Also dim the backlight.
+    Hot,
+    /// Stop all radio activity.
+    Critical,
+}
+
+/// Thresholds in °C, `warm < hot < critical`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalConfig {
+    pub warm: u8,
+    pub hot: u8,
+    pub critical: u8,
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        Self {
+            warm: 55,
+            hot: 65,
+            critical: 75,
+        }
+    }
+}
+
+impl ThermalConfig {
+    /// Clamp the thresholds into [`THRESHOLD_RANGE`] and keep them in order.
+    pub fn sanitized(self) -> Self {
+        let (min, max) = (*THRESHOLD_RANGE.start(), *THRESHOLD_RANGE.end());
+        let warm = self.warm.clamp(min, max - 2);
+        let hot = self.hot.clamp(warm + 1, max - 1);
+        let critical = self.critical.clamp(hot + 1, max);
+        Self {
+            warm,
+            hot,
+            critical,
+        }
+    }
+
+    fn threshold(&self, level: ThermalLevel) -> f32 {
+        match level {
+            ThermalLevel::Normal => f32::MIN,
+            ThermalLevel::Warm => self.warm as f32,
+            ThermalLevel::Hot => self.hot as f32,
+            ThermalLevel::Critical => self.critical as f32,
+        }
+    }
+
+    /// Decide the new level, with hysteresis when cooling down.
+    pub fn next_level(&self, current: ThermalLevel, celsius: f32) -> ThermalLevel {
+        let raw = if celsius >= self.critical as f32 {
+            ThermalLevel::Critical
+        } else if celsius >= self.hot as f32 {
+            ThermalLevel::Hot
+        } else if celsius >= self.warm as f32 {
+            ThermalLevel::Warm
+        } else {
+            ThermalLevel::Normal
+        };
+        if raw < current && celsius > self.threshold(current) - HYSTERESIS {
+            current
+        } else {
+            raw
+        }
+    }
+
+    pub fn load(nvs: &EspNvs<NvsDefault>) -> Self {
+        let default = Self::default();
+        let get = |key: &str, fallback: u8| nvs.get_u8(key).ok().flatten().unwrap_or(fallback);
+        Self {
+            warm: get("warm", default.warm),
+            hot: get("hot", default.hot),
+            critical: get("crit", default.critical),
+        }
+        .sanitized()
+    }
+
+    pub fn store(&self, nvs: &mut EspNvs<NvsDefault>) -> Result<(), EspError> {
+        nvs.set_u8("warm", self.warm)?;
+        nvs.set_u8("hot", self.hot)?;
+        nvs.set_u8("crit", self.critical)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ThermalReading {
+    /// Decides the level.
+    pub pmu_celsius: f32,
+    /// For information only. Not available if the sensor is disabled or not working.
+    pub esp_celsius: Option<f32>,
+    pub level: ThermalLevel,
+}
+
+/// Die temperature of AXP2101, the ADC channel must be enabled.
+pub fn read_pmu_temperature<I2C: I2c>(
+    registers: &mut Axp2101Registers<I2C>,
+) -> Result<f32, I2C::Error> {
+    let raw = ((registers.read(REG_TDIE_HIGH)? & 0b111111) as u16) << 8
+        | registers.read(REG_TDIE_LOW)? as u16;
+    Ok(22.0 + (7274.0 - raw as f32) / 20.0)
+}
+
+/// ESP32's internal temperature sensor, it's not calibrated and reads high.
+pub fn read_esp_temperature() -> Option<f32> {
+    extern "C" {
+        // undocumented, provided by the ESP32 PHY library, yes it is misspelled
+        fn temprature_sens_read() -> u8;
+    }
+    let fahrenheit = unsafe { temprature_sens_read() };
+    // 128 means the sensor is not working
+    (fahrenheit != 128).then(|| (fahrenheit as f32 - 32.0) / 1.8)
+}
@@ -0,0 +1,71 @@
+use embedded_hal::i2c::I2c;
+use esp_idf_svc::hal::delay::FreeRtos as FreeRtosDelay;
+use std::{
+    sync::mpsc::{self, SyncSender},
+    thread,
+};
+
+use crate::{inputevent::InputEvent, pmu::Axp2101Registers};
+
+use super::{read_esp_temperature, read_pmu_temperature, ThermalConfig, ThermalReading};
+
+const INTERVAL_MS: u32 = 2000;
+
+/// Simple task control commands.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThermalControl {
+    SetConfig(ThermalConfig),
+    Terminate,
+}
+
+/// Spawn the thermal monitor task and return a control handle.
+///
+/// Readings are delivered as [`InputEvent::Thermal`], with the level already decided.
+#[inline]
+pub fn spawn_thermal_task<I2C>(
+    mut registers: Axp2101Registers<I2C>,
+    config: ThermalConfig,
+    sender: SyncSender<InputEvent>,
+) -> SyncSender<ThermalControl>
+where
+    I2C: I2c + Send + 'static,
+{
+    let (control_sender, receiver) = mpsc::sync_channel::<ThermalControl>(3);
+    let _ = thread::spawn(move || {
+        let mut config = config.sanitized();
+        let mut reading = ThermalReading::default();
+
+        loop {
+            for event in receiver.try_iter() {
+                match event {
+                    ThermalControl::SetConfig(value) => config = value.sanitized(),
+                    ThermalControl::Terminate => return,
+                };
+            }
+
+            match read_pmu_temperature(&mut registers) {
+                Ok(pmu_celsius) => {
+                    reading.pmu_celsius = pmu_celsius;
+                    reading.esp_celsius = read_esp_temperature();
+                    let level = config.next_level(reading.level, reading.pmu_celsius);
+                    if level != reading.level {
+                        log::warn!(
+                            "Thermal level {:?} -> {:?} at {:.1}°C",
+                            reading.level,
+                            level,
+                            reading.pmu_celsius
+                        );
+                    }
+                    reading.level = level;
+                    let _ = sender.try_send(InputEvent::Thermal(reading));
+                }
+                Err(e) => log::warn!("Failed to read PMU temperature: {:?}", e),
+            }
+
+            FreeRtosDelay::delay_ms(INTERVAL_MS);
+        }
+    });
+
+    control_sender
+}
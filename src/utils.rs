@@ -3,6 +3,7 @@ use esp_idf_svc::hal::{
     gpio::{Input, InputPin, InterruptType, PinDriver},
     task::notification::Notification,
 };
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// block until the desired interrupt occurs on the given pin
 #[inline]
@@ -26,3 +27,11 @@ where
     // wait for notification
     notification.wait_any();
 }
+
+/// Seconds since UNIX epoch, only meaningful if the system time is set.
+pub fn now_timestamp() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as u32)
+        .unwrap_or(0)
+}